@@ -33,8 +33,8 @@ static char* dupstr(const char *s) {
 %token T_RETURN T_IF T_ELSE T_FOR T_WHILE
 
 %token <str> T_IDENTIFIER
-%token <intval> T_INTLIT
-%token <floatval> T_FLOATLIT
+%token <intval> T_INTLIT      /* 42, 0x2A, 0o52, 0b101010, 1_000: converted to i64 by the lexer */
+%token <floatval> T_FLOATLIT  /* 3.25, 1.5e-3, 2E10: converted to double by the lexer */
 %token <str> T_STRINGLIT
 %token <boolval> T_BOOLLIT
 
@@ -53,6 +53,9 @@ static char* dupstr(const char *s) {
 
 /* precedence & associativity */
 /* from low to high precedence (lower listed earlier => lower prec) */
+/* NOTE: this grammar covers only a subset of the language and its expression rules
+   encode precedence by nesting; the compiler's precedence table (grammar.md, Operator
+   Precedence) has assignment lowest and more operators than are declared here. */
 %left T_OROR
 %left T_ANDAND
 %left T_EQUALSOP T_NEQ
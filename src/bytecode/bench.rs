@@ -0,0 +1,114 @@
+// Benchmark of the bytecode VM against the tree-walking interpreter on a few small
+// programs that each stress one thing: calls, arithmetic in loops, array indexing and
+// struct fields. Run it with optimisations, e.g. `cargo run --release -- --bench-vm`.
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::bytecode::codegen::compile;
+use crate::bytecode::vm::Vm;
+use crate::interpreter::value::Value;
+use crate::parser::ast::Program;
+
+pub const PROGRAMS: &[(&str, &str)] = &[
+    ("calls", "\
+fn fib(int n): int {
+  if (n < 2) {
+    return n;
+  }
+  return fib(n - 1) + fib(n - 2);
+}
+return fib(24);
+"),
+    ("loops", "\
+fn main(): int {
+  int total = 0;
+  for (int i = 0; i < 300000; i = i + 1) {
+    if (i % 3 == 0 || i % 5 == 0) {
+      total = total + i * 2 - (i >> 1);
+    }
+  }
+  return total;
+}
+"),
+    ("arrays", "\
+fn main(): int {
+  bool[20000] composite;
+  int count = 0;
+  for (int i = 2; i < 20000; i = i + 1) {
+    if (!composite[i]) {
+      count = count + 1;
+      for (int j = i * 2; j < 20000; j = j + i) {
+        composite[j] = true;
+      }
+    }
+  }
+  return count;
+}
+"),
+    ("structs", "\
+struct Body {
+  float x;
+  float v;
+}
+fn main(): float {
+  Body b = Body { x: 0.0, v: 1.0 };
+  int steps = 0;
+  while (steps < 100000) {
+    b.v = b.v - b.x * 0.01;
+    b.x = b.x + b.v * 0.01;
+    steps = steps + 1;
+  }
+  return b.x;
+}
+"),
+];
+
+pub struct BenchResult {
+    pub program: &'static str,
+    pub ast: Duration, // fastest run of the tree-walking interpreter
+    pub vm: Duration,  // fastest compile and run on the VM
+    pub agree: bool,   // both produced the same value
+}
+
+impl BenchResult {
+    pub fn speedup(&self) -> f64 {
+        self.ast.as_secs_f64() / self.vm.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<8} ast {:>9.2} ms  vm {:>9.2} ms  {:>5.1}x{}",
+               self.program, self.ast.as_secs_f64() * 1000.0, self.vm.as_secs_f64() * 1000.0, self.speedup(),
+               if self.agree { "" } else { "  RESULTS DIFFER" })
+    }
+}
+
+fn time<F: FnMut() -> Option<Value>>(iterations: usize, mut f: F) -> (Option<Value>, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        result = f();
+        best = best.min(start.elapsed());
+    }
+    (result, best)
+}
+
+pub fn run(iterations: usize) -> Vec<BenchResult> {
+    PROGRAMS
+        .iter()
+        .map(|(name, src)| {
+            let (program, diagnostics) = crate::parse(src);
+            assert!(diagnostics.is_empty(), "benchmark `{}` does not parse", name);
+            assert!(crate::check(&program).1.is_empty(), "benchmark `{}` does not check", name);
+            let (ast_result, ast) = time(iterations, || crate::run(&program).ok());
+            let (vm_result, vm) = time(iterations, || run_vm(&program));
+            BenchResult { program: name, ast, vm, agree: ast_result.is_some() && ast_result == vm_result }
+        })
+        .collect()
+}
+
+fn run_vm(program: &Program) -> Option<Value> {
+    let module = compile(program);
+    Vm::new(&module).run().ok()
+}
@@ -0,0 +1,206 @@
+// The bytecode: a Module holds one Chunk of instructions for each function and one for
+// the top-level code. Instructions are small fixed-size values; anything bigger than an
+// index (literal values, access paths, struct layouts, error messages) lives in a table
+// the instruction points into.
+use crate::interpreter::error::RuntimeErrorKind;
+use crate::interpreter::value::Value;
+use crate::span::Span;
+use crate::token::TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32), // push chunk.constants[i]
+    Void,
+    Pop,
+    GetLocal(u32), // slot in the current call's frame
+    SetLocal(u32), // stores the value on top of the stack and leaves it there
+    GetGlobal(u32),
+    SetGlobal(u32),
+    Load(u32),  // read through module.paths[i], whose index values are on the stack
+    Store(u32), // like Load, with the value to store below the root and indexes
+    Array(u32), // pop n elements, push the array of them
+    Struct(u32), // pop the initializers of module.structs[i], push the struct
+    Neg,
+    Not,
+    BitNot,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Cast(Conversion),
+    Jump(u32), // absolute instruction index
+    JumpIfFalse(u32), // pops a bool
+    JumpIfTrue(u32),
+    Call(u32), // module.functions[i]; its arguments are on the stack
+    Return,
+    Halt, // end of the top-level code; `main` runs next if there is one
+    Trap(u32), // fails with module.traps[i]
+}
+
+impl Op {
+    // The operator token of a unary or binary operation, for the shared implementation in
+    // interpreter::ops
+    pub fn operator(self) -> Option<TokenKind> {
+        Some(match self {
+            Op::Neg | Op::Sub => TokenKind::T_MINUS,
+            Op::Not => TokenKind::T_NOT,
+            Op::BitNot => TokenKind::T_TILDE,
+            Op::Add => TokenKind::T_PLUS,
+            Op::Mul => TokenKind::T_STAR,
+            Op::Div => TokenKind::T_SLASH,
+            Op::Rem => TokenKind::T_PERCENT,
+            Op::Pow => TokenKind::T_CARET,
+            Op::BitAnd => TokenKind::T_AMP,
+            Op::BitOr => TokenKind::T_PIPE,
+            Op::Shl => TokenKind::T_LSHIFT,
+            Op::Shr => TokenKind::T_RSHIFT,
+            Op::Eq => TokenKind::T_EQUALSOP,
+            Op::Ne => TokenKind::T_NEQ,
+            Op::Lt => TokenKind::T_LT,
+            Op::Gt => TokenKind::T_GT,
+            Op::Le => TokenKind::T_LTE,
+            Op::Ge => TokenKind::T_GTE,
+            _ => return None,
+        })
+    }
+}
+
+// Target of a cast
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conversion {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+}
+
+impl Conversion {
+    pub fn from_token(kind: &TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::T_INT => Some(Conversion::Int),
+            TokenKind::T_FLOAT => Some(Conversion::Float),
+            TokenKind::T_BOOL => Some(Conversion::Bool),
+            TokenKind::T_CHAR => Some(Conversion::Char),
+            TokenKind::T_STRING => Some(Conversion::Str),
+            _ => None,
+        }
+    }
+
+    pub fn token(self) -> TokenKind {
+        match self {
+            Conversion::Int => TokenKind::T_INT,
+            Conversion::Float => TokenKind::T_FLOAT,
+            Conversion::Bool => TokenKind::T_BOOL,
+            Conversion::Char => TokenKind::T_CHAR,
+            Conversion::Str => TokenKind::T_STRING,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>, // source of each instruction, for runtime errors
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn constant(&mut self, value: Value) -> u32 {
+        // scalars are shared; arrays and structs are rare enough as literals
+        if let Some(i) = self.constants.iter().position(|c| *c == value && !matches!(c, Value::Array(_) | Value::Struct(..))) {
+            return i as u32;
+        }
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    // Points the jump at `at` to the next instruction emitted
+    pub fn patch(&mut self, at: usize) {
+        let target = self.code.len() as u32;
+        match &mut self.code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpIfTrue(t) => *t = target,
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: u32,
+    pub locals: Vec<String>, // name of each frame slot; the parameters come first
+    pub chunk: Option<Chunk>, // None for a prototype that is never defined
+    pub span: Span,
+}
+
+// `xs[i].p`-style access: where it starts and what is applied to it. The index values are
+// computed onto the stack, in order, just before the Load or Store.
+#[derive(Debug)]
+pub struct Path {
+    pub root: Root,
+    pub steps: Vec<PathStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Root {
+    Local(u32),
+    Global(u32),
+    Temporary, // a value computed onto the stack before the indexes, e.g. `f().x`
+}
+
+#[derive(Debug)]
+pub enum PathStep {
+    Index(Span),
+    Field(String, Span),
+}
+
+impl Path {
+    pub fn indexes(&self) -> usize {
+        self.steps.iter().filter(|s| matches!(s, PathStep::Index(_))).count()
+    }
+}
+
+// How to build a struct from the initializers of a literal, which are on the stack in
+// source order
+#[derive(Debug)]
+pub struct StructLayout {
+    pub name: String,
+    pub fields: Vec<(String, Option<u32>)>, // declaration order; position of the initializer, if any
+    pub given: u32,                          // number of initializers on the stack
+}
+
+#[derive(Debug)]
+pub struct Trap {
+    pub kind: RuntimeErrorKind,
+    pub note: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub script: Function, // the top-level code
+    pub globals: Vec<String>,
+    pub paths: Vec<Path>,
+    pub structs: Vec<StructLayout>,
+    pub traps: Vec<Trap>,
+    pub main: Option<u32>, // `main` without parameters, run after the top-level code
+}
@@ -0,0 +1,461 @@
+// Compiles a Program to bytecode. Names are resolved here: locals become frame slots,
+// globals and functions become table indexes, so the VM never looks a name up. Every local
+// gets its own slot (a function's frame holds all of them at once), which keeps block
+// exits and `break` free of cleanup. Anything the interpreter would fail on when it gets
+// there (a call of an unknown function, a statement that did not parse) compiles to a
+// Trap in the same place, so both engines fail the same way.
+use std::collections::HashMap;
+use crate::bytecode::chunk::{Chunk, Conversion, Function, Module, Op, Path, PathStep, Root, StructLayout, Trap};
+use crate::interpreter::error::RuntimeErrorKind;
+use crate::interpreter::ops::declared_value;
+use crate::interpreter::value::Value;
+use crate::parser::ast::{DeclKind, Expr, ExprKind, Field, Program, Stmt, StmtKind, TypeAnnot};
+use crate::span::Span;
+use crate::token::TokenKind;
+
+pub fn compile(program: &Program) -> Module {
+    let mut codegen = Codegen::new(program);
+    for decl in &program.declarations {
+        if let DeclKind::Function { name, params, return_type, body } = &decl.kind {
+            codegen.begin_function(params.iter().map(|p| p.name.clone()).collect());
+            codegen.stmt(body);
+            // falling off the end of the body
+            if return_type.is_some() {
+                codegen.trap(RuntimeErrorKind::MissingReturnValue, decl.span,
+                             format!("`{}` has a return type, so every call must end in a `return` with a value", name));
+            } else {
+                codegen.emit(Op::Void, decl.span);
+                codegen.emit(Op::Return, decl.span);
+            }
+            // as in the interpreter, a later definition of the same name wins
+            let id = codegen.function_ids[name.as_str()] as usize;
+            let (chunk, locals) = codegen.end_function();
+            let function = &mut codegen.module.functions[id];
+            function.chunk = Some(chunk);
+            function.locals = locals;
+            function.span = decl.span;
+        }
+    }
+
+    codegen.begin_function(Vec::new());
+    codegen.scopes.clear(); // outside of any block, declarations are globals
+    for decl in &program.declarations {
+        match &decl.kind {
+            DeclKind::GlobalVar { name, type_annot, value } => {
+                codegen.initializer(value.as_ref(), type_annot.as_ref(), decl.span);
+                let id = codegen.global_ids[name.as_str()];
+                codegen.emit(Op::SetGlobal(id), decl.span);
+                codegen.emit(Op::Pop, decl.span);
+            }
+            DeclKind::Stmt(stmt) => codegen.stmt(stmt),
+            DeclKind::Function { .. } | DeclKind::Prototype { .. } | DeclKind::Struct { .. } => {}
+        }
+    }
+    let end = program.declarations.last().map_or_else(Span::default, |d| d.span);
+    codegen.emit(Op::Halt, end);
+    let (chunk, locals) = codegen.end_function();
+    codegen.module.script.chunk = Some(chunk);
+    codegen.module.script.locals = locals;
+
+    codegen.module.main = codegen.function_ids.get("main").copied().filter(|&id| {
+        let main = &codegen.module.functions[id as usize];
+        main.arity == 0 && main.chunk.is_some()
+    });
+    codegen.module
+}
+
+struct Codegen<'a> {
+    module: Module,
+    function_ids: HashMap<&'a str, u32>,
+    global_ids: HashMap<&'a str, u32>,
+    structs: HashMap<&'a str, &'a [Field]>,
+    // the function being compiled
+    chunk: Chunk,
+    locals: Vec<String>,
+    scopes: Vec<Vec<(String, u32)>>, // names declared in each open block, with their slots
+    loops: Vec<Vec<usize>>,          // `break` jumps of each enclosing loop, patched at its end
+}
+
+impl<'a> Codegen<'a> {
+    // Numbers every function, struct and global before any code is compiled, so code can
+    // refer to ones declared further down
+    fn new(program: &'a Program) -> Self {
+        let script = Function { name: "<top level>".to_string(), arity: 0, locals: Vec::new(), chunk: None, span: Span::default() };
+        let module = Module { functions: Vec::new(), script, globals: Vec::new(), paths: Vec::new(), structs: Vec::new(), traps: Vec::new(), main: None };
+        let mut codegen = Codegen {
+            module,
+            function_ids: HashMap::new(),
+            global_ids: HashMap::new(),
+            structs: HashMap::new(),
+            chunk: Chunk::default(),
+            locals: Vec::new(),
+            scopes: Vec::new(),
+            loops: Vec::new(),
+        };
+        for decl in &program.declarations {
+            match &decl.kind {
+                DeclKind::Function { name, params, .. } | DeclKind::Prototype { name, params, .. } => {
+                    if !codegen.function_ids.contains_key(name.as_str()) {
+                        codegen.function_ids.insert(name, codegen.module.functions.len() as u32);
+                        codegen.module.functions.push(Function { name: name.clone(), arity: params.len() as u32, locals: Vec::new(), chunk: None, span: decl.span });
+                    }
+                    if let DeclKind::Function { .. } = decl.kind {
+                        codegen.module.functions[codegen.function_ids[name.as_str()] as usize].arity = params.len() as u32;
+                    }
+                }
+                DeclKind::Struct { name, fields } => {
+                    codegen.structs.insert(name, fields);
+                }
+                DeclKind::GlobalVar { name, .. } | DeclKind::Stmt(Stmt { kind: StmtKind::Let { name, .. }, .. }) => {
+                    if !codegen.global_ids.contains_key(name.as_str()) {
+                        codegen.global_ids.insert(name, codegen.module.globals.len() as u32);
+                        codegen.module.globals.push(name.clone());
+                    }
+                }
+                DeclKind::Stmt(_) => {}
+            }
+        }
+        codegen
+    }
+
+    fn begin_function(&mut self, params: Vec<String>) {
+        self.chunk = Chunk::default();
+        self.scopes = vec![params.iter().cloned().zip(0..).collect()];
+        self.locals = params;
+        self.loops.clear();
+    }
+
+    fn end_function(&mut self) -> (Chunk, Vec<String>) {
+        (std::mem::take(&mut self.chunk), std::mem::take(&mut self.locals))
+    }
+
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.chunk.emit(op, span)
+    }
+
+    fn trap(&mut self, kind: RuntimeErrorKind, span: Span, note: impl Into<String>) {
+        self.module.traps.push(Trap { kind, note: Some(note.into()) });
+        let id = self.module.traps.len() as u32 - 1;
+        self.emit(Op::Trap(id), span);
+    }
+
+    fn constant(&mut self, value: Value, span: Span) {
+        let id = self.chunk.constant(value);
+        self.emit(Op::Constant(id), span);
+    }
+
+    // Pushes the initial value of a variable
+    fn initializer(&mut self, value: Option<&'a Expr>, type_annot: Option<&TypeAnnot>, span: Span) {
+        match value {
+            Some(value) => self.expr(value),
+            None => self.constant(declared_value(type_annot), span),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<Root> {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, slot)) = scope.iter().rev().find(|(n, _)| n == name) {
+                return Some(Root::Local(*slot));
+            }
+        }
+        self.global_ids.get(name).map(|&id| Root::Global(id))
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::Pop, span);
+            }
+            StmtKind::Let { name, type_annot, value } => {
+                // the initializer still sees an outer variable of the same name
+                self.initializer(value.as_ref(), type_annot.as_ref(), span);
+                let op = match self.scopes.last_mut() {
+                    Some(scope) => {
+                        let slot = self.locals.len() as u32;
+                        self.locals.push(name.clone());
+                        scope.push((name.clone(), slot));
+                        Op::SetLocal(slot)
+                    }
+                    None => Op::SetGlobal(self.global_ids[name.as_str()]),
+                };
+                self.emit(op, span);
+                self.emit(Op::Pop, span);
+            }
+            StmtKind::Block(stmts) => {
+                self.scopes.push(Vec::new());
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit(Op::Void, span);
+                    }
+                }
+                self.emit(Op::Return, span);
+            }
+            StmtKind::Break => {
+                if self.loops.is_empty() {
+                    self.trap(RuntimeErrorKind::TypeMismatch, span, "`break` outside of a loop");
+                } else {
+                    let jump = self.emit(Op::Jump(0), span);
+                    if let Some(breaks) = self.loops.last_mut() {
+                        breaks.push(jump);
+                    }
+                }
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.expr(condition);
+                let to_else = self.emit(Op::JumpIfFalse(0), condition.span);
+                self.stmt(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let to_end = self.emit(Op::Jump(0), span);
+                        self.chunk.patch(to_else);
+                        self.stmt(else_branch);
+                        self.chunk.patch(to_end);
+                    }
+                    None => self.chunk.patch(to_else),
+                }
+            }
+            StmtKind::While { condition, body } => {
+                let start = self.chunk.code.len() as u32;
+                self.expr(condition);
+                let exit = self.emit(Op::JumpIfFalse(0), condition.span);
+                self.loop_body(body, None, start, span);
+                self.chunk.patch(exit);
+                self.patch_breaks();
+            }
+            // `for (init; cond; incr) body` runs like `{ init; while (cond) { body; incr; } }`
+            StmtKind::For { init, condition, increment, body } => {
+                self.scopes.push(Vec::new());
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                let start = self.chunk.code.len() as u32;
+                let exit = condition.as_ref().map(|condition| {
+                    self.expr(condition);
+                    self.emit(Op::JumpIfFalse(0), condition.span)
+                });
+                self.loop_body(body, increment.as_ref(), start, span);
+                if let Some(exit) = exit {
+                    self.chunk.patch(exit);
+                }
+                self.patch_breaks();
+                self.scopes.pop();
+            }
+            StmtKind::Function { .. } => {} // nested functions are not callable, see analysis.rs
+            StmtKind::Error => self.trap(RuntimeErrorKind::TypeMismatch, span, "this statement did not parse"),
+        }
+    }
+
+    // Body of a loop, then the increment of a `for`, then the jump back to the condition.
+    // The caller patches the `break`s once the exit is known.
+    fn loop_body(&mut self, body: &'a Stmt, increment: Option<&'a Expr>, start: u32, span: Span) {
+        self.loops.push(Vec::new());
+        self.stmt(body);
+        if let Some(increment) = increment {
+            self.expr(increment);
+            self.emit(Op::Pop, increment.span);
+        }
+        self.emit(Op::Jump(start), span);
+    }
+
+    fn patch_breaks(&mut self) {
+        for jump in self.loops.pop().unwrap_or_default() {
+            self.chunk.patch(jump);
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.resolve(name) {
+                Some(Root::Local(slot)) => {
+                    self.emit(Op::GetLocal(slot), span);
+                }
+                Some(Root::Global(id)) => {
+                    self.emit(Op::GetGlobal(id), span);
+                }
+                _ => self.trap(RuntimeErrorKind::TypeMismatch, span, format!("`{}` is not a variable", name)),
+            },
+            ExprKind::Integer(n) => self.constant(Value::Int(*n), span),
+            ExprKind::Float(x) => self.constant(Value::Float(*x), span),
+            ExprKind::Boolean(b) => self.constant(Value::Bool(*b), span),
+            ExprKind::StringLit(s) => self.constant(Value::Str(s.clone()), span),
+            ExprKind::Char(c) => self.constant(Value::Char(*c), span),
+            ExprKind::Array(elements) => {
+                for element in elements {
+                    self.expr(element);
+                }
+                self.emit(Op::Array(elements.len() as u32), span);
+            }
+            ExprKind::Unary { operator, expr: inner } => {
+                self.expr(inner);
+                let op = match operator {
+                    TokenKind::T_MINUS => Op::Neg,
+                    TokenKind::T_NOT => Op::Not,
+                    TokenKind::T_TILDE => Op::BitNot,
+                    _ => return self.trap(RuntimeErrorKind::TypeMismatch, span, "no such unary operation"),
+                };
+                self.emit(op, span);
+            }
+            ExprKind::Binary { left, operator: TokenKind::T_ASSIGNOP, right } => self.assign(left, right),
+            // `a && b` is `a ? b : false` and `a || b` is `a ? true : b`, with the operands
+            // checked to be bools by the jumps
+            ExprKind::Binary { left, operator: operator @ (TokenKind::T_ANDAND | TokenKind::T_OROR), right } => {
+                let or = *operator == TokenKind::T_OROR;
+                let decide = |at| if or { Op::JumpIfTrue(at) } else { Op::JumpIfFalse(at) };
+                self.expr(left);
+                let first = self.emit(decide(0), left.span);
+                self.expr(right);
+                let second = self.emit(decide(0), right.span);
+                self.constant(Value::Bool(!or), span);
+                let to_end = self.emit(Op::Jump(0), span);
+                self.chunk.patch(first);
+                self.chunk.patch(second);
+                self.constant(Value::Bool(or), span);
+                self.chunk.patch(to_end);
+            }
+            ExprKind::Binary { left, operator, right } => {
+                self.expr(left);
+                self.expr(right);
+                let op = match operator {
+                    TokenKind::T_PLUS => Op::Add,
+                    TokenKind::T_MINUS => Op::Sub,
+                    TokenKind::T_STAR => Op::Mul,
+                    TokenKind::T_SLASH => Op::Div,
+                    TokenKind::T_PERCENT => Op::Rem,
+                    TokenKind::T_CARET => Op::Pow,
+                    TokenKind::T_AMP => Op::BitAnd,
+                    TokenKind::T_PIPE => Op::BitOr,
+                    TokenKind::T_LSHIFT => Op::Shl,
+                    TokenKind::T_RSHIFT => Op::Shr,
+                    TokenKind::T_EQUALSOP => Op::Eq,
+                    TokenKind::T_NEQ => Op::Ne,
+                    TokenKind::T_LT => Op::Lt,
+                    TokenKind::T_GT => Op::Gt,
+                    TokenKind::T_LTE => Op::Le,
+                    TokenKind::T_GTE => Op::Ge,
+                    _ => return self.trap(RuntimeErrorKind::TypeMismatch, span, "not a binary operator"),
+                };
+                self.emit(op, span);
+            }
+            ExprKind::Call { callee, args } => {
+                let ExprKind::Identifier(name) = &callee.kind else {
+                    return self.trap(RuntimeErrorKind::UndefinedFunction, callee.span, "only functions can be called, by name");
+                };
+                for arg in args {
+                    self.expr(arg);
+                }
+                match self.function_ids.get(name.as_str()) {
+                    Some(&id) if self.module.functions[id as usize].arity as usize == args.len() => {
+                        self.emit(Op::Call(id), span);
+                    }
+                    Some(&id) => {
+                        let arity = self.module.functions[id as usize].arity;
+                        self.trap(RuntimeErrorKind::TypeMismatch, span, format!("`{}` takes {} arguments but was given {}", name, arity, args.len()));
+                    }
+                    None => self.trap(RuntimeErrorKind::UndefinedFunction, span, format!("`{}` is not a function", name)),
+                }
+            }
+            ExprKind::Grouping(inner) => self.expr(inner),
+            ExprKind::Index { .. } | ExprKind::Field { .. } => {
+                let path = self.place(expr);
+                self.emit(Op::Load(path), span);
+            }
+            ExprKind::StructLit { name, fields } => {
+                let Some(declared) = self.structs.get(name.as_str()).copied() else {
+                    return self.trap(RuntimeErrorKind::TypeMismatch, span, format!("`{}` is not a struct", name));
+                };
+                // initializers run in source order; the value keeps the declaration's order
+                for init in fields {
+                    self.expr(&init.value);
+                }
+                let layout = declared
+                    .iter()
+                    .map(|field| (field.name.clone(), fields.iter().position(|init| init.name == field.name).map(|i| i as u32)))
+                    .collect();
+                self.module.structs.push(StructLayout { name: name.clone(), fields: layout, given: fields.len() as u32 });
+                let id = self.module.structs.len() as u32 - 1;
+                self.emit(Op::Struct(id), span);
+            }
+            ExprKind::Cast { target, expr: inner } => {
+                self.expr(inner);
+                match Conversion::from_token(target) {
+                    Some(conversion) => {
+                        self.emit(Op::Cast(conversion), span);
+                    }
+                    None => self.trap(RuntimeErrorKind::TypeMismatch, span, "no such conversion"),
+                }
+            }
+            ExprKind::Error => self.trap(RuntimeErrorKind::TypeMismatch, span, "this expression did not parse"),
+        }
+    }
+
+    // `target = value`: the value is computed first, then stored; the result is the value
+    fn assign(&mut self, target: &'a Expr, value: &'a Expr) {
+        self.expr(value);
+        let mut inner = target;
+        while let ExprKind::Grouping(e) = &inner.kind {
+            inner = e;
+        }
+        match &inner.kind {
+            ExprKind::Identifier(name) => match self.resolve(name) {
+                Some(Root::Local(slot)) => {
+                    self.emit(Op::SetLocal(slot), target.span);
+                }
+                Some(Root::Global(id)) => {
+                    self.emit(Op::SetGlobal(id), target.span);
+                }
+                _ => self.trap(RuntimeErrorKind::TypeMismatch, target.span, format!("`{}` is not a variable", name)),
+            },
+            _ => {
+                let path = self.place(target);
+                self.emit(Op::Store(path), target.span);
+            }
+        }
+    }
+
+    // Emits the code that computes the root (if it is a temporary) and the indexes of
+    // `xs[i].p`, from the inside out, and returns the path describing the rest
+    fn place(&mut self, expr: &'a Expr) -> u32 {
+        let mut steps = Vec::new();
+        let root = self.place_steps(expr, &mut steps);
+        self.module.paths.push(Path { root, steps });
+        self.module.paths.len() as u32 - 1
+    }
+
+    fn place_steps(&mut self, expr: &'a Expr, steps: &mut Vec<PathStep>) -> Root {
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.resolve(name) {
+                Some(root) => root,
+                None => {
+                    self.trap(RuntimeErrorKind::TypeMismatch, expr.span, format!("`{}` is not a variable", name));
+                    Root::Temporary
+                }
+            },
+            ExprKind::Grouping(inner) => self.place_steps(inner, steps),
+            ExprKind::Index { array, index } => {
+                let root = self.place_steps(array, steps);
+                self.expr(index);
+                steps.push(PathStep::Index(index.span));
+                root
+            }
+            ExprKind::Field { object, field } => {
+                let root = self.place_steps(object, steps);
+                steps.push(PathStep::Field(field.clone(), expr.span));
+                root
+            }
+            _ => {
+                self.expr(expr);
+                Root::Temporary
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Readable listing of a compiled Module for `--emit=bytecode`: each function's slots and
+// constant pool, then one instruction per line with its index, source position and what
+// its operand refers to, e.g.
+//
+//   fn bump(r)
+//     slots: r
+//     constants: #0 = 1
+//     0000     7:11  get_global 0     ; calls
+//     0001     7:19  constant #0      ; 1
+//     0002     7:11  add
+use crate::bytecode::chunk::{Chunk, Function, Module, Op, PathStep, Root};
+
+pub fn disassemble(module: &Module, src: &str) -> String {
+    let mut out = String::new();
+    for function in module.functions.iter().chain(std::iter::once(&module.script)) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        function_text(&mut out, module, function, src);
+    }
+    out
+}
+
+fn function_text(out: &mut String, module: &Module, function: &Function, src: &str) {
+    let params = &function.locals[..(function.arity as usize).min(function.locals.len())];
+    if std::ptr::eq(function, &module.script) {
+        out.push_str(&format!("{}\n", function.name));
+    } else {
+        out.push_str(&format!("fn {}({})\n", function.name, params.join(", ")));
+    }
+    let Some(chunk) = &function.chunk else {
+        out.push_str("  (prototype only)\n");
+        return;
+    };
+    if !function.locals.is_empty() {
+        out.push_str(&format!("  slots: {}\n", function.locals.join(", ")));
+    }
+    if !chunk.constants.is_empty() {
+        let constants: Vec<String> = chunk.constants.iter().enumerate().map(|(i, c)| format!("#{} = {}", i, c)).collect();
+        out.push_str(&format!("  constants: {}\n", constants.join(", ")));
+    }
+    for (i, op) in chunk.code.iter().enumerate() {
+        let (line, col) = chunk.spans[i].line_col(src);
+        let (text, comment) = op_text(module, function, chunk, *op);
+        let position = format!("{}:{}", line, col);
+        let line = match comment {
+            Some(comment) => format!("  {:04} {:>8}  {:<16} ; {}", i, position, text, comment),
+            None => format!("  {:04} {:>8}  {}", i, position, text),
+        };
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+}
+
+// Mnemonic with its operand, and what the operand refers to
+fn op_text(module: &Module, function: &Function, chunk: &Chunk, op: Op) -> (String, Option<String>) {
+    let local = |slot: u32| function.locals.get(slot as usize).cloned();
+    let global = |id: u32| module.globals.get(id as usize).cloned();
+    match op {
+        Op::Constant(i) => (format!("constant #{}", i), chunk.constants.get(i as usize).map(|c| c.to_string())),
+        Op::GetLocal(slot) => (format!("get_local {}", slot), local(slot)),
+        Op::SetLocal(slot) => (format!("set_local {}", slot), local(slot)),
+        Op::GetGlobal(id) => (format!("get_global {}", id), global(id)),
+        Op::SetGlobal(id) => (format!("set_global {}", id), global(id)),
+        Op::Load(path) => (format!("load {}", path), Some(path_text(module, function, path))),
+        Op::Store(path) => (format!("store {}", path), Some(path_text(module, function, path))),
+        Op::Array(n) => (format!("array {}", n), None),
+        Op::Struct(id) => (format!("struct {}", id), module.structs.get(id as usize).map(|s| {
+            let fields: Vec<&str> = s.fields.iter().map(|(f, _)| f.as_str()).collect();
+            format!("{} {{ {} }}", s.name, fields.join(", "))
+        })),
+        Op::Cast(conversion) => (format!("cast {:?}", conversion).to_lowercase(), None),
+        Op::Jump(target) => (format!("jump {:04}", target), None),
+        Op::JumpIfFalse(target) => (format!("jump_if_false {:04}", target), None),
+        Op::JumpIfTrue(target) => (format!("jump_if_true {:04}", target), None),
+        Op::Call(id) => (format!("call {}", id), module.functions.get(id as usize).map(|f| f.name.clone())),
+        Op::Trap(id) => ("trap".to_string(), module.traps.get(id as usize).map(|t| match &t.note {
+            Some(note) => format!("{:?}: {}", t.kind, note),
+            None => format!("{:?}", t.kind),
+        })),
+        Op::Void => ("void".to_string(), None),
+        Op::Pop => ("pop".to_string(), None),
+        Op::Neg => ("neg".to_string(), None),
+        Op::Not => ("not".to_string(), None),
+        Op::BitNot => ("bit_not".to_string(), None),
+        Op::Add => ("add".to_string(), None),
+        Op::Sub => ("sub".to_string(), None),
+        Op::Mul => ("mul".to_string(), None),
+        Op::Div => ("div".to_string(), None),
+        Op::Rem => ("rem".to_string(), None),
+        Op::Pow => ("pow".to_string(), None),
+        Op::BitAnd => ("bit_and".to_string(), None),
+        Op::BitOr => ("bit_or".to_string(), None),
+        Op::Shl => ("shl".to_string(), None),
+        Op::Shr => ("shr".to_string(), None),
+        Op::Eq => ("eq".to_string(), None),
+        Op::Ne => ("ne".to_string(), None),
+        Op::Lt => ("lt".to_string(), None),
+        Op::Gt => ("gt".to_string(), None),
+        Op::Le => ("le".to_string(), None),
+        Op::Ge => ("ge".to_string(), None),
+        Op::Return => ("return".to_string(), None),
+        Op::Halt => ("halt".to_string(), None),
+    }
+}
+
+// `xs[_].p`: the root, with `_` for each index taken from the stack
+fn path_text(module: &Module, function: &Function, path: u32) -> String {
+    let Some(path) = module.paths.get(path as usize) else {
+        return "?".to_string();
+    };
+    let mut text = match path.root {
+        Root::Local(slot) => function.locals.get(slot as usize).cloned().unwrap_or_default(),
+        Root::Global(id) => module.globals.get(id as usize).cloned().unwrap_or_default(),
+        Root::Temporary => "(temporary)".to_string(),
+    };
+    for step in &path.steps {
+        match step {
+            PathStep::Index(_) => text.push_str("[_]"),
+            PathStep::Field(name, _) => {
+                text.push('.');
+                text.push_str(name);
+            }
+        }
+    }
+    text
+}
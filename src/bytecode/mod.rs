@@ -0,0 +1,5 @@
+pub mod chunk;
+pub mod codegen;
+pub mod vm;
+pub mod disasm;
+pub mod bench;
@@ -0,0 +1,293 @@
+// Stack machine for a compiled Module. The value stack holds, for each active call, its
+// frame (arguments, then every local, see codegen.rs) followed by the operands of the
+// expression being computed. Call frames only record where a call's frame starts and
+// where to continue when it returns, so calls don't recurse in Rust: the depth limit is
+// MAX_CALL_DEPTH, the same as in the tree-walking interpreter.
+use crate::bytecode::chunk::{Chunk, Module, Op, PathStep, Root};
+use crate::interpreter::error::{Frame, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::eval::MAX_CALL_DEPTH;
+use crate::interpreter::ops::{self, mismatch, step_into, step_into_mut, Step};
+use crate::interpreter::value::Value;
+use crate::span::Span;
+
+struct CallFrame {
+    function: Option<u32>, // None for the top-level code
+    ip: usize,             // where to continue once the call this frame makes returns
+    base: usize,           // stack index of slot 0
+    call: Option<Span>,    // None for the top-level code and the implicit call of `main`
+}
+
+pub struct Vm<'m> {
+    module: &'m Module,
+    stack: Vec<Value>,
+    globals: Vec<Value>,
+    frames: Vec<CallFrame>,
+}
+
+impl<'m> Vm<'m> {
+    pub fn new(module: &'m Module) -> Self {
+        Vm { module, stack: Vec::new(), globals: vec![Value::Unassigned; module.globals.len()], frames: Vec::new() }
+    }
+
+    // Runs the module; its result is the value of a top-level `return`, else what `main`
+    // returns, else Void
+    pub fn run(mut self) -> Result<Value, RuntimeError> {
+        let script = &self.module.script;
+        self.stack.resize(script.locals.len(), Value::Unassigned);
+        self.frames.push(CallFrame { function: None, ip: 0, base: 0, call: None });
+        let result = self.execute();
+        result.map_err(|mut e| {
+            // the calls that were active, innermost first
+            e.trace = self.frames.iter().rev().filter_map(|frame| {
+                frame.function.map(|id| Frame { function: self.module.functions[id as usize].name.clone(), call: frame.call })
+            }).collect();
+            e
+        })
+    }
+
+    fn chunk(&self, function: Option<u32>) -> &'m Chunk {
+        let function = match function {
+            Some(id) => &self.module.functions[id as usize],
+            None => &self.module.script,
+        };
+        function.chunk.as_ref().expect("only functions with a body get a frame")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("bytecode left the stack unbalanced")
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        let module = self.module;
+        let mut chunk = self.chunk(None);
+        let mut ip = 0;
+        let mut base = 0;
+        loop {
+            let op = chunk.code[ip];
+            let span = chunk.spans[ip];
+            ip += 1;
+            match op {
+                Op::Constant(i) => self.stack.push(chunk.constants[i as usize].clone()),
+                Op::Void => self.stack.push(Value::Void),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::GetLocal(slot) => match &self.stack[base + slot as usize] {
+                    Value::Unassigned => {
+                        let name = self.local_name(slot);
+                        return Err(unassigned(span, name));
+                    }
+                    value => self.stack.push(value.clone()),
+                },
+                Op::SetLocal(slot) => {
+                    let value = self.stack.last().expect("bytecode left the stack unbalanced").clone();
+                    self.stack[base + slot as usize] = value;
+                }
+                Op::GetGlobal(id) => match &self.globals[id as usize] {
+                    Value::Unassigned => return Err(unassigned(span, &module.globals[id as usize])),
+                    value => self.stack.push(value.clone()),
+                },
+                Op::SetGlobal(id) => {
+                    let value = self.stack.last().expect("bytecode left the stack unbalanced").clone();
+                    self.globals[id as usize] = value;
+                }
+                Op::Load(path) => self.load(path, base, span)?,
+                Op::Store(path) => self.store(path, base)?,
+                Op::Array(n) => {
+                    let elements = self.stack.split_off(self.stack.len() - n as usize);
+                    self.stack.push(Value::Array(elements));
+                }
+                Op::Struct(id) => {
+                    let layout = &module.structs[id as usize];
+                    let mut given = self.stack.split_off(self.stack.len() - layout.given as usize);
+                    let fields = layout
+                        .fields
+                        .iter()
+                        .map(|(name, at)| {
+                            let value = at.map_or(Value::Unassigned, |i| std::mem::replace(&mut given[i as usize], Value::Unassigned));
+                            (name.clone(), value)
+                        })
+                        .collect();
+                    self.stack.push(Value::Struct(layout.name.clone(), fields));
+                }
+                Op::Neg | Op::Not | Op::BitNot => {
+                    let value = self.pop();
+                    let operator = op.operator().expect("unary operation");
+                    self.stack.push(ops::unary(&operator, value, span)?);
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem | Op::Pow | Op::BitAnd | Op::BitOr | Op::Shl | Op::Shr
+                | Op::Eq | Op::Ne | Op::Lt | Op::Gt | Op::Le | Op::Ge => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let value = match (&l, &r) {
+                        (Value::Int(a), Value::Int(b)) => int_fast(op, *a, *b),
+                        _ => None,
+                    };
+                    let value = match value {
+                        Some(value) => value,
+                        None => ops::binary(&op.operator().expect("binary operation"), l, r, span)?,
+                    };
+                    self.stack.push(value);
+                }
+                Op::Cast(conversion) => {
+                    let value = self.pop();
+                    self.stack.push(ops::cast(&conversion.token(), value, span)?);
+                }
+                Op::Jump(target) => ip = target as usize,
+                Op::JumpIfFalse(target) | Op::JumpIfTrue(target) => match self.pop() {
+                    Value::Bool(b) => {
+                        if b == matches!(op, Op::JumpIfTrue(_)) {
+                            ip = target as usize;
+                        }
+                    }
+                    other => return Err(mismatch(span, &other, "a condition must be a `bool`")),
+                },
+                Op::Call(id) => {
+                    let function = &module.functions[id as usize];
+                    let Some(callee) = &function.chunk else {
+                        return Err(RuntimeError::new(RuntimeErrorKind::UndefinedFunction, span)
+                            .with_note(format!("`{}` is declared by a prototype but never defined", function.name)));
+                    };
+                    if self.frames.len() > MAX_CALL_DEPTH {
+                        return Err(RuntimeError::new(RuntimeErrorKind::StackOverflow, span)
+                            .with_note(format!("calls can be nested at most {} deep", MAX_CALL_DEPTH)));
+                    }
+                    if let Some(caller) = self.frames.last_mut() {
+                        caller.ip = ip;
+                    }
+                    base = self.stack.len() - function.arity as usize;
+                    self.stack.resize(base + function.locals.len(), Value::Unassigned);
+                    self.frames.push(CallFrame { function: Some(id), ip: 0, base, call: Some(span) });
+                    chunk = callee;
+                    ip = 0;
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("a frame is running");
+                    // the top-level code and `main` have no caller to go back to
+                    if frame.call.is_none() {
+                        return Ok(value);
+                    }
+                    self.stack.truncate(frame.base);
+                    self.stack.push(value);
+                    let caller = self.frames.last().expect("a called function has a caller");
+                    chunk = self.chunk(caller.function);
+                    ip = caller.ip;
+                    base = caller.base;
+                }
+                Op::Halt => {
+                    let Some(main) = module.main else {
+                        return Ok(Value::Void);
+                    };
+                    let function = &module.functions[main as usize];
+                    base = self.stack.len();
+                    self.stack.resize(base + function.locals.len(), Value::Unassigned);
+                    self.frames.push(CallFrame { function: Some(main), ip: 0, base, call: None });
+                    chunk = self.chunk(Some(main));
+                    ip = 0;
+                }
+                Op::Trap(id) => {
+                    let trap = &module.traps[id as usize];
+                    let e = RuntimeError::new(trap.kind.clone(), span);
+                    return Err(match &trap.note {
+                        Some(note) => e.with_note(note.clone()),
+                        None => e,
+                    });
+                }
+            }
+        }
+    }
+
+    fn local_name(&self, slot: u32) -> &'m str {
+        let function = match self.frames.last().and_then(|f| f.function) {
+            Some(id) => &self.module.functions[id as usize],
+            None => &self.module.script,
+        };
+        &function.locals[slot as usize]
+    }
+
+    // Reads the element a path leads to. The stack holds the root (when it is a
+    // temporary) and then one value per index; all of them are replaced by the element.
+    fn load(&mut self, path: u32, base: usize, span: Span) -> Result<(), RuntimeError> {
+        let path = &self.module.paths[path as usize];
+        let first_index = self.stack.len() - path.indexes();
+        let (below, indexes) = self.stack.split_at(first_index);
+        let mut value = match path.root {
+            Root::Local(slot) => &below[base + slot as usize],
+            Root::Global(id) => &self.globals[id as usize],
+            Root::Temporary => below.last().expect("temporary root on the stack"),
+        };
+        let mut indexes = indexes.iter();
+        for step in &path.steps {
+            value = step_into(value, &step_of(step, &mut indexes)?)?;
+        }
+        let value = match value {
+            Value::Unassigned => {
+                return Err(RuntimeError::new(RuntimeErrorKind::UnassignedValue, span).with_note("this element was never assigned"));
+            }
+            value => value.clone(),
+        };
+        let keep = first_index - usize::from(path.root == Root::Temporary);
+        self.stack.truncate(keep);
+        self.stack.push(value);
+        Ok(())
+    }
+
+    // Stores into the element a path leads to. Below the root and indexes (as for load) is
+    // the value, which stays on the stack as the result of the assignment.
+    fn store(&mut self, path: u32, base: usize) -> Result<(), RuntimeError> {
+        let path = &self.module.paths[path as usize];
+        let first_index = self.stack.len() - path.indexes();
+        let temporary = usize::from(path.root == Root::Temporary);
+        let value_at = first_index - temporary - 1;
+        let value = self.stack[value_at].clone();
+        let (below, indexes) = self.stack.split_at_mut(first_index);
+        let mut slot = match path.root {
+            Root::Local(slot) => &mut below[base + slot as usize],
+            Root::Global(id) => &mut self.globals[id as usize],
+            Root::Temporary => below.last_mut().expect("temporary root on the stack"),
+        };
+        let mut indexes = indexes.iter();
+        for step in &path.steps {
+            slot = step_into_mut(slot, &step_of(step, &mut indexes)?)?;
+        }
+        *slot = value;
+        self.stack.truncate(value_at + 1);
+        Ok(())
+    }
+}
+
+// The step a path takes next, with its index value taken from the stack
+fn step_of<'p>(step: &'p PathStep, indexes: &mut std::slice::Iter<Value>) -> Result<Step<'p>, RuntimeError> {
+    match step {
+        PathStep::Index(span) => match indexes.next() {
+            Some(Value::Int(i)) => Ok(Step::Index(*i, *span)),
+            Some(other) => Err(mismatch(*span, other, "an index must be an `int`")),
+            None => unreachable!("one index value per index step"),
+        },
+        PathStep::Field(name, span) => Ok(Step::Field(name, *span)),
+    }
+}
+
+fn unassigned(span: Span, name: &str) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::UnassignedValue, span).with_note(format!("`{}` is read before it is assigned", name))
+}
+
+// Int operations that cannot fail, without going through ops::binary; None for the rest,
+// including ones that overflow, so the shared code reports them
+fn int_fast(op: Op, a: i64, b: i64) -> Option<Value> {
+    match op {
+        Op::Add => a.checked_add(b).map(Value::Int),
+        Op::Sub => a.checked_sub(b).map(Value::Int),
+        Op::Mul => a.checked_mul(b).map(Value::Int),
+        Op::BitAnd => Some(Value::Int(a & b)),
+        Op::BitOr => Some(Value::Int(a | b)),
+        Op::Eq => Some(Value::Bool(a == b)),
+        Op::Ne => Some(Value::Bool(a != b)),
+        Op::Lt => Some(Value::Bool(a < b)),
+        Op::Gt => Some(Value::Bool(a > b)),
+        Op::Le => Some(Value::Bool(a <= b)),
+        Op::Ge => Some(Value::Bool(a >= b)),
+        _ => None,
+    }
+}
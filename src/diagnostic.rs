@@ -0,0 +1,373 @@
+// One format for everything the compiler reports. LexError, ParseError, ScopeError,
+// TypeChkError, FlowWarning and RuntimeError each convert into a Diagnostic, which
+// carries a stable code, a severity and labelled spans, and renders like rustc:
+//
+//   error[E0416]: mismatched types in assignment
+//    --> sample.src:7:1
+//     |
+//   7 | y = "text";
+//     | ^^^^^^^^^^ expected `int`, found `string`
+//
+// Codes are grouped by stage: E01xx lexer, E02xx parser, E03xx names and scopes,
+// E04xx types, W05xx control flow, E06xx runtime. A code is never reused for a
+// different problem.
+//
+// For tools there are two machine-readable forms of the same data, selected with
+// --error-format: a small JSON document of our own (`to_json`, its layout is versioned)
+// and SARIF 2.1.0 (`to_sarif`), which code-review bots and CI annotations understand.
+use std::fmt;
+use crate::span::{self, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String, // may be empty: the span is underlined without text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>, // related places, e.g. an earlier declaration
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            primary: Label { span, message: String::new() },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    // text under the primary span
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // rustc-style text: header, location, every labelled source line, then notes and help.
+    // `src` must be the text the spans point into.
+    pub fn render(&self, file_name: &str, src: &str) -> String {
+        let (line, col) = self.primary.span.line_col(src);
+        let mut labels: Vec<(&Label, bool)> = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|l| (l, false)));
+        labels.sort_by_key(|(l, _)| l.span.start);
+
+        let last_line = labels.iter().map(|(l, _)| l.span.line_col(src).0).max().unwrap_or(line);
+        let pad = " ".repeat(last_line.to_string().len());
+
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        out.push_str(&format!("{}--> {}:{}:{}\n", pad, file_name, line, col));
+        out.push_str(&format!("{} |\n", pad));
+
+        let mut shown_line = 0;
+        for (label, is_primary) in labels {
+            let (label_line, _) = label.span.line_col(src);
+            let (line_start, text) = source_line(src, label.span.start);
+            if label_line != shown_line {
+                out.push_str(&format!("{:>w$} | {}\n", label_line, text, w = pad.len()));
+                shown_line = label_line;
+            }
+            // keep tabs so the marker lines up with the source line above it
+            let indent: String = src[line_start..label.span.start.min(src.len())]
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            // a span running past the end of its line is underlined up to the line end
+            let line_end = line_start + text.len();
+            let end = label.span.end.clamp(label.span.start, line_end.max(label.span.start));
+            let width = src.get(label.span.start..end).map_or(0, |s| s.chars().count()).max(1);
+            let marker = if is_primary { "^" } else { "-" }.repeat(width);
+            let text = if label.message.is_empty() { String::new() } else { format!(" {}", label.message) };
+            out.push_str(&format!("{} | {}{}{}\n", pad, indent, marker, text));
+        }
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            out.push_str(&format!("{} |\n", pad));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", pad, note));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} = help: {}\n", pad, help));
+        }
+        out
+    }
+}
+
+// byte offset where the line holding `offset` starts, and that line without its newline
+fn source_line(src: &str, offset: usize) -> (usize, &str) {
+    let offset = offset.min(src.len());
+    let start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+    (start, src[start..end].trim_end_matches('\r'))
+}
+
+// "1 error and 2 warnings"-style summary; None when there is nothing to report
+pub fn summary(diagnostics: &[Diagnostic]) -> Option<String> {
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    match (errors, warnings) {
+        (0, 0) => None,
+        (e, 0) => Some(plural(e, "error")),
+        (0, w) => Some(plural(w, "warning")),
+        (e, w) => Some(format!("{} and {}", plural(e, "error"), plural(w, "warning"))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human, // rustc-style text
+    Json,
+    Sarif,
+}
+
+impl ErrorFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            "sarif" => Some(ErrorFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+// one input file and everything reported about it; a file that could not be read has
+// an empty `src`, no diagnostics and the reason in `read_error`
+pub struct FileDiagnostics<'a> {
+    pub name: &'a str,
+    pub src: &'a str,
+    pub diagnostics: &'a [Diagnostic],
+    pub read_error: Option<&'a str>,
+}
+
+// Bumped whenever a field is renamed or removed; adding fields keeps the version.
+pub const JSON_VERSION: u32 = 1;
+
+// {"version": 1, "errors": N, "warnings": N, "diagnostics": [{"file", "code", "severity",
+//   "message", "span", "label", "related": [{"span", "message"}], "notes", "help"}],
+//   "unreadable": [{"file", "message"}]}
+// where a span is {"start", "end"} byte offsets plus 1-based "line", "column",
+// "end_line", "end_column" (columns in chars, end exclusive)
+pub fn to_json(files: &[FileDiagnostics]) -> String {
+    let all = files.iter().flat_map(|f| f.diagnostics.iter());
+    let errors = all.clone().filter(|d| d.is_error()).count();
+    let warnings = all.count() - errors;
+
+    let mut entries = Vec::new();
+    for file in files {
+        for d in file.diagnostics {
+            let related: Vec<String> = d
+                .secondary
+                .iter()
+                .map(|l| format!("{{\"span\": {}, \"message\": {}}}", json_span(l.span, file.src), json_str(&l.message)))
+                .collect();
+            let notes: Vec<String> = d.notes.iter().map(|n| json_str(n)).collect();
+            entries.push(format!(
+                "    {{\"file\": {}, \"code\": {}, \"severity\": {}, \"message\": {}, \"span\": {}, \"label\": {}, \"related\": [{}], \"notes\": [{}], \"help\": {}}}",
+                json_str(file.name),
+                json_str(d.code),
+                json_str(&d.severity.to_string()),
+                json_str(&d.message),
+                json_span(d.primary.span, file.src),
+                json_str(&d.primary.message),
+                related.join(", "),
+                notes.join(", "),
+                d.help.as_deref().map_or("null".to_string(), json_str),
+            ));
+        }
+    }
+    let list = if entries.is_empty() { "[]".to_string() } else { format!("[\n{}\n  ]", entries.join(",\n")) };
+    let unreadable: Vec<String> = files
+        .iter()
+        .filter_map(|f| f.read_error.map(|e| format!("{{\"file\": {}, \"message\": {}}}", json_str(f.name), json_str(e))))
+        .collect();
+    format!(
+        "{{\n  \"version\": {},\n  \"errors\": {},\n  \"warnings\": {},\n  \"diagnostics\": {},\n  \"unreadable\": [{}]\n}}\n",
+        JSON_VERSION,
+        errors,
+        warnings,
+        list,
+        unreadable.join(", ")
+    )
+}
+
+// SARIF 2.1.0 log with a single run. Each code becomes a rule; notes and help go into
+// the result message after the headline, related labels into relatedLocations. Files
+// that could not be read become toolExecutionNotifications of the run's invocation.
+pub fn to_sarif(files: &[FileDiagnostics]) -> String {
+    let mut codes: Vec<&str> = files.iter().flat_map(|f| f.diagnostics.iter().map(|d| d.code)).collect();
+    codes.sort_unstable();
+    codes.dedup();
+    let rules: Vec<String> = codes.iter().map(|c| format!("{{\"id\": {}}}", json_str(c))).collect();
+
+    let mut results = Vec::new();
+    for file in files {
+        for d in file.diagnostics {
+            let mut text = d.message.clone();
+            if !d.primary.message.is_empty() {
+                text.push_str(&format!(": {}", d.primary.message));
+            }
+            for note in &d.notes {
+                text.push_str(&format!("\nnote: {}", note));
+            }
+            if let Some(help) = &d.help {
+                text.push_str(&format!("\nhelp: {}", help));
+            }
+            let related: Vec<String> = d
+                .secondary
+                .iter()
+                .enumerate()
+                .map(|(i, l)| {
+                    format!(
+                        "{{\"id\": {}, \"physicalLocation\": {}, \"message\": {{\"text\": {}}}}}",
+                        i,
+                        sarif_location(file.name, l.span, file.src),
+                        json_str(&l.message)
+                    )
+                })
+                .collect();
+            let level = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            results.push(format!(
+                "        {{\"ruleId\": {}, \"level\": \"{}\", \"message\": {{\"text\": {}}}, \"locations\": [{{\"physicalLocation\": {}}}], \"relatedLocations\": [{}]}}",
+                json_str(d.code),
+                level,
+                json_str(&text),
+                sarif_location(file.name, d.primary.span, file.src),
+                related.join(", ")
+            ));
+        }
+    }
+    let results = if results.is_empty() { "[]".to_string() } else { format!("[\n{}\n      ]", results.join(",\n")) };
+    let notifications: Vec<String> = files
+        .iter()
+        .filter_map(|f| {
+            f.read_error.map(|e| {
+                format!(
+                    "{{\"level\": \"error\", \"message\": {{\"text\": {}}}, \"locations\": [{{\"physicalLocation\": {{\"artifactLocation\": {{\"uri\": {}}}}}}}]}}",
+                    json_str(&format!("cannot read {}: {}", f.name, e)),
+                    json_str(f.name)
+                )
+            })
+        })
+        .collect();
+    let invocation = format!(
+        "{{\"executionSuccessful\": {}, \"toolExecutionNotifications\": [{}]}}",
+        notifications.is_empty(),
+        notifications.join(", ")
+    );
+    format!(
+        concat!(
+            "{{\n",
+            "  \"$schema\": \"https://json.schemastore.org/sarif-2.1.0.json\",\n",
+            "  \"version\": \"2.1.0\",\n",
+            "  \"runs\": [\n",
+            "    {{\n",
+            "      \"tool\": {{\"driver\": {{\"name\": \"{}\", \"version\": \"{}\", \"rules\": [{}]}}}},\n",
+            "      \"invocations\": [{}],\n",
+            "      \"columnKind\": \"unicodeCodePoints\",\n",
+            "      \"results\": {}\n",
+            "    }}\n",
+            "  ]\n",
+            "}}\n"
+        ),
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        rules.join(", "),
+        invocation,
+        results
+    )
+}
+
+fn json_span(span: Span, src: &str) -> String {
+    let (line, column) = span::line_col(src, span.start);
+    let (end_line, end_column) = span::line_col(src, span.end);
+    format!(
+        "{{\"start\": {}, \"end\": {}, \"line\": {}, \"column\": {}, \"end_line\": {}, \"end_column\": {}}}",
+        span.start, span.end, line, column, end_line, end_column
+    )
+}
+
+fn sarif_location(file_name: &str, span: Span, src: &str) -> String {
+    let (line, column) = span::line_col(src, span.start);
+    let (end_line, end_column) = span::line_col(src, span.end);
+    format!(
+        "{{\"artifactLocation\": {{\"uri\": {}}}, \"region\": {{\"startLine\": {}, \"startColumn\": {}, \"endLine\": {}, \"endColumn\": {}, \"byteOffset\": {}, \"byteLength\": {}}}}}",
+        json_str(file_name), line, column, end_line, end_column, span.start, span.len()
+    )
+}
+
+// JSON string literal for `s`, quotes included
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
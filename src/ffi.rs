@@ -0,0 +1,254 @@
+// C ABI over HandLexer for the Bison parser in grammar/. The functions and TokenBridge
+// below are what grammar/lexer_shim.c declares; the kind numbers are the ones its yylex
+// switch maps onto Bison tokens. Build the library with `cargo build` and link the
+// parser against target/debug/libcompiler.a (see README).
+use std::ffi::{c_char, c_double, c_int, c_longlong, c_void, CStr};
+use std::ptr;
+use crate::lexer::HandLexer;
+use crate::token::{Token, TokenKind};
+
+unsafe extern "C" {
+    // strings handed to C are freed by parser.y with free(), so they must come from malloc
+    fn malloc(size: usize) -> *mut c_void;
+}
+
+// Layout must match the TokenBridge typedef in grammar/lexer_shim.c
+#[repr(C)]
+pub struct TokenBridge {
+    pub kind: c_int,
+    pub intval: c_longlong,
+    pub floatval: c_double,
+    pub strptr: *mut c_char, // malloc'd, NUL-terminated; owned by the receiver
+    pub boolv: c_int,
+}
+
+// Kind numbers shared with lexer_shim.c
+pub const KIND_IDENT: c_int = 1;
+pub const KIND_INTLIT: c_int = 2;
+pub const KIND_FLOATLIT: c_int = 3;
+pub const KIND_STRINGLIT: c_int = 4;
+pub const KIND_BOOLLIT: c_int = 5;
+
+// token kind -> shim kind number; None for tokens the Bison grammar has no terminal for
+pub fn bridge_kind(kind: &TokenKind) -> Option<c_int> {
+    use TokenKind::*;
+    let n = match kind {
+        T_IDENTIFIER(_) => KIND_IDENT,
+        T_INTLIT(_) => KIND_INTLIT,
+        T_FLOATLIT(_) => KIND_FLOATLIT,
+        T_STRINGLIT(_) => KIND_STRINGLIT,
+        T_BOOLLIT(_) => KIND_BOOLLIT,
+
+        T_FUNCTION => 10,
+        T_RETURN => 11,
+        T_IF => 12,
+        T_ELSE => 13,
+        T_FOR => 14,
+        T_WHILE => 15,
+        T_INT => 20,
+        T_FLOAT => 21,
+        T_BOOL => 22,
+        T_STRING => 23,
+
+        T_PARENL => 30,
+        T_PARENR => 31,
+        T_BRACEL => 32,
+        T_BRACER => 33,
+        T_COMMA => 40,
+        T_SEMICOLON => 41,
+        T_COLON => 42,
+
+        T_ASSIGNOP => 50,
+        T_EQUALSOP => 51,
+        T_NEQ => 52,
+        T_LT => 60,
+        T_GT => 61,
+        T_LTE => 62,
+        T_GTE => 63,
+        T_PLUS => 70,
+        T_MINUS => 71,
+        T_STAR => 72,
+        T_SLASH => 73,
+        T_PERCENT => 74,
+        T_NOT => 80,
+        T_ANDAND => 81,
+        T_OROR => 82,
+        T_LSHIFT => 90,
+        T_RSHIFT => 91,
+        T_AMP => 92,
+        T_PIPE => 93,
+        T_CARET => 94,
+        T_TILDE => 95,
+
+        T_BREAK | T_CHAR | T_STRUCT | T_CHARLIT(_) | T_BRACKETL | T_BRACKETR | T_DOT
+        | T_QUOTES | T_ERROR | T_EOF => return None,
+    };
+    Some(n)
+}
+
+// Token stream handed out one token at a time to lexer_next_bridge
+pub struct BridgeLexer {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl BridgeLexer {
+    pub fn new(src: &str) -> Self {
+        let (tokens, errors) = HandLexer::new(src).tokenize_recovering();
+        for e in &errors {
+            eprintln!("Lexing error: {}", e);
+        }
+        Self { tokens, next: 0 }
+    }
+
+    // the next token the Bison grammar knows about; quotes around strings are skipped.
+    // Stops (None) at T_EOF, and at the first token the grammar cannot represent.
+    pub fn next_token(&mut self) -> Option<&Token> {
+        while let Some(token) = self.tokens.get(self.next) {
+            self.next += 1;
+            match token.kind {
+                TokenKind::T_QUOTES => continue,
+                TokenKind::T_EOF => return None,
+                TokenKind::T_ERROR => {
+                    // already reported by the lexer
+                    self.next = self.tokens.len();
+                    return None;
+                }
+                _ if bridge_kind(&token.kind).is_none() => {
+                    eprintln!("Token {} at {}:{} is not supported by the Bison grammar", token.kind, token.line, token.col);
+                    self.next = self.tokens.len();
+                    return None;
+                }
+                _ => return self.tokens.get(self.next - 1),
+            }
+        }
+        None
+    }
+}
+
+// copies `s` into a malloc'd C string (cut at the first interior NUL, if any)
+fn malloc_c_string(s: &str) -> *mut c_char {
+    let bytes = s.as_bytes();
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    unsafe {
+        let buf = malloc(len + 1) as *mut u8;
+        if buf.is_null() {
+            return ptr::null_mut();
+        }
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buf, len);
+        *buf.add(len) = 0;
+        buf as *mut c_char
+    }
+}
+
+/// Lexes a NUL-terminated source string and returns an opaque lexer handle, or null if
+/// `src` is null. Invalid UTF-8 is replaced with U+FFFD. Release it with `lexer_free`.
+///
+/// # Safety
+/// `src` must be null or point to a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lexer_new_from_c(src: *const c_char) -> *mut c_void {
+    if src.is_null() {
+        return ptr::null_mut();
+    }
+    let src = unsafe { CStr::from_ptr(src) }.to_string_lossy();
+    Box::into_raw(Box::new(BridgeLexer::new(&src))) as *mut c_void
+}
+
+/// Writes the next token into `out` and returns 1, or returns 0 at the end of input.
+/// String and identifier tokens carry a malloc'd `strptr` the caller must free().
+///
+/// # Safety
+/// `lex` must be a handle from `lexer_new_from_c` that has not been freed, and `out` must
+/// point to a writable TokenBridge.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lexer_next_bridge(lex: *mut c_void, out: *mut c_void) -> c_int {
+    if lex.is_null() || out.is_null() {
+        return 0;
+    }
+    let lexer = unsafe { &mut *(lex as *mut BridgeLexer) };
+    let Some(token) = lexer.next_token() else {
+        return 0;
+    };
+    let mut bridge = TokenBridge {
+        kind: bridge_kind(&token.kind).unwrap(),
+        intval: 0,
+        floatval: 0.0,
+        strptr: ptr::null_mut(),
+        boolv: 0,
+    };
+    match &token.kind {
+        TokenKind::T_IDENTIFIER(s) | TokenKind::T_STRINGLIT(s) => bridge.strptr = malloc_c_string(s),
+        TokenKind::T_INTLIT(n) => bridge.intval = *n,
+        TokenKind::T_FLOATLIT(x) => bridge.floatval = *x,
+        TokenKind::T_BOOLLIT(b) => bridge.boolv = *b as c_int,
+        _ => {}
+    }
+    unsafe { ptr::write(out as *mut TokenBridge, bridge) };
+    1
+}
+
+/// Releases a handle from `lexer_new_from_c`; null is ignored.
+///
+/// # Safety
+/// `lex` must be null or a handle from `lexer_new_from_c` that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lexer_free(lex: *mut c_void) {
+    if !lex.is_null() {
+        drop(unsafe { Box::from_raw(lex as *mut BridgeLexer) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    unsafe extern "C" {
+        fn free(ptr: *mut c_void);
+    }
+
+    // every token the bridge hands out for `src`, with its text if it carries one
+    fn bridge_tokens(src: &str) -> Vec<(c_int, c_longlong, Option<String>)> {
+        let src = CString::new(src).unwrap();
+        let mut tokens = Vec::new();
+        unsafe {
+            let lex = lexer_new_from_c(src.as_ptr());
+            assert!(!lex.is_null());
+            let mut out = TokenBridge { kind: 0, intval: 0, floatval: 0.0, strptr: ptr::null_mut(), boolv: 0 };
+            while lexer_next_bridge(lex, &mut out as *mut TokenBridge as *mut c_void) == 1 {
+                let text = (!out.strptr.is_null()).then(|| CStr::from_ptr(out.strptr).to_string_lossy().into_owned());
+                free(out.strptr as *mut c_void);
+                out.strptr = ptr::null_mut();
+                tokens.push((out.kind, out.intval, text));
+            }
+            lexer_free(lex);
+        }
+        tokens
+    }
+
+    #[test]
+    fn bridge_hands_out_kinds_values_and_strings() {
+        let tokens = bridge_tokens("int x = 0x10 + 1_000; \"hi\"");
+        let expected = [
+            (20, 0, None),
+            (KIND_IDENT, 0, Some("x".to_string())),
+            (50, 0, None),
+            (KIND_INTLIT, 16, None),
+            (70, 0, None),
+            (KIND_INTLIT, 1000, None),
+            (41, 0, None),
+            (KIND_STRINGLIT, 0, Some("hi".to_string())),
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn bridge_stops_at_tokens_the_grammar_lacks() {
+        assert_eq!(bridge_tokens("x; break; y").len(), 2);
+        unsafe {
+            assert!(lexer_new_from_c(ptr::null()).is_null());
+            lexer_free(ptr::null_mut());
+        }
+    }
+}
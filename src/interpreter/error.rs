@@ -0,0 +1,129 @@
+use std::fmt;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span, // expression or statement that failed
+    pub note: Option<String>,
+    pub trace: Vec<Frame>, // the calls that were active, innermost first
+}
+
+// One active call: the function and where it was called from. The implicit call of
+// `main` after the top-level code has no call site.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub call: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> Self {
+        Self { kind, span, note: None, trace: Vec::new() }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    IntegerOverflow,
+    IndexOutOfBounds,
+    UnassignedValue,    // read of a variable, element or field that was never assigned
+    MissingReturnValue, // function with a return type finished without a `return`
+    InvalidConversion,  // e.g. `char(n)` for an n that is not a code point
+    InvalidShift,       // shift amount outside 0..64
+    NegativeExponent,   // int ^ negative int
+    StackOverflow,
+    UndefinedFunction,  // only a prototype, or a callee that is not a function name
+    TypeMismatch,       // an operation the type checker would have rejected
+}
+
+impl RuntimeErrorKind {
+    // stable code, headline and label of each error
+    fn describe(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            RuntimeErrorKind::DivisionByZero => ("E0601", "division by zero", "the divisor is zero"),
+            RuntimeErrorKind::IntegerOverflow => ("E0602", "integer overflow", "the result does not fit in an `int`"),
+            RuntimeErrorKind::IndexOutOfBounds => ("E0603", "index out of bounds", "no element at this index"),
+            RuntimeErrorKind::UnassignedValue => ("E0604", "use of an unassigned value", "this value was never assigned"),
+            RuntimeErrorKind::MissingReturnValue => ("E0605", "function finished without returning a value", "the end of this function was reached"),
+            RuntimeErrorKind::InvalidConversion => ("E0606", "invalid conversion", "this value cannot be converted"),
+            RuntimeErrorKind::InvalidShift => ("E0607", "shift amount out of range", "shift amounts must be between 0 and 63"),
+            RuntimeErrorKind::NegativeExponent => ("E0608", "negative exponent", "an `int` can only be raised to a power of 0 or more"),
+            RuntimeErrorKind::StackOverflow => ("E0609", "call stack exhausted", "this call is nested too deeply"),
+            RuntimeErrorKind::UndefinedFunction => ("E0610", "call of a function without a body", "nothing to run for this call"),
+            RuntimeErrorKind::TypeMismatch => ("E0611", "operation on values of the wrong type", "unexpected value here"),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind.describe().1)?;
+        if let Some(note) = &self.note {
+            write!(f, " ({})", note)?;
+        }
+        if let Some(stack) = stack(&self.trace) {
+            write!(f, ", {}", stack)?;
+        }
+        Ok(())
+    }
+}
+
+// "in `fact` (3 calls deep), called from `main`"-style description of a trace, with runs
+// of a recursive function folded together; None outside of any function
+fn stack(trace: &[Frame]) -> Option<String> {
+    let mut runs: Vec<(&str, usize)> = Vec::new();
+    for frame in trace {
+        match runs.last_mut() {
+            Some((name, n)) if *name == frame.function => *n += 1,
+            _ => runs.push((&frame.function, 1)),
+        }
+    }
+    let mut parts = runs.iter().map(|(name, n)| if *n == 1 { format!("`{}`", name) } else { format!("`{}` ({} calls deep)", name, n) });
+    let innermost = parts.next()?;
+    let callers: Vec<String> = parts.collect();
+    if callers.is_empty() {
+        Some(format!("in {}", innermost))
+    } else {
+        Some(format!("in {}, called from {}", innermost, callers.join(", called from ")))
+    }
+}
+
+// How many call sites of the trace are pointed at in the rendered source
+const SHOWN_CALLS: usize = 8;
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Self {
+        let (code, message, label) = e.kind.describe();
+        let mut d = Diagnostic::error(code, message, e.span).with_label(label);
+        // each call site once, so deep recursion doesn't flood the output
+        let mut shown: Vec<Span> = Vec::new();
+        for frame in &e.trace {
+            if let Some(call) = frame.call
+                && call != e.span
+                && !shown.contains(&call)
+                && shown.len() < SHOWN_CALLS
+            {
+                shown.push(call);
+                d = d.with_secondary(call, format!("`{}` called here", frame.function));
+            }
+        }
+        if let Some(note) = &e.note {
+            d = d.with_note(note.clone());
+        }
+        if let Some(stack) = stack(&e.trace) {
+            d = d.with_note(format!("stack: {}", stack));
+        }
+        if e.kind == RuntimeErrorKind::TypeMismatch {
+            d = d.with_help("run only programs that `check` without errors");
+        }
+        d
+    }
+}
@@ -0,0 +1,409 @@
+// Tree-walking interpreter. Runs a Program that checked without errors: the global
+// declarations and top-level statements in source order, then `main` if the file defines
+// one without parameters. Operations the type checker would have rejected fail with
+// TypeMismatch instead of panicking, so any Program can be handed to it.
+use std::collections::HashMap;
+use crate::interpreter::error::{Frame, RuntimeError, RuntimeErrorKind};
+use crate::interpreter::ops::{binary, cast, declared_value, mismatch, step_into, step_into_mut, unary, Step};
+use crate::interpreter::value::Value;
+use crate::parser::ast::{DeclKind, Expr, ExprKind, Field, Param, Program, Stmt, StmtKind};
+use crate::span::Span;
+use crate::token::TokenKind;
+
+// Deepest nesting of calls before the next one fails with StackOverflow
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+type Scope = HashMap<String, Value>;
+
+// How a statement finished
+enum Exec {
+    Next,
+    Break,
+    Return(Value),
+}
+
+struct Function<'a> {
+    params: &'a [Param],
+    returns_value: bool,
+    body: Option<&'a Stmt>, // None for a prototype that is never defined
+    span: Span,
+}
+
+// Where an assignment stores or a read looks: a variable, or a value computed on the
+// spot (`f().x`), followed by the indexes and fields applied to it (see ops::Step)
+enum Root<'a> {
+    Variable(&'a str),
+    Temporary(Value),
+}
+
+pub struct Interpreter<'a> {
+    program: &'a Program,
+    functions: HashMap<&'a str, Function<'a>>,
+    structs: HashMap<&'a str, &'a [Field]>,
+    globals: Scope,
+    frames: Vec<Vec<Scope>>, // block scopes of each active call; frames[0] is the top-level code
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut functions = HashMap::new();
+        let mut structs = HashMap::new();
+        for decl in &program.declarations {
+            match &decl.kind {
+                DeclKind::Function { name, params, return_type, body } => {
+                    let returns_value = return_type.is_some();
+                    functions.insert(name.as_str(), Function { params, returns_value, body: Some(body), span: decl.span });
+                }
+                // a definition anywhere in the file wins over the prototype
+                DeclKind::Prototype { name, params, return_type } => {
+                    functions.entry(name.as_str()).or_insert(Function { params, returns_value: return_type.is_some(), body: None, span: decl.span });
+                }
+                DeclKind::Struct { name, fields } => {
+                    structs.insert(name.as_str(), fields.as_slice());
+                }
+                DeclKind::GlobalVar { .. } | DeclKind::Stmt(_) => {}
+            }
+        }
+        Interpreter { program, functions, structs, globals: Scope::new(), frames: vec![Vec::new()] }
+    }
+
+    // Runs the program; its result is the value of a top-level `return`, else what `main`
+    // returns, else Void
+    pub fn run(mut self) -> Result<Value, RuntimeError> {
+        let program = self.program;
+        for decl in &program.declarations {
+            match &decl.kind {
+                DeclKind::GlobalVar { name, type_annot, value } => {
+                    let value = match value {
+                        Some(value) => self.eval(value)?,
+                        None => declared_value(type_annot.as_ref()),
+                    };
+                    self.globals.insert(name.clone(), value);
+                }
+                DeclKind::Stmt(stmt) => {
+                    if let Exec::Return(value) = self.exec(stmt)? {
+                        return Ok(value);
+                    }
+                }
+                DeclKind::Function { .. } | DeclKind::Prototype { .. } | DeclKind::Struct { .. } => {}
+            }
+        }
+        match self.functions.get("main") {
+            Some(Function { params: [], body: Some(_), .. }) => self.call("main", Vec::new(), None),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    fn exec(&mut self, stmt: &'a Stmt) -> Result<Exec, RuntimeError> {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            StmtKind::Let { name, type_annot, value } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => declared_value(type_annot.as_ref()),
+                };
+                self.declare(name, value);
+            }
+            StmtKind::Block(stmts) => {
+                self.enter_scope();
+                let result = self.exec_all(stmts);
+                self.exit_scope();
+                return result;
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Void,
+                };
+                return Ok(Exec::Return(value));
+            }
+            StmtKind::Break => return Ok(Exec::Break),
+            StmtKind::If { condition, then_branch, else_branch } => {
+                if self.condition(condition)? {
+                    return self.exec(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.exec(else_branch);
+                }
+            }
+            StmtKind::While { condition, body } => {
+                while self.condition(condition)? {
+                    match self.exec(body)? {
+                        Exec::Next => {}
+                        Exec::Break => break,
+                        Exec::Return(value) => return Ok(Exec::Return(value)),
+                    }
+                }
+            }
+            StmtKind::For { init, condition, increment, body } => {
+                // the loop variable lives in a scope around the whole loop
+                self.enter_scope();
+                let result = self.exec_for(init.as_deref(), condition.as_ref(), increment.as_ref(), body);
+                self.exit_scope();
+                return result;
+            }
+            StmtKind::Function { .. } => {} // nested functions are not callable, see analysis.rs
+            StmtKind::Error => {
+                return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, stmt.span).with_note("this statement did not parse"));
+            }
+        }
+        Ok(Exec::Next)
+    }
+
+    fn exec_all(&mut self, stmts: &'a [Stmt]) -> Result<Exec, RuntimeError> {
+        for stmt in stmts {
+            match self.exec(stmt)? {
+                Exec::Next => {}
+                done => return Ok(done),
+            }
+        }
+        Ok(Exec::Next)
+    }
+
+    fn exec_for(&mut self, init: Option<&'a Stmt>, condition: Option<&'a Expr>, increment: Option<&'a Expr>, body: &'a Stmt) -> Result<Exec, RuntimeError> {
+        if let Some(init) = init {
+            self.exec(init)?;
+        }
+        loop {
+            // a missing condition is always true
+            if let Some(condition) = condition
+                && !self.condition(condition)?
+            {
+                break;
+            }
+            match self.exec(body)? {
+                Exec::Next => {}
+                Exec::Break => break,
+                Exec::Return(value) => return Ok(Exec::Return(value)),
+            }
+            if let Some(increment) = increment {
+                self.eval(increment)?;
+            }
+        }
+        Ok(Exec::Next)
+    }
+
+    fn condition(&mut self, expr: &'a Expr) -> Result<bool, RuntimeError> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(mismatch(expr.span, &other, "a condition must be a `bool`")),
+        }
+    }
+
+    fn eval(&mut self, expr: &'a Expr) -> Result<Value, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.variable(name) {
+                Some(Value::Unassigned) => Err(RuntimeError::new(RuntimeErrorKind::UnassignedValue, expr.span)
+                    .with_note(format!("`{}` is read before it is assigned", name))),
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, expr.span).with_note(format!("`{}` is not a variable", name))),
+            },
+            ExprKind::Integer(n) => Ok(Value::Int(*n)),
+            ExprKind::Float(x) => Ok(Value::Float(*x)),
+            ExprKind::Boolean(b) => Ok(Value::Bool(*b)),
+            ExprKind::StringLit(s) => Ok(Value::Str(s.clone())),
+            ExprKind::Char(c) => Ok(Value::Char(*c)),
+            ExprKind::Array(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.eval(element)?);
+                }
+                Ok(Value::Array(values))
+            }
+            ExprKind::Unary { operator, expr: inner } => {
+                let value = self.eval(inner)?;
+                unary(operator, value, expr.span)
+            }
+            ExprKind::Binary { left, operator: TokenKind::T_ASSIGNOP, right } => self.assign(left, right),
+            // the right operand of && and || runs only when it decides the result
+            ExprKind::Binary { left, operator: operator @ (TokenKind::T_ANDAND | TokenKind::T_OROR), right } => {
+                let short_circuit = *operator == TokenKind::T_OROR;
+                match self.eval(left)? {
+                    Value::Bool(b) if b == short_circuit => Ok(Value::Bool(b)),
+                    Value::Bool(_) => match self.eval(right)? {
+                        Value::Bool(b) => Ok(Value::Bool(b)),
+                        other => Err(mismatch(right.span, &other, "logical operators take `bool` operands")),
+                    },
+                    other => Err(mismatch(left.span, &other, "logical operators take `bool` operands")),
+                }
+            }
+            ExprKind::Binary { left, operator, right } => {
+                let l = self.eval(left)?;
+                let r = self.eval(right)?;
+                binary(operator, l, r, expr.span)
+            }
+            ExprKind::Call { callee, args } => {
+                let ExprKind::Identifier(name) = &callee.kind else {
+                    return Err(RuntimeError::new(RuntimeErrorKind::UndefinedFunction, callee.span).with_note("only functions can be called, by name"));
+                };
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(name, values, Some(expr.span))
+            }
+            ExprKind::Grouping(inner) => self.eval(inner),
+            ExprKind::Index { .. } | ExprKind::Field { .. } => {
+                let (root, steps) = self.place(expr)?;
+                let value = match &root {
+                    Root::Variable(name) => self.variable(name).ok_or_else(|| {
+                        RuntimeError::new(RuntimeErrorKind::TypeMismatch, expr.span).with_note(format!("`{}` is not a variable", name))
+                    })?,
+                    Root::Temporary(value) => value,
+                };
+                let mut value = value;
+                for step in &steps {
+                    value = step_into(value, step)?;
+                }
+                match value {
+                    Value::Unassigned => Err(RuntimeError::new(RuntimeErrorKind::UnassignedValue, expr.span).with_note("this element was never assigned")),
+                    value => Ok(value.clone()),
+                }
+            }
+            ExprKind::StructLit { name, fields } => {
+                let Some(declared) = self.structs.get(name.as_str()).copied() else {
+                    return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, expr.span).with_note(format!("`{}` is not a struct", name)));
+                };
+                // initializers run in source order; the value keeps the declaration's order
+                let mut given = Vec::with_capacity(fields.len());
+                for init in fields {
+                    given.push((init.name.as_str(), self.eval(&init.value)?));
+                }
+                let values = declared
+                    .iter()
+                    .map(|field| {
+                        let value = given.iter().position(|(f, _)| *f == field.name).map(|i| given.swap_remove(i).1);
+                        (field.name.clone(), value.unwrap_or(Value::Unassigned))
+                    })
+                    .collect();
+                Ok(Value::Struct(name.clone(), values))
+            }
+            ExprKind::Cast { target, expr: inner } => {
+                let value = self.eval(inner)?;
+                cast(target, value, expr.span)
+            }
+            ExprKind::Error => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, expr.span).with_note("this expression did not parse")),
+        }
+    }
+
+    // `target = value`: the value is computed first, then stored; the result is the value
+    fn assign(&mut self, target: &'a Expr, value: &'a Expr) -> Result<Value, RuntimeError> {
+        let value = self.eval(value)?;
+        let (root, steps) = self.place(target)?;
+        let mut slot = match root {
+            Root::Variable(name) => self.variable_mut(name).ok_or_else(|| {
+                RuntimeError::new(RuntimeErrorKind::TypeMismatch, target.span).with_note(format!("`{}` is not a variable", name))
+            })?,
+            // storing into a temporary has no effect beyond the checks on the way
+            Root::Temporary(mut temporary) => {
+                let mut slot = &mut temporary;
+                for step in &steps {
+                    slot = step_into_mut(slot, step)?;
+                }
+                return Ok(value);
+            }
+        };
+        for step in &steps {
+            slot = step_into_mut(slot, step)?;
+        }
+        *slot = value.clone();
+        Ok(value)
+    }
+
+    // Splits `xs[i].p` into its root and steps, evaluating the indexes from the inside out
+    fn place(&mut self, expr: &'a Expr) -> Result<(Root<'a>, Vec<Step<'a>>), RuntimeError> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => Ok((Root::Variable(name), Vec::new())),
+            ExprKind::Grouping(inner) => self.place(inner),
+            ExprKind::Index { array, index } => {
+                let (root, mut steps) = self.place(array)?;
+                match self.eval(index)? {
+                    Value::Int(i) => steps.push(Step::Index(i, index.span)),
+                    other => return Err(mismatch(index.span, &other, "an index must be an `int`")),
+                }
+                Ok((root, steps))
+            }
+            ExprKind::Field { object, field } => {
+                let (root, mut steps) = self.place(object)?;
+                steps.push(Step::Field(field, expr.span));
+                Ok((root, steps))
+            }
+            _ => Ok((Root::Temporary(self.eval(expr)?), Vec::new())),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>, call: Option<Span>) -> Result<Value, RuntimeError> {
+        let (params, returns_value, body, span) = match self.functions.get(name) {
+            Some(Function { params, returns_value, body: Some(body), span }) => (*params, *returns_value, *body, *span),
+            Some(Function { span, .. }) => {
+                return Err(RuntimeError::new(RuntimeErrorKind::UndefinedFunction, call.unwrap_or(*span))
+                    .with_note(format!("`{}` is declared by a prototype but never defined", name)));
+            }
+            None => {
+                return Err(RuntimeError::new(RuntimeErrorKind::UndefinedFunction, call.unwrap_or_default())
+                    .with_note(format!("`{}` is not a function", name)));
+            }
+        };
+        let call_span = call.unwrap_or(span);
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(RuntimeErrorKind::StackOverflow, call_span)
+                .with_note(format!("calls can be nested at most {} deep", MAX_CALL_DEPTH)));
+        }
+        if params.len() != args.len() {
+            return Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, call_span)
+                .with_note(format!("`{}` takes {} arguments but was given {}", name, params.len(), args.len())));
+        }
+
+        let scope = params.iter().map(|p| p.name.clone()).zip(args).collect();
+        self.frames.push(vec![scope]);
+        let result = self.exec(body);
+        self.frames.pop();
+
+        let result = match result {
+            Ok(Exec::Return(value)) => Ok(value),
+            Ok(_) if returns_value => Err(RuntimeError::new(RuntimeErrorKind::MissingReturnValue, span)
+                .with_note(format!("`{}` has a return type, so every call must end in a `return` with a value", name))),
+            Ok(_) => Ok(Value::Void),
+            Err(e) => Err(e),
+        };
+        result.map_err(|mut e| {
+            e.trace.push(Frame { function: name.to_string(), call });
+            e
+        })
+    }
+
+    fn enter_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.push(Scope::new());
+        }
+    }
+
+    fn exit_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pop();
+        }
+    }
+
+    // Adds a variable to the innermost scope; outside of any block that is the globals
+    fn declare(&mut self, name: &str, value: Value) {
+        match self.frames.last_mut().and_then(|frame| frame.last_mut()) {
+            Some(scope) => scope.insert(name.to_string(), value),
+            None => self.globals.insert(name.to_string(), value),
+        };
+    }
+
+    // Innermost variable called `name` visible from the running code: the locals of the
+    // current call, then the globals
+    fn variable(&self, name: &str) -> Option<&Value> {
+        let frame = self.frames.last()?;
+        frame.iter().rev().find_map(|scope| scope.get(name)).or_else(|| self.globals.get(name))
+    }
+
+    fn variable_mut(&mut self, name: &str) -> Option<&mut Value> {
+        if let Some(scope) = self.frames.last_mut().and_then(|frame| frame.iter_mut().rev().find(|scope| scope.contains_key(name))) {
+            return scope.get_mut(name);
+        }
+        self.globals.get_mut(name)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod value;
+pub mod error;
+pub mod ops;
+pub mod eval;
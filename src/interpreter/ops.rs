@@ -0,0 +1,193 @@
+// Semantics of the operations themselves, shared by the tree-walking interpreter and the
+// bytecode VM so both give the same results and the same runtime errors.
+use crate::interpreter::error::{RuntimeError, RuntimeErrorKind};
+use crate::interpreter::value::Value;
+use crate::parser::ast::TypeAnnot;
+use crate::span::Span;
+use crate::token::TokenKind;
+
+// One index or field applied to a value on the way to the element a read or store uses
+pub enum Step<'a> {
+    Index(i64, Span),
+    Field(&'a str, Span),
+}
+
+// Value of a variable declared without an initializer: arrays of scalars are filled with
+// zero values, everything else must be assigned before it is read
+pub fn declared_value(type_annot: Option<&TypeAnnot>) -> Value {
+    match type_annot {
+        Some(TypeAnnot::Array(elem, len)) => {
+            let zero = match &**elem {
+                TypeAnnot::Named(TokenKind::T_INT) => Value::Int(0),
+                TypeAnnot::Named(TokenKind::T_FLOAT) => Value::Float(0.0),
+                TypeAnnot::Named(TokenKind::T_BOOL) => Value::Bool(false),
+                TypeAnnot::Named(TokenKind::T_CHAR) => Value::Char('\0'),
+                TypeAnnot::Named(TokenKind::T_STRING) => Value::Str(String::new()),
+                other => declared_value(Some(other)),
+            };
+            Value::Array(vec![zero; *len])
+        }
+        _ => Value::Unassigned,
+    }
+}
+
+pub fn step_into<'v>(value: &'v Value, step: &Step) -> Result<&'v Value, RuntimeError> {
+    match (value, step) {
+        (Value::Array(elements), Step::Index(i, span)) => Ok(&elements[index(elements.len(), *i, *span)?]),
+        (Value::Struct(name, fields), Step::Field(field, span)) => match fields.iter().find(|(f, _)| f == field) {
+            Some((_, value)) => Ok(value),
+            None => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, *span).with_note(format!("struct `{}` has no field `{}`", name, field))),
+        },
+        (value, step) => Err(step_error(value, step)),
+    }
+}
+
+pub fn step_into_mut<'v>(value: &'v mut Value, step: &Step) -> Result<&'v mut Value, RuntimeError> {
+    match (value, step) {
+        (Value::Array(elements), Step::Index(i, span)) => {
+            let i = index(elements.len(), *i, *span)?;
+            Ok(&mut elements[i])
+        }
+        (Value::Struct(name, fields), Step::Field(field, span)) => {
+            let note = format!("struct `{}` has no field `{}`", name, field);
+            match fields.iter_mut().find(|(f, _)| f == field) {
+                Some((_, value)) => Ok(value),
+                None => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, *span).with_note(note)),
+            }
+        }
+        (value, step) => Err(step_error(value, step)),
+    }
+}
+
+pub fn index(len: usize, i: i64, span: Span) -> Result<usize, RuntimeError> {
+    match usize::try_from(i) {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(RuntimeError::new(RuntimeErrorKind::IndexOutOfBounds, span)
+            .with_note(format!("the array has {} elements but the index is {}", len, i))),
+    }
+}
+
+// Indexing something that is not an array, or taking a field of something that is not a struct
+fn step_error(value: &Value, step: &Step) -> RuntimeError {
+    let span = match step {
+        Step::Index(_, span) | Step::Field(_, span) => *span,
+    };
+    match value {
+        Value::Unassigned => RuntimeError::new(RuntimeErrorKind::UnassignedValue, span).with_note("the value this reads from was never assigned"),
+        Value::Array(_) => mismatch(span, value, "arrays have no fields"),
+        _ => mismatch(span, value, "only arrays can be indexed and only structs have fields"),
+    }
+}
+
+pub fn unary(operator: &TokenKind, value: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (operator, value) {
+        (TokenKind::T_MINUS, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or_else(|| overflow(span)),
+        (TokenKind::T_MINUS, Value::Float(x)) => Ok(Value::Float(-x)),
+        (TokenKind::T_NOT, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (TokenKind::T_TILDE, Value::Int(n)) => Ok(Value::Int(!n)),
+        (_, value) => Err(mismatch(span, &value, "no such unary operation")),
+    }
+}
+
+pub fn binary(operator: &TokenKind, l: Value, r: Value, span: Span) -> Result<Value, RuntimeError> {
+    match operator {
+        TokenKind::T_PLUS | TokenKind::T_MINUS | TokenKind::T_STAR | TokenKind::T_SLASH | TokenKind::T_PERCENT | TokenKind::T_CARET => {
+            match (l, r) {
+                (Value::Int(a), Value::Int(b)) => int_arithmetic(operator, a, b, span),
+                // an int meeting a float is widened
+                (Value::Float(a), Value::Float(b)) => Ok(float_arithmetic(operator, a, b)),
+                (Value::Int(a), Value::Float(b)) => Ok(float_arithmetic(operator, a as f64, b)),
+                (Value::Float(a), Value::Int(b)) => Ok(float_arithmetic(operator, a, b as f64)),
+                (l, _) => Err(mismatch(span, &l, "arithmetic takes `int` and `float` operands")),
+            }
+        }
+        TokenKind::T_AMP | TokenKind::T_PIPE | TokenKind::T_LSHIFT | TokenKind::T_RSHIFT => {
+            let (Value::Int(a), Value::Int(b)) = (&l, &r) else {
+                return Err(mismatch(span, &l, "bitwise operators and shifts take `int` operands"));
+            };
+            let (a, b) = (*a, *b);
+            match operator {
+                TokenKind::T_AMP => Ok(Value::Int(a & b)),
+                TokenKind::T_PIPE => Ok(Value::Int(a | b)),
+                _ if !(0..64).contains(&b) => Err(RuntimeError::new(RuntimeErrorKind::InvalidShift, span).with_note(format!("the shift amount is {}", b))),
+                TokenKind::T_LSHIFT => Ok(Value::Int(a << b)),
+                _ => Ok(Value::Int(a >> b)),
+            }
+        }
+        TokenKind::T_EQUALSOP => Ok(Value::Bool(l == r)),
+        TokenKind::T_NEQ => Ok(Value::Bool(l != r)),
+        TokenKind::T_LT | TokenKind::T_GT | TokenKind::T_LTE | TokenKind::T_GTE => {
+            // NaN compares false with everything
+            let Some(ordering) = l.compare(&r) else {
+                return match (&l, &r) {
+                    (Value::Float(_) | Value::Int(_), Value::Float(_) | Value::Int(_)) => Ok(Value::Bool(false)),
+                    _ => Err(mismatch(span, &l, "only numbers, chars, strings, bools and arrays of them are ordered")),
+                };
+            };
+            Ok(Value::Bool(match operator {
+                TokenKind::T_LT => ordering.is_lt(),
+                TokenKind::T_GT => ordering.is_gt(),
+                TokenKind::T_LTE => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        _ => Err(RuntimeError::new(RuntimeErrorKind::TypeMismatch, span).with_note("not a binary operator")),
+    }
+}
+
+fn int_arithmetic(operator: &TokenKind, a: i64, b: i64, span: Span) -> Result<Value, RuntimeError> {
+    if matches!(operator, TokenKind::T_SLASH | TokenKind::T_PERCENT) && b == 0 {
+        return Err(RuntimeError::new(RuntimeErrorKind::DivisionByZero, span));
+    }
+    let result = match operator {
+        TokenKind::T_PLUS => a.checked_add(b),
+        TokenKind::T_MINUS => a.checked_sub(b),
+        TokenKind::T_STAR => a.checked_mul(b),
+        TokenKind::T_SLASH => a.checked_div(b),
+        TokenKind::T_PERCENT => a.checked_rem(b),
+        _ => {
+            let Ok(exponent) = u32::try_from(b) else {
+                return Err(RuntimeError::new(RuntimeErrorKind::NegativeExponent, span).with_note(format!("the exponent is {}", b)));
+            };
+            a.checked_pow(exponent)
+        }
+    };
+    result.map(Value::Int).ok_or_else(|| overflow(span))
+}
+
+fn float_arithmetic(operator: &TokenKind, a: f64, b: f64) -> Value {
+    Value::Float(match operator {
+        TokenKind::T_PLUS => a + b,
+        TokenKind::T_MINUS => a - b,
+        TokenKind::T_STAR => a * b,
+        TokenKind::T_SLASH => a / b,
+        TokenKind::T_PERCENT => a % b,
+        _ => a.powf(b),
+    })
+}
+
+// `int(c)` and `char(n)` go through the code point; chars and ints can also become strings
+pub fn cast(target: &TokenKind, value: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (target, value) {
+        (TokenKind::T_INT, Value::Char(c)) => Ok(Value::Int(c as i64)),
+        (TokenKind::T_CHAR, Value::Int(n)) => u32::try_from(n).ok().and_then(char::from_u32).map(Value::Char).ok_or_else(|| {
+            RuntimeError::new(RuntimeErrorKind::InvalidConversion, span).with_note(format!("{} is not the code of a character", n))
+        }),
+        (TokenKind::T_STRING, Value::Char(c)) => Ok(Value::Str(c.to_string())),
+        (TokenKind::T_STRING, Value::Int(n)) => Ok(Value::Str(n.to_string())),
+        (TokenKind::T_INT, v @ Value::Int(_))
+        | (TokenKind::T_CHAR, v @ Value::Char(_))
+        | (TokenKind::T_STRING, v @ Value::Str(_))
+        | (TokenKind::T_FLOAT, v @ Value::Float(_))
+        | (TokenKind::T_BOOL, v @ Value::Bool(_)) => Ok(v),
+        (_, value) => Err(mismatch(span, &value, "no such conversion")),
+    }
+}
+
+fn overflow(span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::IntegerOverflow, span)
+}
+
+pub fn mismatch(span: Span, found: &Value, note: &str) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::TypeMismatch, span).with_note(format!("{} (found {})", note, found))
+}
@@ -0,0 +1,190 @@
+// Control-flow graph of a function: the edges between its blocks, as lists of
+// predecessors and successors, and the reachable blocks in reverse postorder (every block
+// before its successors, loops aside), the order forward dataflow wants. Only reachable
+// blocks count: edges out of a block nothing jumps to are left out.
+use crate::ir::tac::{BasicBlock, BlockId, Function, InstKind, Terminator};
+
+pub struct Cfg {
+    preds: Vec<Vec<BlockId>>,
+    succs: Vec<Vec<BlockId>>,
+    rpo: Vec<BlockId>,
+    reachable: Vec<bool>,
+}
+
+impl Cfg {
+    pub fn new(function: &Function) -> Self {
+        let n = function.blocks.len();
+        let mut cfg = Cfg { preds: vec![Vec::new(); n], succs: vec![Vec::new(); n], rpo: Vec::new(), reachable: vec![false; n] };
+        if n == 0 {
+            return cfg;
+        }
+        // depth-first from the entry; a block is finished once all its successors are
+        let mut postorder = Vec::with_capacity(n);
+        let mut stack = vec![(BlockId(0), 0)];
+        cfg.reachable[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let succs = function.block(block).terminator.successors();
+            match succs.get(next) {
+                Some(&succ) => {
+                    stack.push((block, next + 1));
+                    if !cfg.reachable[succ.0 as usize] {
+                        cfg.reachable[succ.0 as usize] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        for &block in &postorder {
+            for succ in function.block(block).terminator.successors() {
+                // `branch c, bb1, bb1` is one edge
+                if !cfg.succs[block.0 as usize].contains(&succ) {
+                    cfg.succs[block.0 as usize].push(succ);
+                    cfg.preds[succ.0 as usize].push(block);
+                }
+            }
+        }
+        // predecessors in block order, so phis list them the same way every time
+        for preds in &mut cfg.preds {
+            preds.sort();
+        }
+        postorder.reverse();
+        cfg.rpo = postorder;
+        cfg
+    }
+
+    pub fn preds(&self, block: BlockId) -> &[BlockId] {
+        &self.preds[block.0 as usize]
+    }
+
+    pub fn succs(&self, block: BlockId) -> &[BlockId] {
+        &self.succs[block.0 as usize]
+    }
+
+    pub fn reverse_postorder(&self) -> &[BlockId] {
+        &self.rpo
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.reachable[block.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.preds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.preds.is_empty()
+    }
+}
+
+// Drops the blocks control never reaches and renumbers the rest, keeping their order;
+// phis forget the predecessors that are gone. Returns whether anything was removed.
+pub fn remove_unreachable(function: &mut Function) -> bool {
+    let cfg = Cfg::new(function);
+    if function.block_ids().all(|b| cfg.is_reachable(b)) {
+        return false;
+    }
+    let mut renumbered = Vec::with_capacity(function.blocks.len());
+    let mut next = 0;
+    for block in function.block_ids() {
+        renumbered.push(BlockId(next));
+        if cfg.is_reachable(block) {
+            next += 1;
+        }
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks.into_iter().enumerate().filter(|(i, _)| cfg.reachable[*i]).map(|(_, block)| block).collect();
+    for block in &mut function.blocks {
+        retarget(block, |target| renumbered[target.0 as usize]);
+        for inst in &mut block.insts {
+            if let InstKind::Phi { args, .. } = &mut inst.kind {
+                args.retain(|(pred, _)| cfg.is_reachable(*pred));
+                for (pred, _) in args {
+                    *pred = renumbered[pred.0 as usize];
+                }
+            }
+        }
+    }
+    true
+}
+
+// Puts an empty block on every edge from a block with several successors to one with
+// several predecessors, so code that belongs to just that edge (the copies that replace
+// phis) has somewhere to go. Phis in the target are updated to name the new block.
+// Returns whether any edge was split.
+pub fn split_critical_edges(function: &mut Function) -> bool {
+    let cfg = Cfg::new(function);
+    let mut split = false;
+    for block in function.block_ids() {
+        let succs = cfg.succs(block);
+        if succs.len() < 2 {
+            continue;
+        }
+        for &succ in succs {
+            if cfg.preds(succ).len() < 2 {
+                continue;
+            }
+            let middle = BlockId(function.blocks.len() as u32);
+            function.blocks.push(BasicBlock { insts: Vec::new(), terminator: Terminator::Jump(succ) });
+            retarget(function.block_mut(block), |target| if target == succ { middle } else { target });
+            for inst in &mut function.block_mut(succ).insts {
+                inst.kind.rename_phi_pred(block, middle);
+            }
+            split = true;
+        }
+    }
+    split
+}
+
+// Appends each block that is only entered by a jump from one other block to that block,
+// so a chain of blocks that always run one after the other becomes one. The phis of a
+// block with one predecessor can only take its value, and become copies. Returns whether
+// any blocks were merged.
+pub fn merge_blocks(function: &mut Function) -> bool {
+    let cfg = Cfg::new(function);
+    let mut merged = vec![false; function.blocks.len()];
+    for block in function.block_ids() {
+        if !cfg.is_reachable(block) || merged[block.0 as usize] {
+            continue;
+        }
+        while let Terminator::Jump(next) = function.block(block).terminator
+            && next != block
+            && next != BlockId(0)
+            && cfg.preds(next).len() == 1
+        {
+            let BasicBlock { insts, terminator } =
+                std::mem::replace(function.block_mut(next), BasicBlock { insts: Vec::new(), terminator: Terminator::Unreachable });
+            let insts = insts.into_iter().map(|mut inst| {
+                if let InstKind::Phi { dest, args } = &mut inst.kind {
+                    let (_, src) = args.pop().expect("a phi has a value for each predecessor");
+                    inst.kind = InstKind::Copy { dest: *dest, src };
+                }
+                inst
+            });
+            for succ in terminator.successors() {
+                for inst in &mut function.block_mut(succ).insts {
+                    inst.kind.rename_phi_pred(next, block);
+                }
+            }
+            let body = function.block_mut(block);
+            body.insts.extend(insts);
+            body.terminator = terminator;
+            merged[next.0 as usize] = true;
+        }
+    }
+    // the merged blocks are left with nothing jumping to them
+    remove_unreachable(function)
+}
+
+// Rewrites where the block's terminator jumps to
+pub fn retarget(block: &mut BasicBlock, mut to: impl FnMut(BlockId) -> BlockId) {
+    match &mut block.terminator {
+        Terminator::Jump(target) => *target = to(*target),
+        Terminator::Branch { then_block, else_block, .. } => {
+            *then_block = to(*then_block);
+            *else_block = to(*else_block);
+        }
+        Terminator::Return(_) | Terminator::Unreachable => {}
+    }
+}
@@ -0,0 +1,153 @@
+// Dominance over a Cfg: block A dominates block B when every path from the entry to B
+// goes through A. Computed once per CFG with the iterative algorithm of Cooper, Harvey
+// and Kennedy ("A Simple, Fast Dominance Algorithm"), then queried: the immediate
+// dominator and dominator-tree children of a block, its dominance frontier (the blocks
+// where its dominance ends, which is where phis for what it defines go), and whether one
+// block dominates another, in constant time from a numbering of the tree.
+use crate::ir::cfg::Cfg;
+use crate::ir::tac::BlockId;
+
+pub struct Dominators {
+    idom: Vec<Option<BlockId>>, // None for the entry and for unreachable blocks
+    children: Vec<Vec<BlockId>>,
+    frontiers: Vec<Vec<BlockId>>,
+    // position of each block in a depth-first walk of the tree, on entry and on exit: A
+    // dominates B exactly when B's interval is inside A's. usize::MAX when unreachable.
+    enter: Vec<usize>,
+    exit: Vec<usize>,
+    preorder: Vec<BlockId>,
+}
+
+impl Dominators {
+    pub fn new(cfg: &Cfg) -> Self {
+        let n = cfg.len();
+        let rpo = cfg.reverse_postorder();
+        let mut position = vec![usize::MAX; n];
+        for (i, block) in rpo.iter().enumerate() {
+            position[block.0 as usize] = i;
+        }
+
+        // idom of each block, as its position in reverse postorder
+        let mut idom: Vec<Option<usize>> = vec![None; n];
+        if let Some(&entry) = rpo.first() {
+            idom[entry.0 as usize] = Some(0);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in cfg.preds(block) {
+                    if idom[pred.0 as usize].is_none() {
+                        continue; // not processed yet
+                    }
+                    let pred = position[pred.0 as usize];
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, rpo, pred, other),
+                    });
+                }
+                if new_idom.is_some() && idom[block.0 as usize] != new_idom {
+                    idom[block.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let idom: Vec<Option<BlockId>> = (0..n)
+            .map(|b| match idom[b] {
+                Some(_) if position[b] == 0 => None,
+                Some(i) => Some(rpo[i]),
+                None => None,
+            })
+            .collect();
+
+        let mut children = vec![Vec::new(); n];
+        for block in (0..n as u32).map(BlockId) {
+            if let Some(parent) = idom[block.0 as usize] {
+                children[parent.0 as usize].push(block);
+            }
+        }
+
+        let mut frontiers: Vec<Vec<BlockId>> = vec![Vec::new(); n];
+        for (i, &block) in rpo.iter().enumerate() {
+            // the entry is also entered from outside, which makes it a join when it has any
+            let preds = cfg.preds(block);
+            if preds.len() < 2 && (i > 0 || preds.is_empty()) {
+                continue;
+            }
+            for &pred in preds {
+                let mut runner = Some(pred);
+                while let Some(r) = runner
+                    && Some(r) != idom[block.0 as usize]
+                {
+                    if !frontiers[r.0 as usize].contains(&block) {
+                        frontiers[r.0 as usize].push(block);
+                    }
+                    runner = idom[r.0 as usize];
+                }
+            }
+        }
+
+        let mut dominators = Dominators { idom, children, frontiers, enter: vec![usize::MAX; n], exit: vec![usize::MAX; n], preorder: Vec::with_capacity(rpo.len()) };
+        if let Some(&entry) = rpo.first() {
+            let mut clock = 0;
+            let mut stack = vec![(entry, 0)];
+            while let Some((block, next)) = stack.pop() {
+                if next == 0 {
+                    dominators.enter[block.0 as usize] = clock;
+                    dominators.preorder.push(block);
+                    clock += 1;
+                }
+                match dominators.children[block.0 as usize].get(next) {
+                    Some(&child) => {
+                        stack.push((block, next + 1));
+                        stack.push((child, 0));
+                    }
+                    None => {
+                        dominators.exit[block.0 as usize] = clock;
+                        clock += 1;
+                    }
+                }
+            }
+        }
+        dominators
+    }
+
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0 as usize]
+    }
+
+    // The blocks `block` immediately dominates, in block order
+    pub fn children(&self, block: BlockId) -> &[BlockId] {
+        &self.children[block.0 as usize]
+    }
+
+    pub fn frontier(&self, block: BlockId) -> &[BlockId] {
+        &self.frontiers[block.0 as usize]
+    }
+
+    // Whether every path to `b` goes through `a`; a block dominates itself
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        let (a, b) = (a.0 as usize, b.0 as usize);
+        self.enter[b] != usize::MAX && self.enter[a] <= self.enter[b] && self.exit[b] <= self.exit[a]
+    }
+
+    // The reachable blocks, each before the blocks it dominates
+    pub fn preorder(&self) -> &[BlockId] {
+        &self.preorder
+    }
+}
+
+// Nearest common dominator of two blocks, given as positions in reverse postorder
+fn intersect(idom: &[Option<usize>], rpo: &[BlockId], mut a: usize, mut b: usize) -> usize {
+    let parent = |i: usize| idom[rpo[i].0 as usize].expect("processed blocks have an idom");
+    while a != b {
+        while a > b {
+            a = parent(a);
+        }
+        while b > a {
+            b = parent(b);
+        }
+    }
+    a
+}
@@ -0,0 +1,114 @@
+// Textual form of the IR for `--emit=ir`. Globals come first, then each function and the
+// top-level code, one block per label. Every definition shows the type of what it
+// defines, and nothing depends on source positions, so the text only changes when the
+// IR does. For example:
+//
+//   global calls: int
+//
+//   fn fib(n: int): int {
+//   bb0:
+//       t1: bool = n < 2
+//       branch t1, bb1, bb2
+//   bb1:
+//       return n
+//   ...
+//   }
+use crate::ir::tac::{Access, BasicBlock, Function, InstKind, Module, Operand, Terminator, Var};
+
+pub fn dump_module(module: &Module) -> String {
+    let mut out = String::new();
+    for global in &module.globals {
+        out.push_str(&format!("global {}: {}\n", global.name, global.ty));
+    }
+    for function in module.functions.iter().chain(std::iter::once(&module.script)) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&dump_function(module, function));
+    }
+    out
+}
+
+pub fn dump_function(module: &Module, function: &Function) -> String {
+    let mut out = if std::ptr::eq(function, &module.script) {
+        function.name.clone()
+    } else {
+        let params: Vec<String> = function.params.iter().map(|&p| format!("{}: {}", var(function, p), function.var(p).ty)).collect();
+        format!("fn {}({}): {}", function.name, params.join(", "), function.return_type)
+    };
+    if function.blocks.is_empty() {
+        out.push_str(";\n");
+        return out;
+    }
+    out.push_str(" {\n");
+    for (id, block) in function.block_ids().zip(&function.blocks) {
+        out.push_str(&format!("bb{}:\n", id.0));
+        block_text(&mut out, module, function, block);
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn block_text(out: &mut String, module: &Module, function: &Function, block: &BasicBlock) {
+    let op = |operand: &Operand| operand_text(function, operand);
+    let ops = |operands: &[Operand]| operands.iter().map(op).collect::<Vec<_>>().join(", ");
+    for inst in &block.insts {
+        let value = match &inst.kind {
+            InstKind::Copy { src, .. } => op(src),
+            InstKind::Unary { op: unary, operand, .. } => format!("{}{}", unary.symbol(), op(operand)),
+            InstKind::Binary { op: binary, left, right, .. } => format!("{} {} {}", op(left), binary.symbol(), op(right)),
+            InstKind::Cast { dest, operand } => format!("{}({})", function.var(*dest).ty, op(operand)),
+            InstKind::Call { function: callee, args, .. } => format!("call {}({})", module.function(*callee).name, ops(args)),
+            InstKind::Extract { aggregate, access, .. } => format!("{}{}", op(aggregate), access_text(function, access)),
+            InstKind::Insert { aggregate, access, value, .. } => {
+                format!("{} with {} = {}", op(aggregate), access_text(function, access), op(value))
+            }
+            InstKind::Array { elements, .. } => format!("[{}]", ops(elements)),
+            InstKind::Struct { name, fields, .. } => {
+                let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{}: {}", field, op(value))).collect();
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+            InstKind::GetGlobal { global, .. } => format!("@{}", module.globals[global.0 as usize].name),
+            InstKind::Phi { args, .. } => {
+                let args: Vec<String> = args.iter().map(|(pred, value)| format!("bb{}: {}", pred.0, op(value))).collect();
+                format!("phi [{}]", args.join(", "))
+            }
+            InstKind::SetGlobal { global, value } => {
+                out.push_str(&format!("    @{} = {}\n", module.globals[global.0 as usize].name, op(value)));
+                continue;
+            }
+        };
+        match inst.kind.dest() {
+            Some(dest) => out.push_str(&format!("    {}: {} = {}\n", var(function, dest), function.var(dest).ty, value)),
+            None => out.push_str(&format!("    {}\n", value)),
+        }
+    }
+    let terminator = match &block.terminator {
+        Terminator::Jump(target) => format!("jump bb{}", target.0),
+        Terminator::Branch { condition, then_block, else_block } => {
+            format!("branch {}, bb{}, bb{}", op(condition), then_block.0, else_block.0)
+        }
+        Terminator::Return(Some(value)) => format!("return {}", op(value)),
+        Terminator::Return(None) => "return".to_string(),
+        Terminator::Unreachable => "unreachable".to_string(),
+    };
+    out.push_str(&format!("    {}\n", terminator));
+}
+
+fn var(function: &Function, var: Var) -> &str {
+    &function.var(var).name
+}
+
+fn operand_text(function: &Function, operand: &Operand) -> String {
+    match operand {
+        Operand::Var(v) => var(function, *v).to_string(),
+        Operand::Const(value) => value.to_string(),
+    }
+}
+
+fn access_text(function: &Function, access: &Access) -> String {
+    match access {
+        Access::Index(index) => format!("[{}]", operand_text(function, index)),
+        Access::Field(field) => format!(".{}", field),
+    }
+}
@@ -0,0 +1,672 @@
+// Lowers a checked Program to three-address code. Names are resolved here, as in the
+// bytecode compiler: every local becomes its own Var (a shadowing `x` gets `x.1`), globals
+// and functions become ids. Control flow becomes blocks and jumps: `for` is lowered as
+// the `while` it stands for, `{ init; while (cond) { body; incr; } }`, and `&&`, `||`
+// and `!` in conditions become branches, so a condition never computes a bool it only
+// branches on. The types of temporaries come from the checker's TypeTable; the program
+// must have checked without errors, anything the checker rejects panics here.
+use std::collections::{HashMap, HashSet};
+use crate::interpreter::ops::declared_value;
+use crate::interpreter::value::Value;
+use crate::ir::tac::{
+    Access, BasicBlock, BinaryOp, BlockId, Function, FunctionId, Global, GlobalId, Inst, InstKind, Module, Operand,
+    Terminator, UnaryOp, Var, VarInfo, VarKind,
+};
+use crate::parser::ast::{DeclKind, Expr, ExprKind, Field, Program, Stmt, StmtKind, TypeAnnot};
+use crate::semantics::analysis::annot_to_type;
+use crate::semantics::scope::scope::Type;
+use crate::semantics::typechecker::TypeTable;
+use crate::span::Span;
+use crate::token::TokenKind;
+
+pub fn lower(program: &Program, types: &TypeTable) -> Module {
+    let mut lowering = Lowering::new(program, types);
+    for decl in &program.declarations {
+        if let DeclKind::Function { name, params, return_type, body } = &decl.kind {
+            lowering.begin_function(name, params.iter().map(|p| (p.name.as_str(), annot_to_type(&p.param_type))).collect(),
+                                    return_type.as_ref().map_or(Type::Void, annot_to_type), decl.span);
+            lowering.stmt(body);
+            // the checker has made sure a function with a return type never gets here
+            let end = if lowering.function.return_type == Type::Void { Terminator::Return(None) } else { Terminator::Unreachable };
+            lowering.terminate(end);
+            // as in the interpreter, a later definition of the same name wins
+            let id = lowering.function_ids[name.as_str()];
+            lowering.module.functions[id.0 as usize] = lowering.end_function();
+        }
+    }
+
+    lowering.begin_function("<top level>", Vec::new(), Type::Unknown, Span::default());
+    lowering.scopes.clear(); // outside of any block, declarations are globals
+    for decl in &program.declarations {
+        match &decl.kind {
+            DeclKind::GlobalVar { name, type_annot, value } => {
+                let value = lowering.initializer(value.as_ref(), type_annot.as_ref());
+                let global = lowering.global_ids[name.as_str()];
+                lowering.emit(InstKind::SetGlobal { global, value }, decl.span);
+            }
+            DeclKind::Stmt(stmt) => lowering.stmt(stmt),
+            DeclKind::Function { .. } | DeclKind::Prototype { .. } | DeclKind::Struct { .. } => {}
+        }
+    }
+    let end = program.declarations.last().map_or_else(Span::default, |d| d.span);
+    let main = lowering.function_ids.get("main").copied().filter(|&id| {
+        let main = lowering.module.function(id);
+        main.params.is_empty() && !main.blocks.is_empty()
+    });
+    let result = main.and_then(|id| lowering.call(id, Vec::new(), lowering.module.function(id).return_type.clone(), end));
+    lowering.terminate(Terminator::Return(result));
+    lowering.module.script = lowering.end_function();
+    lowering.module
+}
+
+struct Lowering<'a> {
+    types: &'a TypeTable,
+    module: Module,
+    function_ids: HashMap<&'a str, FunctionId>,
+    global_ids: HashMap<&'a str, GlobalId>,
+    structs: HashMap<&'a str, &'a [Field]>,
+    // the function being lowered
+    function: Function,
+    names: HashSet<String>,          // names of its Vars so far
+    current: Option<BlockId>,        // None after a terminator, until the next block starts
+    scopes: Vec<Vec<(&'a str, Var)>>, // names declared in each open block
+    loops: Vec<BlockId>,             // where a `break` in each enclosing loop goes
+    entered: Vec<BlockId>,           // blocks in the order code started going into them
+}
+
+impl<'a> Lowering<'a> {
+    // Numbers every function and global and records their types before anything is
+    // lowered, so code can refer to ones declared further down
+    fn new(program: &'a Program, types: &'a TypeTable) -> Self {
+        let empty = |name: &str, span| Function { name: name.to_string(), params: Vec::new(), return_type: Type::Void, vars: Vec::new(), blocks: Vec::new(), span };
+        let mut lowering = Lowering {
+            types,
+            module: Module { functions: Vec::new(), script: empty("<top level>", Span::default()), globals: Vec::new() },
+            function_ids: HashMap::new(),
+            global_ids: HashMap::new(),
+            structs: HashMap::new(),
+            function: empty("", Span::default()),
+            names: HashSet::new(),
+            current: None,
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            entered: Vec::new(),
+        };
+        for decl in &program.declarations {
+            match &decl.kind {
+                DeclKind::Function { name, params, return_type, .. } | DeclKind::Prototype { name, params, return_type } => {
+                    let declared = lowering.function_ids.contains_key(name.as_str());
+                    let id = *lowering.function_ids.entry(name).or_insert_with(|| {
+                        lowering.module.functions.push(empty(name, decl.span));
+                        FunctionId(lowering.module.functions.len() as u32 - 1)
+                    });
+                    // the signature is the definition's; a prototype that is never defined
+                    // keeps its own, with no blocks
+                    if !declared || matches!(decl.kind, DeclKind::Function { .. }) {
+                        let function = &mut lowering.module.functions[id.0 as usize];
+                        function.vars = params
+                            .iter()
+                            .map(|p| VarInfo { name: p.name.clone(), ty: annot_to_type(&p.param_type), kind: VarKind::Param })
+                            .collect();
+                        function.params = (0..params.len() as u32).map(Var).collect();
+                        function.return_type = return_type.as_ref().map_or(Type::Void, annot_to_type);
+                    }
+                }
+                DeclKind::Struct { name, fields } => {
+                    lowering.structs.insert(name, fields);
+                }
+                DeclKind::GlobalVar { name, type_annot, value } | DeclKind::Stmt(Stmt { kind: StmtKind::Let { name, type_annot, value }, .. }) => {
+                    if !lowering.global_ids.contains_key(name.as_str()) {
+                        let ty = match (type_annot, value) {
+                            (Some(annot), _) => annot_to_type(annot),
+                            (None, Some(value)) => lowering.type_of(value),
+                            (None, None) => Type::Unknown,
+                        };
+                        lowering.global_ids.insert(name, GlobalId(lowering.module.globals.len() as u32));
+                        lowering.module.globals.push(Global { name: name.clone(), ty });
+                    }
+                }
+                DeclKind::Stmt(_) => {}
+            }
+        }
+        lowering
+    }
+
+    fn begin_function(&mut self, name: &str, params: Vec<(&'a str, Type)>, return_type: Type, span: Span) {
+        self.function = Function { name: name.to_string(), params: Vec::new(), return_type, vars: Vec::new(), blocks: Vec::new(), span };
+        self.names.clear();
+        self.loops.clear();
+        self.entered.clear();
+        let mut scope = Vec::new();
+        for (name, ty) in params {
+            let var = self.declare(name, ty, VarKind::Param);
+            self.function.params.push(var);
+            scope.push((name, var));
+        }
+        self.scopes = vec![scope];
+        let entry = self.new_block();
+        self.switch_to(entry);
+    }
+
+    // Blocks are numbered as they are made, which is often before their code is lowered
+    // (the exit of a loop is made before its body). The finished function has them in the
+    // order their code was lowered instead, which follows the source.
+    fn end_function(&mut self) -> Function {
+        self.current = None;
+        let mut function = std::mem::replace(&mut self.function, Function {
+            name: String::new(), params: Vec::new(), return_type: Type::Void, vars: Vec::new(), blocks: Vec::new(), span: Span::default(),
+        });
+        let mut order = std::mem::take(&mut self.entered);
+        order.extend(function.block_ids().filter(|id| !order.contains(id)).collect::<Vec<_>>());
+        let mut renumbered = vec![BlockId(0); order.len()];
+        for (new, old) in order.iter().enumerate() {
+            renumbered[old.0 as usize] = BlockId(new as u32);
+        }
+        let mut blocks: Vec<Option<BasicBlock>> = function.blocks.into_iter().map(Some).collect();
+        function.blocks = order.iter().map(|old| blocks[old.0 as usize].take().expect("each block once")).collect();
+        for block in &mut function.blocks {
+            match &mut block.terminator {
+                Terminator::Jump(target) => *target = renumbered[target.0 as usize],
+                Terminator::Branch { then_block, else_block, .. } => {
+                    *then_block = renumbered[then_block.0 as usize];
+                    *else_block = renumbered[else_block.0 as usize];
+                }
+                Terminator::Return(_) | Terminator::Unreachable => {}
+            }
+        }
+        function
+    }
+
+    fn type_of(&self, expr: &Expr) -> Type {
+        self.types.get(&expr.span).cloned().unwrap_or(Type::Unknown)
+    }
+
+    // A new Var named after `name`, or after it with a number if that is taken
+    fn declare(&mut self, name: &str, ty: Type, kind: VarKind) -> Var {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.names.contains(&unique) {
+            unique = format!("{}.{}", name, n);
+            n += 1;
+        }
+        self.names.insert(unique.clone());
+        self.function.vars.push(VarInfo { name: unique, ty, kind });
+        Var(self.function.vars.len() as u32 - 1)
+    }
+
+    fn temp(&mut self, ty: Type) -> Var {
+        let mut n = self.function.vars.len();
+        while self.names.contains(&format!("t{}", n)) {
+            n += 1;
+        }
+        self.declare(&format!("t{}", n), ty, VarKind::Temp)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(BasicBlock { insts: Vec::new(), terminator: Terminator::Unreachable });
+        BlockId(self.function.blocks.len() as u32 - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = Some(block);
+        self.entered.push(block);
+    }
+
+    // Code after a `return` or `break` still gets lowered, into a block nothing jumps to
+    fn emit(&mut self, kind: InstKind, span: Span) {
+        let block = match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.switch_to(block);
+                block
+            }
+        };
+        self.function.block_mut(block).insts.push(Inst::new(kind, span));
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some(block) = self.current.take() {
+            self.function.block_mut(block).terminator = terminator;
+        }
+    }
+
+    // `var = value`. When the value is in the temporary the last instruction made, that
+    // instruction computes it straight into the variable instead. Returns what now holds
+    // the value.
+    fn define(&mut self, var: Var, value: Operand, span: Span) -> Operand {
+        if let Operand::Var(temp) = value
+            && temp.0 as usize + 1 == self.function.vars.len()
+            && self.function.var(temp).kind == VarKind::Temp
+            && let Some(block) = self.current
+            && let Some(last) = self.function.block_mut(block).insts.last_mut()
+            && let Some(dest) = last.kind.dest_mut()
+            && *dest == temp
+        {
+            *dest = var;
+            let unused = self.function.vars.pop().expect("the temporary");
+            self.names.remove(&unused.name);
+        } else {
+            self.emit(InstKind::Copy { dest: var, src: value }, span);
+        }
+        Operand::Var(var)
+    }
+
+    // The initial value of a variable
+    fn initializer(&mut self, value: Option<&'a Expr>, type_annot: Option<&TypeAnnot>) -> Operand {
+        match value {
+            Some(value) => self.expr(value),
+            None => Operand::Const(declared_value(type_annot)),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<Result<Var, GlobalId>> {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, var)) = scope.iter().rev().find(|(n, _)| *n == name) {
+                return Some(Ok(*var));
+            }
+        }
+        self.global_ids.get(name).map(|&id| Err(id))
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Expr(expr) => match &strip(expr).kind {
+                // the result of a call made for its effect is not kept
+                ExprKind::Call { callee, args } => {
+                    let (id, args) = self.call_parts(callee, args);
+                    self.call(id, args, Type::Void, expr.span);
+                }
+                _ => {
+                    self.expr(expr);
+                }
+            },
+            StmtKind::Let { name, type_annot, value } => {
+                if self.scopes.is_empty() {
+                    let value = self.initializer(value.as_ref(), type_annot.as_ref());
+                    let global = self.global_ids[name.as_str()];
+                    self.emit(InstKind::SetGlobal { global, value }, span);
+                    return;
+                }
+                let ty = match (type_annot, value) {
+                    (Some(annot), _) => annot_to_type(annot),
+                    (None, Some(value)) => self.type_of(value),
+                    (None, None) => Type::Unknown,
+                };
+                // made before the initializer's temporaries so it can take over the last
+                // one, but only in scope after it: the initializer still sees an outer
+                // variable of the same name
+                let var = self.declare(name, ty, VarKind::Local);
+                let value = self.initializer(value.as_ref(), type_annot.as_ref());
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push((name, var));
+                }
+                self.define(var, value, span);
+            }
+            StmtKind::Block(stmts) => {
+                self.scopes.push(Vec::new());
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.expr(value));
+                self.terminate(Terminator::Return(value));
+            }
+            StmtKind::Break => {
+                let exit = *self.loops.last().expect("the checker rejects `break` outside of a loop");
+                self.terminate(Terminator::Jump(exit));
+            }
+            StmtKind::If { condition, then_branch, else_branch } => {
+                let then_block = self.new_block();
+                let end = self.new_block();
+                let else_block = match else_branch {
+                    Some(_) => self.new_block(),
+                    None => end,
+                };
+                self.condition(condition, then_block, else_block);
+                self.switch_to(then_block);
+                self.stmt(then_branch);
+                self.terminate(Terminator::Jump(end));
+                if let Some(else_branch) = else_branch {
+                    self.switch_to(else_block);
+                    self.stmt(else_branch);
+                    self.terminate(Terminator::Jump(end));
+                }
+                self.switch_to(end);
+            }
+            StmtKind::While { condition, body } => self.while_loop(Some(condition), body, None),
+            StmtKind::For { init, condition, increment, body } => {
+                self.scopes.push(Vec::new());
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                self.while_loop(condition.as_ref(), body, increment.as_ref());
+                self.scopes.pop();
+            }
+            StmtKind::Function { .. } => {} // nested functions are not callable, see analysis.rs
+            StmtKind::Error => unreachable!("a program with parse errors is not lowered"),
+        }
+    }
+
+    // `while (condition) { body; increment; }`, where a missing condition is `true`
+    fn while_loop(&mut self, condition: Option<&'a Expr>, body: &'a Stmt, increment: Option<&'a Expr>) {
+        let header = self.new_block();
+        let body_block = self.new_block();
+        let exit = self.new_block();
+        self.terminate(Terminator::Jump(header));
+        self.switch_to(header);
+        match condition {
+            Some(condition) => self.condition(condition, body_block, exit),
+            None => self.terminate(Terminator::Jump(body_block)),
+        }
+        self.switch_to(body_block);
+        self.loops.push(exit);
+        self.stmt(body);
+        self.loops.pop();
+        if let Some(increment) = increment {
+            self.expr(increment);
+        }
+        self.terminate(Terminator::Jump(header));
+        self.switch_to(exit);
+    }
+
+    // Branches to `then_block` if the condition holds, else to `else_block`
+    fn condition(&mut self, condition: &'a Expr, then_block: BlockId, else_block: BlockId) {
+        match &condition.kind {
+            ExprKind::Grouping(inner) => self.condition(inner, then_block, else_block),
+            ExprKind::Unary { operator: TokenKind::T_NOT, expr: inner } => self.condition(inner, else_block, then_block),
+            ExprKind::Binary { left, operator: TokenKind::T_ANDAND, right } => {
+                let rest = self.new_block();
+                self.condition(left, rest, else_block);
+                self.switch_to(rest);
+                self.condition(right, then_block, else_block);
+            }
+            ExprKind::Binary { left, operator: TokenKind::T_OROR, right } => {
+                let rest = self.new_block();
+                self.condition(left, then_block, rest);
+                self.switch_to(rest);
+                self.condition(right, then_block, else_block);
+            }
+            _ => {
+                let value = self.expr(condition);
+                self.terminate(Terminator::Branch { condition: value, then_block, else_block });
+            }
+        }
+    }
+
+    // Lowers the expression and returns what holds its value
+    fn expr(&mut self, expr: &'a Expr) -> Operand {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.resolve(name).expect("the checker rejects undeclared names") {
+                Ok(var) => Operand::Var(var),
+                Err(global) => {
+                    let dest = self.temp(self.type_of(expr));
+                    self.emit(InstKind::GetGlobal { dest, global }, span);
+                    Operand::Var(dest)
+                }
+            },
+            ExprKind::Integer(n) => Operand::Const(Value::Int(*n)),
+            ExprKind::Float(x) => Operand::Const(Value::Float(*x)),
+            ExprKind::Boolean(b) => Operand::Const(Value::Bool(*b)),
+            ExprKind::StringLit(s) => Operand::Const(Value::Str(s.clone())),
+            ExprKind::Char(c) => Operand::Const(Value::Char(*c)),
+            ExprKind::Array(elements) => {
+                let elements = self.operands(elements.iter().collect());
+                let dest = self.temp(self.type_of(expr));
+                self.emit(InstKind::Array { dest, elements }, span);
+                Operand::Var(dest)
+            }
+            ExprKind::Unary { operator, expr: inner } => {
+                let op = UnaryOp::from_token(operator).expect("the parser only makes unary operations of unary operators");
+                let operand = self.expr(inner);
+                let dest = self.temp(self.type_of(expr));
+                self.emit(InstKind::Unary { dest, op, operand }, span);
+                Operand::Var(dest)
+            }
+            ExprKind::Binary { left, operator: TokenKind::T_ASSIGNOP, right } => self.assign(left, right),
+            // as a value, `a && b` is `if (a && b) true else false`, with the branches above
+            ExprKind::Binary { operator: TokenKind::T_ANDAND | TokenKind::T_OROR, .. } => {
+                let dest = self.temp(Type::Bool);
+                let (yes, no, end) = (self.new_block(), self.new_block(), self.new_block());
+                self.condition(expr, yes, no);
+                for (block, value) in [(yes, true), (no, false)] {
+                    self.switch_to(block);
+                    self.emit(InstKind::Copy { dest, src: Operand::Const(Value::Bool(value)) }, span);
+                    self.terminate(Terminator::Jump(end));
+                }
+                self.switch_to(end);
+                Operand::Var(dest)
+            }
+            ExprKind::Binary { left, operator, right } => {
+                let op = BinaryOp::from_token(operator).expect("the parser only makes binary operations of binary operators");
+                let mut operands = self.operands(vec![left, right]);
+                let right = operands.pop().expect("two operands");
+                let left = operands.pop().expect("two operands");
+                let dest = self.temp(self.type_of(expr));
+                self.emit(InstKind::Binary { dest, op, left, right }, span);
+                Operand::Var(dest)
+            }
+            ExprKind::Call { callee, args } => {
+                let (id, args) = self.call_parts(callee, args);
+                let ty = self.module.function(id).return_type.clone();
+                self.call(id, args, ty, span).unwrap_or(Operand::Const(Value::Void))
+            }
+            ExprKind::Grouping(inner) => self.expr(inner),
+            ExprKind::Index { .. } | ExprKind::Field { .. } => {
+                let (root, accesses) = self.place(expr);
+                let mut aggregate = self.root_value(root, span);
+                for (step, access) in accesses {
+                    let dest = self.temp(self.type_of(step));
+                    self.emit(InstKind::Extract { dest, aggregate, access }, step.span);
+                    aggregate = Operand::Var(dest);
+                }
+                aggregate
+            }
+            ExprKind::StructLit { name, fields } => {
+                // initializers run in source order; the value keeps the declaration's order
+                let given = self.operands(fields.iter().map(|init| &init.value).collect());
+                let declared = self.structs.get(name.as_str()).copied().expect("the checker rejects unknown structs");
+                let fields = declared
+                    .iter()
+                    .map(|field| {
+                        let at = fields.iter().position(|init| init.name == field.name);
+                        (field.name.clone(), at.map_or(Operand::Const(Value::Unassigned), |i| given[i].clone()))
+                    })
+                    .collect();
+                let dest = self.temp(self.type_of(expr));
+                self.emit(InstKind::Struct { dest, name: name.clone(), fields }, span);
+                Operand::Var(dest)
+            }
+            ExprKind::Cast { expr: inner, .. } => {
+                let operand = self.expr(inner);
+                let dest = self.temp(self.type_of(expr));
+                self.emit(InstKind::Cast { dest, operand }, span);
+                Operand::Var(dest)
+            }
+            ExprKind::Error => unreachable!("a program with parse errors is not lowered"),
+        }
+    }
+
+    // Lowers expressions evaluated left to right. A variable read early is copied to a
+    // temporary when a later expression assigns, so it keeps the value it had when read.
+    fn operands(&mut self, exprs: Vec<&'a Expr>) -> Vec<Operand> {
+        let mut operands: Vec<Operand> = Vec::with_capacity(exprs.len());
+        for (i, expr) in exprs.iter().enumerate() {
+            let operand = self.expr(expr);
+            let later_assigns = exprs[i + 1..].iter().any(|later| assigns(later));
+            operands.push(self.keep(operand, later_assigns, expr.span));
+        }
+        operands
+    }
+
+    // The operand, copied to a temporary first if it is a variable that is about to be
+    // assigned
+    fn keep(&mut self, operand: Operand, assigned: bool, span: Span) -> Operand {
+        match operand {
+            Operand::Var(var) if assigned => {
+                let copy = self.temp(self.function.var(var).ty.clone());
+                self.emit(InstKind::Copy { dest: copy, src: Operand::Var(var) }, span);
+                Operand::Var(copy)
+            }
+            operand => operand,
+        }
+    }
+
+    fn call_parts(&mut self, callee: &'a Expr, args: &'a [Expr]) -> (FunctionId, Vec<Operand>) {
+        let ExprKind::Identifier(name) = &callee.kind else {
+            unreachable!("the checker only accepts calls of functions by name");
+        };
+        let args = self.operands(args.iter().collect());
+        (self.function_ids[name.as_str()], args)
+    }
+
+    // Emits the call; its result is kept unless its type is void
+    fn call(&mut self, function: FunctionId, args: Vec<Operand>, ty: Type, span: Span) -> Option<Operand> {
+        let dest = (ty != Type::Void).then(|| self.temp(ty));
+        self.emit(InstKind::Call { dest, function, args }, span);
+        dest.map(Operand::Var)
+    }
+
+    // `target = value`: the value is computed first, then the indexes of the target, then
+    // the variable it is in is redefined. The result is the value.
+    fn assign(&mut self, target: &'a Expr, value: &'a Expr) -> Operand {
+        let value = self.expr(value);
+        let value = self.keep(value, assigns(target), target.span);
+        let (root, accesses) = self.place(target);
+        match root {
+            // storing into a temporary, e.g. `f().x = 1`, only has the effects of computing it
+            Root::Temporary(_) => return value,
+            Root::Var(var) if accesses.is_empty() => return self.define(var, value, target.span),
+            Root::Global(global) if accesses.is_empty() => {
+                self.emit(InstKind::SetGlobal { global, value: value.clone() }, target.span);
+                return value;
+            }
+            Root::Var(_) | Root::Global(_) => {}
+        }
+        // read the aggregate at each level on the way down, then rebuild them on the way up
+        let mut aggregates = Vec::with_capacity(accesses.len());
+        let mut aggregate = self.root_value(root.clone(), target.span);
+        for (step, access) in &accesses[..accesses.len() - 1] {
+            aggregates.push(aggregate.clone());
+            let dest = self.temp(self.type_of(step));
+            self.emit(InstKind::Extract { dest, aggregate, access: access.clone() }, step.span);
+            aggregate = Operand::Var(dest);
+        }
+        aggregates.push(aggregate);
+
+        let mut element = value.clone();
+        for i in (0..accesses.len()).rev() {
+            let aggregate = aggregates.pop().expect("one aggregate per access");
+            let (dest, span) = match (i, &root) {
+                (0, Root::Var(var)) => (*var, target.span),
+                (0, _) => (self.temp(self.root_type(&root)), target.span),
+                _ => (self.temp(self.type_of(accesses[i - 1].0)), accesses[i - 1].0.span),
+            };
+            self.emit(InstKind::Insert { dest, aggregate, access: accesses[i].1.clone(), value: element }, span);
+            element = Operand::Var(dest);
+        }
+        if let Root::Global(global) = root {
+            self.emit(InstKind::SetGlobal { global, value: element }, target.span);
+        }
+        value
+    }
+
+    // Splits `xs[i].p` into its root and the accesses applied to it (with the expression
+    // each one gives the value of), as the interpreter does: a root that is not a variable
+    // is computed first, then the indexes from the inside out. A variable root is read or
+    // redefined by the caller after that, so it sees what the indexes did to it.
+    fn place(&mut self, expr: &'a Expr) -> (Root, Vec<(&'a Expr, Access)>) {
+        let mut steps = Vec::new();
+        let mut root = strip(expr);
+        loop {
+            match &root.kind {
+                ExprKind::Index { array, index } => {
+                    steps.push((root, Some(&**index)));
+                    root = strip(array);
+                }
+                ExprKind::Field { object, .. } => {
+                    steps.push((root, None));
+                    root = strip(object);
+                }
+                _ => break,
+            }
+        }
+        steps.reverse();
+        let named = match &root.kind {
+            ExprKind::Identifier(name) => Some(self.resolve(name).expect("the checker rejects undeclared names")),
+            _ => None,
+        };
+        let mut exprs: Vec<&Expr> = if named.is_none() { vec![root] } else { Vec::new() };
+        exprs.extend(steps.iter().filter_map(|(_, index)| *index));
+        let mut operands = self.operands(exprs).into_iter();
+        let root = match named {
+            Some(Ok(var)) => Root::Var(var),
+            Some(Err(global)) => Root::Global(global),
+            None => Root::Temporary(operands.next().expect("the root comes first")),
+        };
+        let accesses = steps
+            .into_iter()
+            .map(|(step, index)| match (&step.kind, index) {
+                (ExprKind::Field { field, .. }, _) => (step, Access::Field(field.clone())),
+                _ => (step, Access::Index(operands.next().expect("one operand per index"))),
+            })
+            .collect();
+        (root, accesses)
+    }
+
+    // What the root of a place holds, reading it if it is a global
+    fn root_value(&mut self, root: Root, span: Span) -> Operand {
+        match root {
+            Root::Var(var) => Operand::Var(var),
+            Root::Global(global) => {
+                let dest = self.temp(self.root_type(&Root::Global(global)));
+                self.emit(InstKind::GetGlobal { dest, global }, span);
+                Operand::Var(dest)
+            }
+            Root::Temporary(operand) => operand,
+        }
+    }
+
+    fn root_type(&self, root: &Root) -> Type {
+        match *root {
+            Root::Var(var) => self.function.var(var).ty.clone(),
+            Root::Global(global) => self.module.globals[global.0 as usize].ty.clone(),
+            Root::Temporary(_) => Type::Unknown,
+        }
+    }
+}
+
+// Where an assignment stores or a read looks: a variable, a global, or a value computed
+// on the spot (`f().x`)
+#[derive(Clone)]
+enum Root {
+    Var(Var),
+    Global(GlobalId),
+    Temporary(Operand),
+}
+
+fn strip(mut expr: &Expr) -> &Expr {
+    while let ExprKind::Grouping(inner) = &expr.kind {
+        expr = inner;
+    }
+    expr
+}
+
+// Whether evaluating the expression can change a local variable
+fn assigns(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Binary { operator: TokenKind::T_ASSIGNOP, .. } => true,
+        ExprKind::Binary { left, right, .. } => assigns(left) || assigns(right),
+        ExprKind::Unary { expr: inner, .. } | ExprKind::Grouping(inner) | ExprKind::Cast { expr: inner, .. } => assigns(inner),
+        ExprKind::Field { object: inner, .. } => assigns(inner),
+        ExprKind::Index { array, index } => assigns(array) || assigns(index),
+        ExprKind::Call { args: exprs, .. } | ExprKind::Array(exprs) => exprs.iter().any(assigns),
+        ExprKind::StructLit { fields, .. } => fields.iter().any(|init| assigns(&init.value)),
+        ExprKind::Identifier(_) | ExprKind::Integer(_) | ExprKind::Float(_) | ExprKind::Boolean(_) | ExprKind::StringLit(_)
+        | ExprKind::Char(_) | ExprKind::Error => false,
+    }
+}
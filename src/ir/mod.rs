@@ -0,0 +1,7 @@
+pub mod tac;
+pub mod lower;
+pub mod cfg;
+pub mod dominators;
+pub mod ssa;
+pub mod dump;
+pub mod opt;
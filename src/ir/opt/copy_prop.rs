@@ -0,0 +1,83 @@
+// Copy propagation: after `x = y`, uses of x read y instead and the copy goes. In SSA
+// form that is always right, y is defined once and before the copy. A phi that picks the
+// same value whichever way control came (other than itself, round a loop) is a copy too,
+// which is how the phis of a variable a loop never changes disappear. Copies of array and
+// struct constants stay, rather than repeat the constant at every use.
+use crate::ir::opt::{same_operand, substitutable};
+use crate::ir::tac::{Function, InstKind, Operand, Var};
+
+pub fn run(function: &mut Function) -> bool {
+    // what each copied Var stands for, followed to the end of chains of copies
+    let mut replaced: Vec<Option<Operand>> = vec![None; function.vars.len()];
+    let resolve = |replaced: &[Option<Operand>], operand: &Operand| {
+        let mut operand = operand.clone();
+        while let Operand::Var(v) = operand
+            && let Some(next) = &replaced[v.0 as usize]
+        {
+            operand = next.clone();
+        }
+        operand
+    };
+
+    // a phi may only become a copy once the copies it reads from have been seen
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &function.blocks {
+            for inst in &block.insts {
+                let Some(dest) = inst.kind.dest() else {
+                    continue;
+                };
+                if replaced[dest.0 as usize].is_some() {
+                    continue;
+                }
+                let source = match &inst.kind {
+                    InstKind::Copy { src, .. } => Some(resolve(&replaced, src)),
+                    InstKind::Phi { args, .. } => same_value(dest, args.iter().map(|(_, arg)| resolve(&replaced, arg))),
+                    _ => None,
+                };
+                if let Some(source) = source
+                    && source != Operand::Var(dest)
+                    && match &source {
+                        Operand::Const(value) => substitutable(value),
+                        Operand::Var(_) => true,
+                    }
+                {
+                    replaced[dest.0 as usize] = Some(source);
+                    changed = true;
+                }
+            }
+        }
+    }
+    if replaced.iter().all(Option::is_none) {
+        return false;
+    }
+
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| inst.kind.dest().is_none_or(|dest| replaced[dest.0 as usize].is_none()));
+        for inst in &mut block.insts {
+            for operand in inst.kind.operands_mut() {
+                *operand = resolve(&replaced, operand);
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            *operand = resolve(&replaced, operand);
+        }
+    }
+    true
+}
+
+// The one value a phi's arguments all have, not counting the phi itself
+fn same_value(phi: Var, args: impl Iterator<Item = Operand>) -> Option<Operand> {
+    let mut same = None;
+    for arg in args {
+        if arg == Operand::Var(phi) || same.as_ref().is_some_and(|same| same_operand(same, &arg)) {
+            continue;
+        }
+        if same.is_some() {
+            return None;
+        }
+        same = Some(arg);
+    }
+    same
+}
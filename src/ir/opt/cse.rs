@@ -0,0 +1,147 @@
+// Common subexpression elimination: an instruction that computes what an earlier one
+// already has (the same operation on the same operands) becomes a copy of the earlier
+// result, for copy-prop to propagate and dce to remove. In SSA form operands never change
+// once defined, so "the same operands" is just the same Vars and constants. The local
+// form only looks for the earlier computation in the same block; the global one takes
+// any whose block dominates this one, which control must have gone through first.
+//
+// Only values are reused: calls and reads of globals may give something different each
+// time. Reusing an operation that can fail is fine, as the first one would have failed
+// already with the same operands.
+use std::collections::HashMap;
+use crate::ir::cfg::Cfg;
+use crate::ir::dominators::Dominators;
+use crate::interpreter::value::Value;
+use crate::ir::tac::{Access, BinaryOp, BlockId, Function, InstKind, Operand, UnaryOp, Var, VarInfo};
+use crate::semantics::scope::scope::Type;
+
+pub fn run(function: &mut Function, global: bool) -> bool {
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+    // the earlier computations of each expression, with where they are
+    let mut available: HashMap<Key, Vec<(BlockId, Var)>> = HashMap::new();
+    let mut replaced: Vec<Option<Var>> = vec![None; function.vars.len()];
+    let mut changed = false;
+    let vars = &function.vars;
+    // every block after the blocks that dominate it, so their computations are known first
+    for &block in dominators.preorder() {
+        for inst in &mut function.blocks[block.0 as usize].insts {
+            for operand in inst.kind.operands_mut() {
+                if let Operand::Var(v) = *operand
+                    && let Some(earlier) = replaced[v.0 as usize]
+                {
+                    *operand = Operand::Var(earlier);
+                }
+            }
+            let (Some(dest), Some(key)) = (inst.kind.dest(), key(vars, &inst.kind)) else {
+                continue;
+            };
+            let candidates = available.entry(key).or_default();
+            let earlier = candidates
+                .iter()
+                .find(|(at, _)| if global { dominators.dominates(*at, block) } else { *at == block })
+                .map(|&(_, var)| var);
+            match earlier {
+                Some(earlier) => {
+                    inst.kind = InstKind::Copy { dest, src: Operand::Var(earlier) };
+                    replaced[dest.0 as usize] = Some(earlier);
+                    changed = true;
+                }
+                None => candidates.push((block, dest)),
+            }
+        }
+    }
+    if !changed {
+        return false;
+    }
+    // uses the walk did not see: phis reading from later blocks, and terminators
+    for block in &mut function.blocks {
+        let operands = block.insts.iter_mut().flat_map(|inst| inst.kind.operands_mut()).chain(block.terminator.operands_mut());
+        for operand in operands {
+            if let Operand::Var(v) = *operand
+                && let Some(earlier) = replaced[v.0 as usize]
+            {
+                *operand = Operand::Var(earlier);
+            }
+        }
+    }
+    true
+}
+
+// What an instruction computes: equal for equal computations. A cast converts to the
+// type of its destination, so that is part of it.
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Unary(UnaryOp, Arg),
+    Binary(BinaryOp, Arg, Arg),
+    Cast(Type, Arg),
+    Extract(Arg, Step),
+    Insert(Arg, Step, Arg),
+    Array(Vec<Arg>),
+    Struct(String, Vec<(String, Arg)>),
+}
+
+// An operand; floats by their bits, the same bits being the same value
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Arg {
+    Var(Var),
+    Int(i64),
+    Float(u64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Step {
+    Index(Arg),
+    Field(String),
+}
+
+// None for the instructions that are not values of their operands alone, and for
+// operands that are aggregate constants
+fn key(vars: &[VarInfo], kind: &InstKind) -> Option<Key> {
+    Some(match kind {
+        InstKind::Unary { op, operand, .. } => Key::Unary(*op, arg(operand)?),
+        InstKind::Binary { op, left, right, .. } => {
+            let (mut left, mut right) = (arg(left)?, arg(right)?);
+            // `a + b` is `b + a`
+            if commutes(*op) && left > right {
+                std::mem::swap(&mut left, &mut right);
+            }
+            Key::Binary(*op, left, right)
+        }
+        InstKind::Cast { dest, operand } => Key::Cast(vars[dest.0 as usize].ty.clone(), arg(operand)?),
+        InstKind::Extract { aggregate, access, .. } => Key::Extract(arg(aggregate)?, step(access)?),
+        InstKind::Insert { aggregate, access, value, .. } => Key::Insert(arg(aggregate)?, step(access)?, arg(value)?),
+        InstKind::Array { elements, .. } => Key::Array(elements.iter().map(arg).collect::<Option<_>>()?),
+        InstKind::Struct { name, fields, .. } => {
+            let fields = fields.iter().map(|(field, value)| Some((field.clone(), arg(value)?))).collect::<Option<_>>()?;
+            Key::Struct(name.clone(), fields)
+        }
+        InstKind::Copy { .. } | InstKind::Call { .. } | InstKind::GetGlobal { .. } | InstKind::SetGlobal { .. } | InstKind::Phi { .. } => return None,
+    })
+}
+
+fn arg(operand: &Operand) -> Option<Arg> {
+    Some(match operand {
+        Operand::Var(v) => Arg::Var(*v),
+        Operand::Const(Value::Int(i)) => Arg::Int(*i),
+        Operand::Const(Value::Float(f)) => Arg::Float(f.to_bits()),
+        Operand::Const(Value::Bool(b)) => Arg::Bool(*b),
+        Operand::Const(Value::Char(c)) => Arg::Char(*c),
+        Operand::Const(Value::Str(s)) => Arg::Str(s.clone()),
+        Operand::Const(_) => return None,
+    })
+}
+
+fn step(access: &Access) -> Option<Step> {
+    Some(match access {
+        Access::Index(index) => Step::Index(arg(index)?),
+        Access::Field(field) => Step::Field(field.clone()),
+    })
+}
+
+fn commutes(op: BinaryOp) -> bool {
+    matches!(op, BinaryOp::Add | BinaryOp::Mul | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::Eq | BinaryOp::Ne)
+}
@@ -0,0 +1,65 @@
+// Dead code elimination: an instruction goes when nothing uses what it defines and it
+// can go without changing what the program does (see opt::removable). Uses are followed
+// backwards from what has to stay, the effects, the terminators and whatever may fail, so
+// values that only feed each other, like a counter nothing reads, go too. A call whose
+// result is unused stays, but no longer keeps the result.
+use crate::ir::opt::removable;
+use crate::ir::tac::{Function, InstKind, Operand, Var};
+
+pub fn run(function: &mut Function) -> bool {
+    let mut live = vec![false; function.vars.len()];
+    let mut worklist: Vec<Var> = Vec::new();
+    let mut mark = |operand: &Operand, worklist: &mut Vec<Var>| {
+        if let Operand::Var(v) = *operand
+            && !live[v.0 as usize]
+        {
+            live[v.0 as usize] = true;
+            worklist.push(v);
+        }
+    };
+    // where each Var is defined, as (block, instruction)
+    let mut definitions = vec![None; function.vars.len()];
+    for (b, block) in function.blocks.iter().enumerate() {
+        for (i, inst) in block.insts.iter().enumerate() {
+            if let Some(dest) = inst.kind.dest() {
+                definitions[dest.0 as usize] = Some((b, i));
+            }
+            if !removable(&function.vars, &inst.kind) {
+                for operand in inst.kind.operands() {
+                    mark(operand, &mut worklist);
+                }
+            }
+        }
+        for operand in block.terminator.operands() {
+            mark(operand, &mut worklist);
+        }
+    }
+    while let Some(var) = worklist.pop() {
+        if let Some((b, i)) = definitions[var.0 as usize] {
+            for operand in function.blocks[b].insts[i].kind.operands() {
+                mark(operand, &mut worklist);
+            }
+        }
+    }
+
+    let mut changed = false;
+    let vars = &function.vars;
+    for block in &mut function.blocks {
+        let before = block.insts.len();
+        block.insts.retain(|inst| match inst.kind.dest() {
+            Some(dest) => live[dest.0 as usize] || !removable(vars, &inst.kind),
+            None => true,
+        });
+        changed |= block.insts.len() != before;
+        for inst in &mut block.insts {
+            if let InstKind::Call { dest, .. } = &mut inst.kind
+                && let Some(var) = *dest
+                && !live[var.0 as usize]
+            {
+                *dest = None;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
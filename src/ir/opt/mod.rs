@@ -0,0 +1,240 @@
+// Optimisation of functions in SSA form (see ssa::construct), as a list of passes run in
+// order over every function of a module. Each pass is a plain function that rewrites one
+// function and says whether it changed anything; the Pipeline runs a list of them,
+// repeating it while it still finds something to do, and reports after each pass so the
+// IR can be dumped in between. A level picks the usual list:
+//
+//   -O0  nothing
+//   -O1  sccp, copy-prop, local-cse, dce, unreachable, merge-blocks; once
+//   -O2  sccp, copy-prop, gcse, copy-prop, dce, unreachable, merge-blocks; again while it
+//        changes things
+//
+// No pass changes what a program does, its runtime errors included: an operation that
+// fails on its constant operands is left for the program to run into, and code whose
+// result is unused is kept when running it could fail.
+pub mod sccp;
+pub mod copy_prop;
+pub mod cse;
+pub mod dce;
+
+use crate::interpreter::value::Value;
+use crate::ir::cfg;
+use crate::ir::tac::{Access, BinaryOp, BlockId, Function, InstKind, Module, Operand, Terminator, UnaryOp, VarInfo};
+use crate::semantics::scope::scope::Type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Sccp,        // sparse conditional constant propagation
+    CopyProp,    // uses of `x = y` read y instead
+    LocalCse,    // a value computed twice in a block is computed once
+    GlobalCse,   // ... or twice where the first computation dominates the second
+    Dce,         // dead code elimination
+    Unreachable, // blocks control cannot reach are dropped
+    MergeBlocks, // a block entered only from the one before it joins that block
+}
+
+impl Pass {
+    pub const ALL: [Pass; 7] =
+        [Pass::Sccp, Pass::CopyProp, Pass::LocalCse, Pass::GlobalCse, Pass::Dce, Pass::Unreachable, Pass::MergeBlocks];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Sccp => "sccp",
+            Pass::CopyProp => "copy-prop",
+            Pass::LocalCse => "local-cse",
+            Pass::GlobalCse => "gcse",
+            Pass::Dce => "dce",
+            Pass::Unreachable => "unreachable",
+            Pass::MergeBlocks => "merge-blocks",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    // Runs the pass over one function; returns whether it changed anything
+    pub fn run(self, function: &mut Function) -> bool {
+        if function.blocks.is_empty() {
+            return false;
+        }
+        match self {
+            Pass::Sccp => sccp::run(function),
+            Pass::CopyProp => copy_prop::run(function),
+            Pass::LocalCse => cse::run(function, false),
+            Pass::GlobalCse => cse::run(function, true),
+            Pass::Dce => dce::run(function),
+            Pass::Unreachable => remove_unreachable(function),
+            Pass::MergeBlocks => cfg::merge_blocks(function),
+        }
+    }
+}
+
+pub struct Pipeline {
+    pub passes: Vec<Pass>,
+    pub max_rounds: usize, // how many times the list may run; it stops early once nothing changes
+}
+
+impl Pipeline {
+    pub fn for_level(level: u8) -> Self {
+        match level {
+            0 => Pipeline { passes: Vec::new(), max_rounds: 1 },
+            1 => Pipeline {
+                passes: vec![Pass::Sccp, Pass::CopyProp, Pass::LocalCse, Pass::Dce, Pass::Unreachable, Pass::MergeBlocks],
+                max_rounds: 1,
+            },
+            _ => Pipeline {
+                passes: vec![
+                    Pass::Sccp,
+                    Pass::CopyProp,
+                    Pass::GlobalCse,
+                    Pass::CopyProp,
+                    Pass::Dce,
+                    Pass::Unreachable,
+                    Pass::MergeBlocks,
+                ],
+                max_rounds: 4,
+            },
+        }
+    }
+
+    pub fn without(mut self, pass: Pass) -> Self {
+        self.passes.retain(|&p| p != pass);
+        self
+    }
+
+    // Runs the passes over every function of a module in SSA form. `after` is called
+    // after each pass with whether it changed anything, e.g. to dump the IR.
+    pub fn run(&self, module: &mut Module, mut after: impl FnMut(Pass, bool, &Module)) {
+        for _ in 0..self.max_rounds {
+            let mut changed_any = false;
+            for &pass in &self.passes {
+                let mut changed = false;
+                for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+                    changed |= pass.run(function);
+                }
+                after(pass, changed, module);
+                changed_any |= changed;
+            }
+            if !changed_any {
+                break;
+            }
+        }
+    }
+}
+
+// `branch c, bb1, bb1` becomes `jump bb1`, and so does a branch whose two ways lead to
+// the same block through empty blocks that only jump there; then the blocks nothing
+// reaches go
+fn remove_unreachable(function: &mut Function) -> bool {
+    let mut changed = false;
+    for block in function.block_ids() {
+        if let Terminator::Branch { then_block, else_block, .. } = function.block(block).terminator
+            && (then_block == else_block || same_destination(function, block, then_block, else_block))
+        {
+            // an empty block left behind goes with the edge into it
+            let target = if forwards_to(function, then_block) == else_block { else_block } else { then_block };
+            function.block_mut(block).terminator = Terminator::Jump(target);
+            changed = true;
+        }
+    }
+    cfg::remove_unreachable(function) || changed
+}
+
+// The block control goes on to from `block`: the one it jumps to if it does nothing else,
+// otherwise itself
+fn forwards_to(function: &Function, block: BlockId) -> BlockId {
+    let body = function.block(block);
+    match body.terminator {
+        Terminator::Jump(target) if body.insts.is_empty() => target,
+        _ => block,
+    }
+}
+
+// Whether going from `from` to `a` or to `b` ends up in the same block, with its phis
+// picking the same values either way
+fn same_destination(function: &Function, from: BlockId, a: BlockId, b: BlockId) -> bool {
+    let destination = forwards_to(function, a);
+    if forwards_to(function, b) != destination {
+        return false;
+    }
+    let pred = |block: BlockId| if block == destination { from } else { block };
+    function.block(destination).insts.iter().all(|inst| match &inst.kind {
+        InstKind::Phi { args, .. } => {
+            let arg = |block: BlockId| args.iter().find(|(p, _)| *p == pred(block)).map(|(_, arg)| arg);
+            match (arg(a), arg(b)) {
+                (Some(a), Some(b)) => same_operand(a, b),
+                (a, b) => a == b,
+            }
+        }
+        _ => true,
+    })
+}
+
+// Whether an instruction whose result is unused can go: it has no effect and, given the
+// types the checker proved, cannot fail at runtime. Integer arithmetic can overflow or
+// divide by zero, an index can be out of bounds, a global read before it is assigned is
+// an error, and a call can do anything. A field is always there to read: a struct only
+// comes from a literal that gives every one of them. So is every element of an array,
+// which the checker wants assigned as a whole before any of it is used, and a constant
+// index can be seen to be in bounds.
+pub fn removable(vars: &[VarInfo], kind: &InstKind) -> bool {
+    match kind {
+        InstKind::Copy { .. } | InstKind::Phi { .. } | InstKind::Array { .. } | InstKind::Struct { .. } => true,
+        InstKind::Unary { op, dest, .. } => match op {
+            UnaryOp::Not | UnaryOp::BitNot => true,
+            UnaryOp::Neg => vars[dest.0 as usize].ty == Type::Float,
+        },
+        InstKind::Binary { op, dest, .. } => match op {
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge => true,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem | BinaryOp::Pow => {
+                vars[dest.0 as usize].ty == Type::Float
+            }
+            BinaryOp::Shl | BinaryOp::Shr => false,
+        },
+        InstKind::Extract { access: Access::Field(_), .. } | InstKind::Insert { access: Access::Field(_), .. } => true,
+        InstKind::Extract { aggregate: Operand::Var(v), access: Access::Index(Operand::Const(Value::Int(i))), .. } => {
+            in_bounds(&vars[v.0 as usize].ty, *i)
+        }
+        InstKind::Insert { dest, access: Access::Index(Operand::Const(Value::Int(i))), .. } => in_bounds(&vars[dest.0 as usize].ty, *i),
+        InstKind::Cast { .. }
+        | InstKind::Extract { .. }
+        | InstKind::Insert { .. }
+        | InstKind::Call { .. }
+        | InstKind::GetGlobal { .. }
+        | InstKind::SetGlobal { .. } => false,
+    }
+}
+
+fn in_bounds(array: &Type, i: i64) -> bool {
+    matches!(array, Type::Array(_, len) if usize::try_from(i).is_ok_and(|i| i < *len))
+}
+
+// Whether two constants are the same value, floats bit for bit: 0.0 and -0.0 compare
+// equal but divide 1.0 into different infinities, and a NaN is the same as itself
+pub fn identical(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| identical(a, b)),
+        (Value::Struct(a, fields_a), Value::Struct(b, fields_b)) => {
+            a == b
+                && fields_a.len() == fields_b.len()
+                && fields_a.iter().zip(fields_b).all(|((f, a), (g, b))| f == g && identical(a, b))
+        }
+        _ => a == b,
+    }
+}
+
+// Operands that stand for the same value: the same variable or identical constants
+pub fn same_operand(a: &Operand, b: &Operand) -> bool {
+    match (a, b) {
+        (Operand::Const(a), Operand::Const(b)) => identical(a, b),
+        _ => a == b,
+    }
+}
+
+// Whether a constant is worth putting in place of the variable that holds it: a copy of
+// an array or struct at every use would only make the code bigger
+pub fn substitutable(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Struct(..) | Value::Unassigned)
+}
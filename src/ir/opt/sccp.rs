@@ -0,0 +1,343 @@
+// Sparse conditional constant propagation (Wegman and Zadeck). Every Var starts out
+// unknown (no definition of it has run yet) and only ever moves down to a constant and
+// then to "not constant"; a block counts only once an edge into it can be taken, and a
+// branch on a constant can only take one of its edges. So a variable that is constant on
+// every path that can actually happen is found to be, loops and all, where folding and
+// pruning branches separately would each miss what the other makes possible.
+//
+// Operations are folded with interpreter::ops, so they give exactly what the program
+// would compute; one that fails on its operands (division by zero, overflow) is left in
+// place to fail at runtime. Afterwards uses of constant variables read the constant,
+// their definitions become copies of it, branches on constants become jumps and the
+// blocks that can't be reached are removed. Aggregates are folded when their parts are
+// constant, so their elements can be, but are never substituted into uses.
+use std::collections::HashSet;
+use crate::interpreter::ops::{self, Step};
+use crate::interpreter::value::Value;
+use crate::ir::cfg::{self, Cfg};
+use crate::ir::opt::{identical, substitutable};
+use crate::ir::tac::{Access, BlockId, Function, Inst, InstKind, Operand, Terminator, Var};
+use crate::semantics::scope::scope::Type;
+use crate::span::Span;
+use crate::token::TokenKind;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    Unknown,
+    Const(Value),
+    Varying,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, x) | (x, Lattice::Unknown) => x.clone(),
+            (Lattice::Const(a), Lattice::Const(b)) if identical(a, b) => self.clone(),
+            _ => Lattice::Varying,
+        }
+    }
+
+    // constants compare bit for bit, so a NaN stays put instead of changing on every visit
+    fn same(&self, other: &Lattice) -> bool {
+        match (self, other) {
+            (Lattice::Const(a), Lattice::Const(b)) => identical(a, b),
+            (Lattice::Unknown, Lattice::Unknown) | (Lattice::Varying, Lattice::Varying) => true,
+            _ => false,
+        }
+    }
+}
+
+// Where a Var is used: an instruction of a block, or its terminator
+#[derive(Clone, Copy)]
+enum Use {
+    Inst(BlockId, usize),
+    Terminator(BlockId),
+}
+
+struct Solver<'f> {
+    function: &'f Function,
+    values: Vec<Lattice>,
+    uses: Vec<Vec<Use>>,
+    executable: Vec<bool>,
+    edges: HashSet<(BlockId, BlockId)>,
+    edge_worklist: Vec<(Option<BlockId>, BlockId)>,
+    var_worklist: Vec<Var>,
+}
+
+pub fn run(function: &mut Function) -> bool {
+    let (values, edges) = {
+        let mut solver = Solver::new(function);
+        solver.solve();
+        (solver.values, solver.edges)
+    };
+    rewrite(function, &values, &edges)
+}
+
+impl<'f> Solver<'f> {
+    fn new(function: &'f Function) -> Self {
+        let mut values = vec![Lattice::Unknown; function.vars.len()];
+        for &param in &function.params {
+            values[param.0 as usize] = Lattice::Varying;
+        }
+        let mut uses = vec![Vec::new(); function.vars.len()];
+        for block in function.block_ids() {
+            let body = function.block(block);
+            for (i, inst) in body.insts.iter().enumerate() {
+                for operand in inst.kind.operands() {
+                    if let Operand::Var(v) = operand {
+                        uses[v.0 as usize].push(Use::Inst(block, i));
+                    }
+                }
+            }
+            for operand in body.terminator.operands() {
+                if let Operand::Var(v) = operand {
+                    uses[v.0 as usize].push(Use::Terminator(block));
+                }
+            }
+        }
+        Solver {
+            function,
+            values,
+            uses,
+            executable: vec![false; function.blocks.len()],
+            edges: HashSet::new(),
+            edge_worklist: vec![(None, BlockId(0))],
+            var_worklist: Vec::new(),
+        }
+    }
+
+    fn solve(&mut self) {
+        loop {
+            if let Some((from, block)) = self.edge_worklist.pop() {
+                if let Some(from) = from
+                    && !self.edges.insert((from, block))
+                {
+                    continue;
+                }
+                let first_visit = !self.executable[block.0 as usize];
+                self.executable[block.0 as usize] = true;
+                let function = self.function;
+                // a new edge only changes the phis, unless the block is new altogether
+                for (i, inst) in function.block(block).insts.iter().enumerate() {
+                    if first_visit || matches!(inst.kind, InstKind::Phi { .. }) {
+                        self.visit_inst(block, i);
+                    }
+                }
+                if first_visit {
+                    self.visit_terminator(block);
+                }
+            } else if let Some(var) = self.var_worklist.pop() {
+                for i in 0..self.uses[var.0 as usize].len() {
+                    match self.uses[var.0 as usize][i] {
+                        Use::Inst(block, i) if self.executable[block.0 as usize] => self.visit_inst(block, i),
+                        Use::Terminator(block) if self.executable[block.0 as usize] => self.visit_terminator(block),
+                        _ => {}
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn visit_inst(&mut self, block: BlockId, i: usize) {
+        let kind = &self.function.block(block).insts[i].kind;
+        let Some(dest) = kind.dest() else {
+            return;
+        };
+        let value = match kind {
+            InstKind::Phi { args, .. } => args
+                .iter()
+                .filter(|(pred, _)| self.edges.contains(&(*pred, block)))
+                .fold(Lattice::Unknown, |acc, (_, arg)| acc.meet(&self.value(arg))),
+            _ => self.evaluate(kind),
+        };
+        // values only move down, which bounds how often each one changes
+        let value = self.values[dest.0 as usize].meet(&value);
+        if !value.same(&self.values[dest.0 as usize]) {
+            self.values[dest.0 as usize] = value;
+            self.var_worklist.push(dest);
+        }
+    }
+
+    fn visit_terminator(&mut self, block: BlockId) {
+        match &self.function.block(block).terminator {
+            Terminator::Jump(target) => self.edge_worklist.push((Some(block), *target)),
+            Terminator::Branch { condition, then_block, else_block } => match self.value(condition) {
+                Lattice::Unknown => {}
+                Lattice::Const(Value::Bool(true)) => self.edge_worklist.push((Some(block), *then_block)),
+                Lattice::Const(Value::Bool(false)) => self.edge_worklist.push((Some(block), *else_block)),
+                Lattice::Const(_) | Lattice::Varying => {
+                    self.edge_worklist.push((Some(block), *then_block));
+                    self.edge_worklist.push((Some(block), *else_block));
+                }
+            },
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+
+    fn value(&self, operand: &Operand) -> Lattice {
+        match operand {
+            Operand::Var(v) => self.values[v.0 as usize].clone(),
+            Operand::Const(value) => Lattice::Const(value.clone()),
+        }
+    }
+
+    // What the instruction computes from what is known of its operands: unknown while any
+    // operand is, constant if it folds, varying otherwise
+    fn evaluate(&self, kind: &InstKind) -> Lattice {
+        let operands: Vec<Lattice> = kind.operands().into_iter().map(|operand| self.value(operand)).collect();
+        if let InstKind::Copy { .. } = kind {
+            return operands[0].clone();
+        }
+        if matches!(kind, InstKind::Call { .. } | InstKind::GetGlobal { .. }) || operands.contains(&Lattice::Varying) {
+            return Lattice::Varying;
+        }
+        if operands.contains(&Lattice::Unknown) {
+            return Lattice::Unknown;
+        }
+        let mut constants = operands.into_iter().map(|operand| match operand {
+            Lattice::Const(value) => value,
+            _ => unreachable!("only constants are left"),
+        });
+        let mut next = || constants.next().expect("one constant per operand");
+        let span = Span::default();
+        let folded = match kind {
+            InstKind::Unary { op, .. } => ops::unary(&op.token(), next(), span).ok(),
+            InstKind::Binary { op, .. } => {
+                let left = next();
+                ops::binary(&op.token(), left, next(), span).ok()
+            }
+            InstKind::Cast { dest, .. } => {
+                cast_token(&self.function.var(*dest).ty).and_then(|target| ops::cast(&target, next(), span).ok())
+            }
+            InstKind::Extract { access, .. } => {
+                let aggregate = next();
+                step(access, &mut next).and_then(|step| match ops::step_into(&aggregate, &step) {
+                    // reading an element that was never assigned is an error
+                    Ok(Value::Unassigned) | Err(_) => None,
+                    Ok(element) => Some(element.clone()),
+                })
+            }
+            InstKind::Insert { access, .. } => {
+                let mut aggregate = next();
+                let step = step(access, &mut next);
+                let value = next();
+                step.and_then(|step| ops::step_into_mut(&mut aggregate, &step).ok().map(|slot| *slot = value)).map(|_| aggregate)
+            }
+            InstKind::Array { elements, .. } => Some(Value::Array(elements.iter().map(|_| next()).collect())),
+            InstKind::Struct { name, fields, .. } => {
+                Some(Value::Struct(name.clone(), fields.iter().map(|(field, _)| (field.clone(), next())).collect()))
+            }
+            InstKind::Copy { .. } | InstKind::Call { .. } | InstKind::GetGlobal { .. } | InstKind::SetGlobal { .. } | InstKind::Phi { .. } => {
+                unreachable!("handled above, or defines nothing")
+            }
+        };
+        folded.map_or(Lattice::Varying, Lattice::Const)
+    }
+}
+
+// The step an access takes, given its index operand's constant if it has one
+fn step<'a>(access: &'a Access, next: &mut impl FnMut() -> Value) -> Option<Step<'a>> {
+    match access {
+        Access::Index(_) => match next() {
+            Value::Int(i) => Some(Step::Index(i, Span::default())),
+            _ => None,
+        },
+        Access::Field(field) => Some(Step::Field(field, Span::default())),
+    }
+}
+
+fn cast_token(ty: &Type) -> Option<TokenKind> {
+    match ty {
+        Type::Int => Some(TokenKind::T_INT),
+        Type::Float => Some(TokenKind::T_FLOAT),
+        Type::Bool => Some(TokenKind::T_BOOL),
+        Type::Char => Some(TokenKind::T_CHAR),
+        Type::String => Some(TokenKind::T_STRING),
+        _ => None,
+    }
+}
+
+fn rewrite(function: &mut Function, values: &[Lattice], edges: &HashSet<(BlockId, BlockId)>) -> bool {
+    let constant = |var: Var| match &values[var.0 as usize] {
+        Lattice::Const(value) if substitutable(value) => Some(value.clone()),
+        _ => None,
+    };
+    let mut changed = false;
+    for block in function.block_ids() {
+        let body = function.block_mut(block);
+        for inst in &mut body.insts {
+            for operand in inst.kind.operands_mut() {
+                if let Operand::Var(v) = *operand
+                    && let Some(value) = constant(v)
+                {
+                    *operand = Operand::Const(value);
+                    changed = true;
+                }
+            }
+        }
+        for operand in body.terminator.operands_mut() {
+            if let Operand::Var(v) = *operand
+                && let Some(value) = constant(v)
+            {
+                *operand = Operand::Const(value);
+                changed = true;
+            }
+        }
+
+        // definitions of constants become copies of them; those of phis go after the
+        // phis that are left, which must stay at the start of the block
+        let mut phis = Vec::new();
+        let mut copies = Vec::new();
+        let mut rest = Vec::new();
+        for inst in std::mem::take(&mut body.insts) {
+            let folded = match &inst.kind {
+                InstKind::Copy { src: Operand::Const(_), .. } => None,
+                kind => kind.dest().and_then(|dest| constant(dest).map(|value| (dest, value))),
+            };
+            match folded {
+                Some((dest, value)) => {
+                    let copy = Inst::new(InstKind::Copy { dest, src: Operand::Const(value) }, inst.span);
+                    match inst.kind {
+                        InstKind::Phi { .. } => copies.push(copy),
+                        _ => rest.push(copy),
+                    }
+                    changed = true;
+                }
+                None if matches!(inst.kind, InstKind::Phi { .. }) => phis.push(inst),
+                None => rest.push(inst),
+            }
+        }
+        body.insts = phis;
+        body.insts.extend(copies);
+        body.insts.extend(rest);
+
+        // a branch that can go only one way is a jump
+        if let Terminator::Branch { then_block, else_block, .. } = body.terminator {
+            let then_taken = edges.contains(&(block, then_block));
+            let else_taken = edges.contains(&(block, else_block));
+            if then_taken != else_taken {
+                body.terminator = Terminator::Jump(if then_taken { then_block } else { else_block });
+                changed = true;
+            }
+        }
+    }
+
+    // phis forget the edges that are gone
+    let cfg = Cfg::new(function);
+    for block in function.block_ids() {
+        if !cfg.is_reachable(block) {
+            continue;
+        }
+        let preds = cfg.preds(block).to_vec();
+        for inst in &mut function.block_mut(block).insts {
+            if let InstKind::Phi { args, .. } = &mut inst.kind {
+                let before = args.len();
+                args.retain(|(pred, _)| preds.contains(pred));
+                changed |= args.len() != before;
+            }
+        }
+    }
+    cfg::remove_unreachable(function) || changed
+}
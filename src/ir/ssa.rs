@@ -0,0 +1,323 @@
+// Static single assignment form: every Var is defined by exactly one instruction (or is a
+// parameter), and where definitions of one source variable meet, a phi at the start of
+// the block picks the one control came through. `construct` puts a lowered function into
+// this form, the classic way (Cytron et al.): phis go on the dominance frontiers of the
+// blocks that define a variable, then a walk of the dominator tree renames each
+// definition to a new version and each use to the version that reaches it. Only
+// variables that are used in some block before being defined there get phis, the others
+// never meet another definition (Briggs' semi-pruned form). The first definition of a
+// variable keeps its Var, the later ones become `x.1`, `x.2`, and so on.
+//
+// `destruct` takes a function back out of SSA form by turning each phi into copies at
+// the end of its predecessors, on critical edges split for the purpose. The copies of one
+// edge happen at once, as the phis did; where one overwrites what another still reads
+// (`a = b, b = a` after a loop that swaps them) a temporary breaks the cycle.
+use std::collections::{HashMap, HashSet};
+use crate::interpreter::value::Value;
+use crate::ir::cfg::{self, Cfg};
+use crate::ir::dominators::Dominators;
+use crate::ir::tac::{BlockId, Function, Inst, InstKind, Operand, Terminator, Var, VarInfo, VarKind};
+use crate::semantics::scope::scope::Type;
+
+pub fn construct(function: &mut Function) {
+    if function.blocks.is_empty() {
+        return;
+    }
+    // renaming only visits reachable blocks
+    cfg::remove_unreachable(function);
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+    let phis = place_phis(function, &cfg, &dominators);
+    Renamer::new(function, phis).rename(&cfg, &dominators);
+}
+
+// Inserts a phi for each variable wherever two of its definitions may meet. Returns, for
+// each block, the variable each of its leading phis is for.
+fn place_phis(function: &mut Function, cfg: &Cfg, dominators: &Dominators) -> Vec<Vec<Var>> {
+    let vars = function.vars.len();
+    let mut defined_in: Vec<Vec<BlockId>> = vec![Vec::new(); vars];
+    let mut crosses_blocks = vec![false; vars];
+    for &param in &function.params {
+        defined_in[param.0 as usize].push(BlockId(0));
+    }
+    for &block in cfg.reverse_postorder() {
+        let mut defined_here = HashSet::new();
+        let body = function.block(block);
+        for inst in &body.insts {
+            for operand in inst.kind.operands() {
+                if let Operand::Var(v) = operand
+                    && !defined_here.contains(v)
+                {
+                    crosses_blocks[v.0 as usize] = true;
+                }
+            }
+            if let Some(dest) = inst.kind.dest() {
+                defined_here.insert(dest);
+                if !defined_in[dest.0 as usize].contains(&block) {
+                    defined_in[dest.0 as usize].push(block);
+                }
+            }
+        }
+        for operand in body.terminator.operands() {
+            if let Operand::Var(v) = operand
+                && !defined_here.contains(v)
+            {
+                crosses_blocks[v.0 as usize] = true;
+            }
+        }
+    }
+
+    let mut phis: Vec<Vec<Var>> = vec![Vec::new(); function.blocks.len()];
+    for var in (0..vars as u32).map(Var) {
+        if !crosses_blocks[var.0 as usize] {
+            continue;
+        }
+        let mut worklist = defined_in[var.0 as usize].clone();
+        let mut has_phi = HashSet::new();
+        while let Some(block) = worklist.pop() {
+            for &join in dominators.frontier(block) {
+                if has_phi.insert(join) {
+                    phis[join.0 as usize].push(var);
+                    // a phi is a definition too
+                    if !defined_in[var.0 as usize].contains(&join) {
+                        worklist.push(join);
+                    }
+                }
+            }
+        }
+    }
+    for block in function.block_ids() {
+        let args: Vec<(BlockId, Operand)> = cfg.preds(block).iter().map(|&pred| (pred, Operand::Const(Value::Unassigned))).collect();
+        let span = function.block(block).insts.first().map(|inst| inst.span).unwrap_or(function.span);
+        let leading: Vec<Inst> = phis[block.0 as usize]
+            .iter()
+            .map(|&var| Inst::new(InstKind::Phi { dest: var, args: args.clone() }, span))
+            .collect();
+        function.block_mut(block).insts.splice(0..0, leading);
+    }
+    phis
+}
+
+struct Renamer<'f> {
+    function: &'f mut Function,
+    names: Names,
+    phis: Vec<Vec<Var>>,    // the variable each leading phi of a block is for
+    versions: Vec<Vec<Var>>, // for each variable, the versions whose definitions enclose the block being renamed
+    original_taken: Vec<bool>,
+}
+
+impl<'f> Renamer<'f> {
+    fn new(function: &'f mut Function, phis: Vec<Vec<Var>>) -> Self {
+        let vars = function.vars.len();
+        let mut versions = vec![Vec::new(); vars];
+        let mut original_taken = vec![false; vars];
+        for &param in &function.params {
+            versions[param.0 as usize].push(param);
+            original_taken[param.0 as usize] = true;
+        }
+        let names = Names::new(function);
+        Renamer { function, names, phis, versions, original_taken }
+    }
+
+    fn define(&mut self, var: Var) -> Var {
+        let version = if self.original_taken[var.0 as usize] {
+            let info = self.function.var(var).clone();
+            self.names.fresh(self.function, &info.name, info.ty, info.kind)
+        } else {
+            self.original_taken[var.0 as usize] = true;
+            var
+        };
+        self.versions[var.0 as usize].push(version);
+        version
+    }
+
+    // Walks the dominator tree, so the definitions that enclose a block in the walk are
+    // the ones that reach it
+    fn rename(mut self, cfg: &Cfg, dominators: &Dominators) {
+        let Some(&entry) = dominators.preorder().first() else {
+            return;
+        };
+        let mut defined: Vec<Vec<Var>> = vec![Vec::new(); self.function.blocks.len()];
+        let mut stack = vec![(entry, false)];
+        while let Some((block, done)) = stack.pop() {
+            if done {
+                for var in defined[block.0 as usize].drain(..) {
+                    self.versions[var.0 as usize].pop();
+                }
+                continue;
+            }
+            let phis = self.phis[block.0 as usize].len();
+            for i in 0..self.function.block(block).insts.len() {
+                let kind = &mut self.function.block_mut(block).insts[i].kind;
+                if i >= phis {
+                    for operand in kind.operands_mut() {
+                        use_current(&self.versions, operand);
+                    }
+                }
+                let var = match kind.dest() {
+                    Some(_) if i < phis => self.phis[block.0 as usize][i],
+                    Some(var) => var,
+                    None => continue,
+                };
+                let version = self.define(var);
+                if let Some(dest) = self.function.block_mut(block).insts[i].kind.dest_mut() {
+                    *dest = version;
+                }
+                defined[block.0 as usize].push(var);
+            }
+            for operand in self.function.block_mut(block).terminator.operands_mut() {
+                use_current(&self.versions, operand);
+            }
+
+            // what flows from here into the phis of each successor
+            for &succ in cfg.succs(block) {
+                for i in 0..self.phis[succ.0 as usize].len() {
+                    let value = current(&self.versions, self.phis[succ.0 as usize][i]);
+                    if let InstKind::Phi { args, .. } = &mut self.function.block_mut(succ).insts[i].kind {
+                        for (pred, arg) in args.iter_mut() {
+                            if *pred == block {
+                                *arg = value.clone();
+                            }
+                        }
+                    }
+                }
+            }
+
+            stack.push((block, true));
+            for &child in dominators.children(block).iter().rev() {
+                stack.push((child, false));
+            }
+        }
+    }
+}
+
+// The version of `var` a use in the block being renamed sees; a variable nothing has
+// defined yet reads as unassigned, as at runtime
+fn current(versions: &[Vec<Var>], var: Var) -> Operand {
+    match versions[var.0 as usize].last() {
+        Some(&version) => Operand::Var(version),
+        None => Operand::Const(Value::Unassigned),
+    }
+}
+
+fn use_current(versions: &[Vec<Var>], operand: &mut Operand) {
+    if let Operand::Var(var) = *operand {
+        *operand = current(versions, var);
+    }
+}
+
+pub fn destruct(function: &mut Function) {
+    if function.blocks.is_empty() {
+        return;
+    }
+    // `branch c, bb1, bb1` is one edge, on which the copies go before the branch; it
+    // must not see them
+    for block in &mut function.blocks {
+        if let Terminator::Branch { then_block, else_block, .. } = block.terminator
+            && then_block == else_block
+        {
+            block.terminator = Terminator::Jump(then_block);
+        }
+    }
+    let original_blocks = function.blocks.len();
+    cfg::split_critical_edges(function);
+    let cfg = Cfg::new(function);
+    let mut names = Names::new(function);
+    for block in function.block_ids() {
+        let insts = &mut function.block_mut(block).insts;
+        let leading = insts.iter().take_while(|inst| matches!(inst.kind, InstKind::Phi { .. })).count();
+        if leading == 0 {
+            continue;
+        }
+        let phis: Vec<Inst> = insts.drain(..leading).collect();
+        for &pred in cfg.preds(block) {
+            let copies: Vec<(Var, Operand)> = phis
+                .iter()
+                .filter_map(|phi| match &phi.kind {
+                    InstKind::Phi { dest, args } => {
+                        args.iter().find(|(from, _)| *from == pred).map(|(_, value)| (*dest, value.clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let span = phis[0].span;
+            let copies = sequentialize(function, &mut names, copies);
+            let copies = copies.into_iter().map(|(dest, src)| Inst::new(InstKind::Copy { dest, src }, span));
+            // with critical edges split, either the predecessor leads only here or this
+            // block is reached only from it
+            if cfg.succs(pred).len() == 1 {
+                function.block_mut(pred).insts.extend(copies);
+            } else {
+                function.block_mut(block).insts.splice(0..0, copies);
+            }
+        }
+    }
+
+    // edges split for copies that turned out not to be needed jump straight through again
+    let through: Vec<Option<BlockId>> = function
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| match block.terminator {
+            Terminator::Jump(target) if i >= original_blocks && block.insts.is_empty() => Some(target),
+            _ => None,
+        })
+        .collect();
+    for block in &mut function.blocks {
+        cfg::retarget(block, |target| through[target.0 as usize].unwrap_or(target));
+    }
+    cfg::remove_unreachable(function);
+}
+
+// Orders copies that happen at once so each source is read before it is overwritten
+fn sequentialize(function: &mut Function, names: &mut Names, copies: Vec<(Var, Operand)>) -> Vec<(Var, Operand)> {
+    let mut pending: Vec<(Var, Operand)> = copies.into_iter().filter(|(dest, src)| *src != Operand::Var(*dest)).collect();
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let free = pending
+            .iter()
+            .position(|(dest, _)| !pending.iter().any(|(_, src)| *src == Operand::Var(*dest)));
+        match free {
+            Some(i) => ordered.push(pending.remove(i)),
+            None => {
+                // every destination is still to be read: save one and read the copy instead
+                let dest = pending[0].0;
+                let ty = function.var(dest).ty.clone();
+                let saved = names.fresh(function, "tmp", ty, VarKind::Temp);
+                ordered.push((saved, Operand::Var(dest)));
+                for (_, src) in &mut pending {
+                    if *src == Operand::Var(dest) {
+                        *src = Operand::Var(saved);
+                    }
+                }
+            }
+        }
+    }
+    ordered
+}
+
+// The names a function's Vars use, to make new ones that don't clash
+struct Names {
+    taken: HashSet<String>,
+    next: HashMap<String, u32>, // the number to try first for each base name
+}
+
+impl Names {
+    fn new(function: &Function) -> Self {
+        Names { taken: function.vars.iter().map(|v| v.name.clone()).collect(), next: HashMap::new() }
+    }
+
+    // A new Var named `base.1`, `base.2`, ... whichever is free first
+    fn fresh(&mut self, function: &mut Function, base: &str, ty: Type, kind: VarKind) -> Var {
+        let n = self.next.entry(base.to_string()).or_insert(1);
+        let mut name = format!("{}.{}", base, n);
+        while self.taken.contains(&name) {
+            *n += 1;
+            name = format!("{}.{}", base, n);
+        }
+        *n += 1;
+        self.taken.insert(name.clone());
+        function.vars.push(VarInfo { name, ty, kind });
+        Var(function.vars.len() as u32 - 1)
+    }
+}
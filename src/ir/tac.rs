@@ -0,0 +1,382 @@
+// Three-address code: each function is a list of basic blocks, each block a run of
+// instructions that compute one value into a variable from at most a few operands, ended
+// by a terminator that says where control goes next. Source variables and the
+// temporaries that hold intermediate results are both Vars, typed with the checker's
+// types. Arrays and structs are values, as at runtime: storing into an element builds
+// the updated aggregate (`xs = xs with [i] = v`) instead of writing through a reference,
+// so every change to a variable is a definition of it.
+use crate::interpreter::value::Value;
+use crate::semantics::scope::scope::Type;
+use crate::span::Span;
+use crate::token::TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Var(pub u32); // index into Function::vars
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32); // index into Function::blocks
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FunctionId(pub u32); // index into Module::functions
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobalId(pub u32); // index into Module::globals
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Var(Var),
+    Const(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl UnaryOp {
+    pub fn from_token(kind: &TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::T_MINUS => Some(UnaryOp::Neg),
+            TokenKind::T_NOT => Some(UnaryOp::Not),
+            TokenKind::T_TILDE => Some(UnaryOp::BitNot),
+            _ => None,
+        }
+    }
+
+    // The operator token, for the shared implementation in interpreter::ops
+    pub fn token(self) -> TokenKind {
+        match self {
+            UnaryOp::Neg => TokenKind::T_MINUS,
+            UnaryOp::Not => TokenKind::T_NOT,
+            UnaryOp::BitNot => TokenKind::T_TILDE,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+impl BinaryOp {
+    // `&&`, `||` and `=` are not operations here: they lower to branches and stores
+    pub fn from_token(kind: &TokenKind) -> Option<Self> {
+        Some(match kind {
+            TokenKind::T_PLUS => BinaryOp::Add,
+            TokenKind::T_MINUS => BinaryOp::Sub,
+            TokenKind::T_STAR => BinaryOp::Mul,
+            TokenKind::T_SLASH => BinaryOp::Div,
+            TokenKind::T_PERCENT => BinaryOp::Rem,
+            TokenKind::T_CARET => BinaryOp::Pow,
+            TokenKind::T_AMP => BinaryOp::BitAnd,
+            TokenKind::T_PIPE => BinaryOp::BitOr,
+            TokenKind::T_LSHIFT => BinaryOp::Shl,
+            TokenKind::T_RSHIFT => BinaryOp::Shr,
+            TokenKind::T_EQUALSOP => BinaryOp::Eq,
+            TokenKind::T_NEQ => BinaryOp::Ne,
+            TokenKind::T_LT => BinaryOp::Lt,
+            TokenKind::T_GT => BinaryOp::Gt,
+            TokenKind::T_LTE => BinaryOp::Le,
+            TokenKind::T_GTE => BinaryOp::Ge,
+            _ => return None,
+        })
+    }
+
+    pub fn token(self) -> TokenKind {
+        match self {
+            BinaryOp::Add => TokenKind::T_PLUS,
+            BinaryOp::Sub => TokenKind::T_MINUS,
+            BinaryOp::Mul => TokenKind::T_STAR,
+            BinaryOp::Div => TokenKind::T_SLASH,
+            BinaryOp::Rem => TokenKind::T_PERCENT,
+            BinaryOp::Pow => TokenKind::T_CARET,
+            BinaryOp::BitAnd => TokenKind::T_AMP,
+            BinaryOp::BitOr => TokenKind::T_PIPE,
+            BinaryOp::Shl => TokenKind::T_LSHIFT,
+            BinaryOp::Shr => TokenKind::T_RSHIFT,
+            BinaryOp::Eq => TokenKind::T_EQUALSOP,
+            BinaryOp::Ne => TokenKind::T_NEQ,
+            BinaryOp::Lt => TokenKind::T_LT,
+            BinaryOp::Gt => TokenKind::T_GT,
+            BinaryOp::Le => TokenKind::T_LTE,
+            BinaryOp::Ge => TokenKind::T_GTE,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+        }
+    }
+}
+
+// One step from an aggregate to one of its elements
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    Index(Operand),
+    Field(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Inst {
+    pub kind: InstKind,
+    pub span: Span, // the expression or statement it was lowered from
+}
+
+#[derive(Debug, Clone)]
+pub enum InstKind {
+    Copy { dest: Var, src: Operand },
+    Unary { dest: Var, op: UnaryOp, operand: Operand },
+    Binary { dest: Var, op: BinaryOp, left: Operand, right: Operand },
+    Cast { dest: Var, operand: Operand }, // to the type of dest
+    Call { dest: Option<Var>, function: FunctionId, args: Vec<Operand> }, // no dest when the result is unused
+    Extract { dest: Var, aggregate: Operand, access: Access }, // dest = aggregate[i] or aggregate.f
+    Insert { dest: Var, aggregate: Operand, access: Access, value: Operand }, // dest = aggregate with that element replaced
+    Array { dest: Var, elements: Vec<Operand> },
+    Struct { dest: Var, name: String, fields: Vec<(String, Operand)> }, // declaration order
+    GetGlobal { dest: Var, global: GlobalId },
+    SetGlobal { global: GlobalId, value: Operand },
+    // only in SSA form, at the start of a block: the value from whichever predecessor
+    // control came from
+    Phi { dest: Var, args: Vec<(BlockId, Operand)> },
+}
+
+impl Inst {
+    pub fn new(kind: InstKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl InstKind {
+    // The variable this instruction defines, if any
+    pub fn dest(&self) -> Option<Var> {
+        match self {
+            InstKind::Copy { dest, .. }
+            | InstKind::Unary { dest, .. }
+            | InstKind::Binary { dest, .. }
+            | InstKind::Cast { dest, .. }
+            | InstKind::Extract { dest, .. }
+            | InstKind::Insert { dest, .. }
+            | InstKind::Array { dest, .. }
+            | InstKind::Struct { dest, .. }
+            | InstKind::GetGlobal { dest, .. }
+            | InstKind::Phi { dest, .. } => Some(*dest),
+            InstKind::Call { dest, .. } => *dest,
+            InstKind::SetGlobal { .. } => None,
+        }
+    }
+
+    pub fn dest_mut(&mut self) -> Option<&mut Var> {
+        match self {
+            InstKind::Copy { dest, .. }
+            | InstKind::Unary { dest, .. }
+            | InstKind::Binary { dest, .. }
+            | InstKind::Cast { dest, .. }
+            | InstKind::Extract { dest, .. }
+            | InstKind::Insert { dest, .. }
+            | InstKind::Array { dest, .. }
+            | InstKind::Struct { dest, .. }
+            | InstKind::GetGlobal { dest, .. }
+            | InstKind::Phi { dest, .. } => Some(dest),
+            InstKind::Call { dest, .. } => dest.as_mut(),
+            InstKind::SetGlobal { .. } => None,
+        }
+    }
+
+    // The operands this instruction reads, in evaluation order
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            InstKind::Copy { src, .. } => vec![src],
+            InstKind::Unary { operand, .. } | InstKind::Cast { operand, .. } => vec![operand],
+            InstKind::Binary { left, right, .. } => vec![left, right],
+            InstKind::Call { args, .. } | InstKind::Array { elements: args, .. } => args.iter().collect(),
+            InstKind::Extract { aggregate, access, .. } => match access {
+                Access::Index(index) => vec![aggregate, index],
+                Access::Field(_) => vec![aggregate],
+            },
+            InstKind::Insert { aggregate, access, value, .. } => match access {
+                Access::Index(index) => vec![aggregate, index, value],
+                Access::Field(_) => vec![aggregate, value],
+            },
+            InstKind::Struct { fields, .. } => fields.iter().map(|(_, value)| value).collect(),
+            InstKind::GetGlobal { .. } => Vec::new(),
+            InstKind::SetGlobal { value, .. } => vec![value],
+            InstKind::Phi { args, .. } => args.iter().map(|(_, value)| value).collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            InstKind::Copy { src, .. } => vec![src],
+            InstKind::Unary { operand, .. } | InstKind::Cast { operand, .. } => vec![operand],
+            InstKind::Binary { left, right, .. } => vec![left, right],
+            InstKind::Call { args, .. } | InstKind::Array { elements: args, .. } => args.iter_mut().collect(),
+            InstKind::Extract { aggregate, access, .. } => match access {
+                Access::Index(index) => vec![aggregate, index],
+                Access::Field(_) => vec![aggregate],
+            },
+            InstKind::Insert { aggregate, access, value, .. } => match access {
+                Access::Index(index) => vec![aggregate, index, value],
+                Access::Field(_) => vec![aggregate, value],
+            },
+            InstKind::Struct { fields, .. } => fields.iter_mut().map(|(_, value)| value).collect(),
+            InstKind::GetGlobal { .. } => Vec::new(),
+            InstKind::SetGlobal { value, .. } => vec![value],
+            InstKind::Phi { args, .. } => args.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+
+    // Makes a phi that names `from` as a predecessor name `to` instead
+    pub fn rename_phi_pred(&mut self, from: BlockId, to: BlockId) {
+        if let InstKind::Phi { args, .. } = self {
+            for (pred, _) in args {
+                if *pred == from {
+                    *pred = to;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch { condition: Operand, then_block: BlockId, else_block: BlockId },
+    Return(Option<Operand>),
+    Unreachable, // the checker has shown control never gets here, e.g. the end of a function that returns a value
+}
+
+impl Terminator {
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Param,
+    Local, // declared in the source
+    Temp,  // introduced by the lowering
+}
+
+#[derive(Debug, Clone)]
+pub struct VarInfo {
+    pub name: String, // unique within the function, e.g. `x.1` for a second `x`
+    pub ty: Type,
+    pub kind: VarKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Var>,
+    pub return_type: Type,
+    pub vars: Vec<VarInfo>,
+    pub blocks: Vec<BasicBlock>, // blocks[0] is the entry; empty for a prototype that is never defined
+    pub span: Span,
+}
+
+impl Function {
+    pub fn var(&self, var: Var) -> &VarInfo {
+        &self.vars[var.0 as usize]
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut BasicBlock {
+        &mut self.blocks[id.0 as usize]
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> + use<> {
+        (0..self.blocks.len() as u32).map(BlockId)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    // the global initializers and top-level statements in source order, then a call of
+    // `main` if there is one without parameters; what it returns is the program's result
+    pub script: Function,
+    pub globals: Vec<Global>,
+}
+
+impl Module {
+    pub fn function(&self, id: FunctionId) -> &Function {
+        &self.functions[id.0 as usize]
+    }
+}
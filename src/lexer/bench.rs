@@ -0,0 +1,71 @@
+// Throughput benchmark of HandLexer against RegexLexer on large generated sources.
+// Run it with optimisations, e.g. `cargo run --release -- --bench-lexers 8`.
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::lexer::{HandLexer, Lexer, RegexLexer};
+
+pub struct BenchResult {
+    pub lexer: &'static str,
+    pub bytes: usize,
+    pub tokens: usize,
+    pub best: Duration, // fastest of all iterations
+}
+
+impl BenchResult {
+    pub fn mb_per_sec(&self) -> f64 {
+        self.bytes as f64 / (1024.0 * 1024.0) / self.best.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<6} {:>10} tokens  {:>9.2} ms  {:>8.2} MB/s",
+               self.lexer, self.tokens, self.best.as_secs_f64() * 1000.0, self.mb_per_sec())
+    }
+}
+
+// A realistic mix of declarations, expressions, strings and comments repeated (with
+// fresh identifiers) until the source is at least `target_bytes` long.
+pub fn synthetic_source(target_bytes: usize) -> String {
+    let mut src = String::with_capacity(target_bytes + 512);
+    let mut i = 0;
+    while src.len() < target_bytes {
+        src.push_str(&format!(
+            "// helper number {i}\n\
+             fn func_{i}(int a_{i}, float b_{i}): int {{\n\
+             \x20   int x_{i} = a_{i} * 42 + {i};\n\
+             \x20   string s_{i} = \"value\\t{i}\\n\";\n\
+             \x20   /* compare and shift */\n\
+             \x20   if (x_{i} >= 10 && b_{i} != 0.5) {{ return x_{i} << 2; }} else {{ return -x_{i}; }}\n\
+             \x20   while (x_{i} > 0) {{ x_{i} = x_{i} - 1; }}\n\
+             }}\n\n"
+        ));
+        i += 1;
+    }
+    src
+}
+
+fn time<F: FnMut() -> usize>(iterations: usize, mut f: F) -> (usize, Duration) {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        tokens = f();
+        best = best.min(start.elapsed());
+    }
+    (tokens, best)
+}
+
+pub fn run(target_bytes: usize, iterations: usize) -> Vec<BenchResult> {
+    let src = synthetic_source(target_bytes);
+    let (hand_tokens, hand_best) = time(iterations, || {
+        HandLexer::new(&src).tokenize().expect("benchmark source must lex").len()
+    });
+    let (regex_tokens, regex_best) = time(iterations, || {
+        RegexLexer::new(&src).tokenize().expect("benchmark source must lex").len()
+    });
+    vec![
+        BenchResult { lexer: "hand", bytes: src.len(), tokens: hand_tokens, best: hand_best },
+        BenchResult { lexer: "regex", bytes: src.len(), tokens: regex_tokens, best: regex_best },
+    ]
+}
@@ -0,0 +1,150 @@
+// Differential harness: runs HandLexer and RegexLexer over the same input and reports
+// the first token (or error) where their outputs disagree.
+use std::fmt;
+use crate::lexer::{HandLexer, LexError, Lexer, RegexLexer};
+use crate::span::line_col;
+use crate::token::Token;
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub offset: usize, // byte offset in the input where the outputs stop agreeing
+    pub line: usize,
+    pub col: usize,
+    pub hand: String,  // what HandLexer produced at that point
+    pub regex: String, // what RegexLexer produced at that point
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "first divergence at {}:{}: hand lexer gave {}, regex lexer gave {}",
+               self.line, self.col, self.hand, self.regex)
+    }
+}
+
+fn describe_token(t: &Token) -> String {
+    format!("{} at {}:{} (bytes {})", t.kind, t.line, t.col, t.span)
+}
+
+fn describe_error(e: &LexError) -> String {
+    format!("error `{}` (bytes {})", e, e.span)
+}
+
+fn same_token(a: &Token, b: &Token) -> bool {
+    a.kind == b.kind && a.line == b.line && a.col == b.col && a.span == b.span
+}
+
+fn divergence(input: &str, offset: usize, hand: String, regex: String) -> Divergence {
+    let (line, col) = line_col(input, offset);
+    Divergence { offset, line, col, hand, regex }
+}
+
+// Lexes `input` with both lexers; None means the outputs are identical.
+pub fn first_divergence(input: &str) -> Option<Divergence> {
+    let hand = HandLexer::new(input).tokenize();
+    let regex = RegexLexer::new(input).tokenize();
+
+    match (hand, regex) {
+        (Ok(h), Ok(r)) => {
+            for (a, b) in h.iter().zip(r.iter()) {
+                if !same_token(a, b) {
+                    let offset = a.span.start.min(b.span.start);
+                    return Some(divergence(input, offset, describe_token(a), describe_token(b)));
+                }
+            }
+            // both streams end in T_EOF, so equal prefixes imply equal lengths
+            None
+        }
+        (Err(h), Err(r)) => {
+            if h.to_string() == r.to_string() && h.span == r.span {
+                None
+            } else {
+                let offset = h.span.start.min(r.span.start);
+                Some(divergence(input, offset, describe_error(&h), describe_error(&r)))
+            }
+        }
+        (Ok(h), Err(r)) => {
+            let at = h.iter().find(|t| t.span.start >= r.span.start).unwrap_or(h.last().unwrap());
+            Some(divergence(input, r.span.start, describe_token(at), describe_error(&r)))
+        }
+        (Err(h), Ok(r)) => {
+            let at = r.iter().find(|t| t.span.start >= h.span.start).unwrap_or(r.last().unwrap());
+            Some(divergence(input, h.span.start, describe_error(&h), describe_token(at)))
+        }
+    }
+}
+
+// Pieces the generator glues together. Besides ordinary tokens this includes the
+// spots where the two lexers are most likely to disagree: keyword prefixes,
+// non-ASCII identifiers, escapes, comments and numbers running into letters.
+const FRAGMENTS: &[&str] = &[
+    "fn", "int", "float", "bool", "string", "char", "struct", "return", "break", "if", "else", "for", "while",
+    "true", "false", "fnord", "iffy", "int2", "structs", "_tmp", "x", "héllo", "变量", "truex",
+    "0", "7", "42", "1234567890", "3.14", "0.5", "1.", "99999999999999999999",
+    "0x1F", "0xff_ff", "0o17", "0b1010", "1_000", "1.5e-3", "2E10", "1e+", "1e", "0x", "0o8", "0b102",
+    "0xFFFFFFFFFFFFFFFF", "1e999", "0x1g", "9_223_372_036_854_775_807",
+    "\"\"", "\"abc\"", "\"a\\nb\"", "\"tab\\t\"", "\"q\\\"q\"", "\"\\x41\"", "\"\\u00e9\"", "\"\\z\"",
+    "'a'", "'\\n'", "'\\''", "'\\x41'", "'\\u00e9'", "'é'", "''", "'ab'", "'\\xZZ'", "'x", "'\\", "char",
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "=", "<", ">", "+", "-", "*", "/", "%",
+    "^", "&", "|", "~", "!", "(", ")", "{", "}", "[", "]", ",", ";", ":", ".",
+    "// line comment\n", "/* block */", "/* multi\nline */",
+];
+
+const SEPARATORS: &[&str] = &[" ", " ", " ", "\n", "\t", "", "  \n  "];
+
+// xorshift64*, good enough to get reproducible variety without extra dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+// Builds a pseudo-random input of `fragments` pieces; the same seed gives the same input.
+pub fn generate_input(seed: u64, fragments: usize) -> String {
+    let mut rng = Rng(seed.wrapping_mul(2).wrapping_add(0x9E37_79B9_7F4A_7C15) | 1);
+    let mut out = String::new();
+    for _ in 0..fragments {
+        out.push_str(rng.pick(FRAGMENTS));
+        out.push_str(rng.pick(SEPARATORS));
+    }
+    out
+}
+
+// Runs both lexers over every corpus entry (name, source) and over `generated`
+// generated inputs, returning the name and first divergence of each failing input.
+pub fn run(corpus: &[(String, String)], generated: usize, seed: u64) -> Vec<(String, String, Divergence)> {
+    let mut failures = Vec::new();
+    for (name, src) in corpus {
+        if let Some(d) = first_divergence(src) {
+            failures.push((name.clone(), src.clone(), d));
+        }
+    }
+    for i in 0..generated as u64 {
+        let src = generate_input(seed.wrapping_add(i), 40);
+        if let Some(d) = first_divergence(&src) {
+            failures.push((format!("generated #{} (seed {})", i, seed.wrapping_add(i)), src, d));
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lexers_agree_on_sample_and_generated_inputs() {
+        let corpus = vec![("sample.src".to_string(), include_str!("../../sample.src").to_string())];
+        let failures = run(&corpus, 500, 1);
+        let report: Vec<String> = failures.iter().map(|(name, src, d)| format!("{}: {}\n  input: {:?}", name, d, src)).collect();
+        assert!(failures.is_empty(), "{}", report.join("\n"));
+    }
+}
@@ -1,36 +1,106 @@
 #![allow(unused)]
 use std::fmt;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+
 #[derive(Debug)]
-pub enum LexError {
-    UnexpectedChar(char, usize, usize),
-    UnterminatedString(usize, usize),
-    UnterminatedComment(usize, usize),
-    InvalidEscape(String, usize, usize),
-    InvalidNumber(usize, usize),
-    InvalidIdentifierStart(char, usize, usize),
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub line: usize,
+    pub col: usize,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum LexErrorKind {
+    UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedChar,
+    UnterminatedComment,
+    InvalidEscape(String),
+    InvalidCharLiteral(String), // e.g. "empty character literal"
+    InvalidNumber,
+    MalformedNumber(String), // what is wrong with the literal, e.g. "invalid digit '8' in octal literal"
+    NumberOverflow(String),  // the literal text, which does not fit in an i64 / f64
+    InvalidIdentifierStart(char),
+}
+
+impl LexError {
+    pub fn new(kind: LexErrorKind, line: usize, col: usize, span: Span) -> Self {
+        Self { kind, line, col, span }
+    }
 }
 
 impl fmt::Display for LexError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            LexError::UnexpectedChar(c, l, ccol) => {
+        let (l, ccol) = (self.line, self.col);
+        match &self.kind {
+            LexErrorKind::UnexpectedChar(c) => {
                 write!(f, "Unexpected character '{}' at {}:{}", c, l, ccol)
             }
-            LexError::UnterminatedString(l, ccol) => {
+            LexErrorKind::UnterminatedString => {
                 write!(f, "Unterminated string starting at {}:{}", l, ccol)
             }
-            LexError::UnterminatedComment(l, ccol) => {
+            LexErrorKind::UnterminatedChar => {
+                write!(f, "Unterminated character literal starting at {}:{}", l, ccol)
+            }
+            LexErrorKind::UnterminatedComment => {
                 write!(f, "Unterminated comment starting at {}:{}", l, ccol)
             }
-            LexError::InvalidEscape(s, l, ccol) => {
+            LexErrorKind::InvalidEscape(s) => {
                 write!(f, "Invalid escape {} at {}:{}", s, l, ccol)
             }
-            LexError::InvalidNumber(l, ccol) => {
+            LexErrorKind::InvalidCharLiteral(why) => {
+                write!(f, "Invalid character literal ({}) at {}:{}", why, l, ccol)
+            }
+            LexErrorKind::InvalidNumber => {
                 write!(f, "Invalid number starting at {}:{}", l, ccol)
             }
-            LexError::InvalidIdentifierStart(ch, l, ccol) => {
+            LexErrorKind::MalformedNumber(why) => {
+                write!(f, "Malformed number ({}) at {}:{}", why, l, ccol)
+            }
+            LexErrorKind::NumberOverflow(lit) => {
+                write!(f, "Number literal {} out of range at {}:{}", lit, l, ccol)
+            }
+            LexErrorKind::InvalidIdentifierStart(ch) => {
                 write!(f, "Invalid identifier start '{}' at {}:{}", ch, l, ccol)
             }
         }
     }
 }
+
+impl From<&LexError> for Diagnostic {
+    fn from(e: &LexError) -> Self {
+        let span = e.span;
+        match &e.kind {
+            LexErrorKind::UnexpectedChar(c) => {
+                Diagnostic::error("E0101", format!("unexpected character '{}'", c), span)
+            }
+            LexErrorKind::UnterminatedString => Diagnostic::error("E0102", "unterminated string literal", span)
+                .with_label("string starts here")
+                .with_help("close the string with '\"'"),
+            LexErrorKind::UnterminatedChar => Diagnostic::error("E0103", "unterminated character literal", span)
+                .with_help("close the character literal with '\''"),
+            LexErrorKind::UnterminatedComment => Diagnostic::error("E0104", "unterminated block comment", span)
+                .with_label("comment starts here")
+                .with_help("close the comment with '*/'"),
+            LexErrorKind::InvalidEscape(s) => Diagnostic::error("E0105", format!("invalid escape {}", s), span)
+                .with_note("\\xHH takes two hex digits and \\uXXXX takes four"),
+            LexErrorKind::InvalidCharLiteral(why) => {
+                Diagnostic::error("E0106", "invalid character literal", span).with_label(why.clone())
+            }
+            LexErrorKind::InvalidNumber => Diagnostic::error("E0107", "invalid number", span),
+            LexErrorKind::MalformedNumber(why) => {
+                Diagnostic::error("E0108", "malformed number", span).with_label(why.clone())
+            }
+            LexErrorKind::NumberOverflow(lit) => {
+                Diagnostic::error("E0109", format!("number literal {} out of range", lit), span)
+                    .with_note("integers are 64-bit signed and floats 64-bit")
+            }
+            LexErrorKind::InvalidIdentifierStart(ch) => {
+                Diagnostic::error("E0110", format!("invalid start of identifier '{}'", ch), span)
+                    .with_help("identifiers start with a letter or '_'")
+            }
+        }
+    }
+}
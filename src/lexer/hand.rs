@@ -1,37 +1,57 @@
 #![allow(unused)]
 use std::fmt;
-use crate::lexer::LexError;
+use crate::lexer::{number, LexError, LexErrorKind, Lexer};
+use crate::span::{FileId, Span};
 use crate::token as tk;
-use tk::{Token, TokenKind};
+use tk::{Token, TokenKind, Trivia, TriviaKind};
 
 pub struct HandLexer<'a> {
     input: &'a str,   // original input string
     chars: Vec<char>, // input as char vector for easy indexing
     pos: usize,       // currrent position in chars
+    offset: usize,    // current byte offset into input (for spans)
     line: usize,      // error reporting: current line (1-based)
     col: usize,       // error reporting: current column (1-based)
+    file: FileId,     // file the spans point into
 }
 
 impl<'a> HandLexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_file(input, 0)
+    }
+
+    pub fn with_file(input: &'a str, file: FileId) -> Self {
         let chars: Vec<char> = input.chars().collect();
         Self {
             input,
             chars,
             pos: 0,
+            offset: 0,
             line: 1,
             col: 1,
+            file,
         }
     }
 
     fn eof(&self) -> bool {
-        return self.pos >= self.chars.len();
+        self.pos >= self.chars.len()
     }
     fn peek(&self) -> Option<char> {
-        return self.chars.get(self.pos).copied(); // return a copy of the char or None if eof
+        self.chars.get(self.pos).copied() // return a copy of the char or None if eof
     }
     fn peek_n(&self, n: usize) -> Option<char> {
-        return self.chars.get(self.pos + n).copied(); // return a copy of the char
+        self.chars.get(self.pos + n).copied() // return a copy of the char
+    }
+
+    // span from a byte offset recorded earlier up to the current position
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.file, start, self.offset)
+    }
+
+    // error pointing at the current character
+    fn error_here(&self, kind: LexErrorKind) -> LexError {
+        let len = self.peek().map(|c| c.len_utf8()).unwrap_or(0);
+        LexError::new(kind, self.line, self.col, Span::new(self.file, self.offset, self.offset + len))
     }
 
     fn advance(&mut self) -> Option<char> {
@@ -41,28 +61,32 @@ impl<'a> HandLexer<'a> {
         }
         let ch = self.chars[self.pos];
         self.pos += 1;
+        self.offset += ch.len_utf8();
         if ch == '\n' {
             self.line += 1;
             self.col = 1;
         } else {
             self.col += 1;
         }
-        return Some(ch); // return the character we advanced over if eof return None
+        Some(ch) // return the character we advanced over if eof return None
     }
 
-    fn skip_whitespace_and_comments(&mut self) -> Result<(), LexError> {
-        // returns ok if success and err if fail
-        loop {
-            let mut progressed = false;
-            // whitespace
-            while matches!(self.peek(), Some(c) if c.is_whitespace()) {
-                // if peek is whitespace
-                progressed = true;
+    // lexes one piece of whitespace or comment at the current position, if there is one
+    fn next_trivia(&mut self) -> Result<Option<Trivia>, LexError> {
+        let start = self.offset;
+        let kind = match (self.peek(), self.peek_n(1)) {
+            (Some('\n'), _) => {
                 self.advance();
+                TriviaKind::Newline
+            }
+            (Some(c), _) if c.is_whitespace() => {
+                while matches!(self.peek(), Some(c) if c.is_whitespace() && c != '\n') {
+                    self.advance();
+                }
+                TriviaKind::Whitespace
             }
             // line comment //
-            if self.peek() == Some('/') && self.peek_n(1) == Some('/') {
-                progressed = true;
+            (Some('/'), Some('/')) => {
                 self.advance();
                 self.advance();
                 while let Some(c) = self.peek() {
@@ -72,17 +96,22 @@ impl<'a> HandLexer<'a> {
                     }
                     self.advance();
                 }
+                TriviaKind::LineComment
             }
             // block comment /* ... */
-            else if self.peek() == Some('/') && self.peek_n(1) == Some('*') {
-                progressed = true;
+            (Some('/'), Some('*')) => {
                 let start_line = self.line;
                 let start_col = self.col;
                 self.advance(); // /
                 self.advance(); // *
                 loop {
                     if self.eof() {
-                        return Err(LexError::UnterminatedComment(start_line, start_col));
+                        return Err(LexError::new(
+                            LexErrorKind::UnterminatedComment,
+                            start_line,
+                            start_col,
+                            self.span_from(start),
+                        ));
                     }
                     if self.peek() == Some('*') && self.peek_n(1) == Some('/') {
                         self.advance();
@@ -92,241 +121,232 @@ impl<'a> HandLexer<'a> {
                         self.advance();
                     }
                 }
+                TriviaKind::BlockComment
             }
-            if !progressed {
+            _ => return Ok(None),
+        };
+        Ok(Some(Trivia { kind, span: self.span_from(start) }))
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), LexError> {
+        // returns ok if success and err if fail
+        while self.next_trivia()?.is_some() {}
+        Ok(())
+    }
+
+    // Lossless mode: like tokenize, but whitespace and comments are kept on the tokens.
+    // Trivia on the rest of a token's line is trailing trivia of that token; from the next
+    // newline on it is leading trivia of the following token (or of T_EOF). Writing out
+    // every token's leading trivia, text and trailing trivia gives back the input exactly.
+    pub fn tokenize_with_trivia(&mut self) -> Result<Vec<Token>, LexError> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut leading = Vec::new();
+
+        loop {
+            while let Some(trivia) = self.next_trivia()? {
+                leading.push(trivia);
+            }
+            if self.eof() {
                 break;
             }
+            let first = tokens.len();
+            self.lex_token(&mut tokens)?; // a string pushes three tokens
+            tokens[first].leading = std::mem::take(&mut leading);
+
+            let last = tokens.len() - 1;
+            while self.peek() != Some('\n') {
+                match self.next_trivia()? {
+                    Some(trivia) => tokens[last].trailing.push(trivia),
+                    None => break,
+                }
+            }
         }
-        Ok(())
+
+        let mut eof = Token::new(TokenKind::T_EOF, self.line, self.col, self.span_from(self.offset));
+        eof.leading = leading;
+        tokens.push(eof);
+        Ok(tokens)
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, LexError> {
+    // Recovery mode: instead of stopping at the first LexError, emit a T_ERROR token
+    // covering the bad input, record the error, resync and keep going.
+    pub fn tokenize_recovering(&mut self) -> (Vec<Token>, Vec<LexError>) {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
         while !self.eof() {
-            self.skip_whitespace_and_comments()?; // ? means if error return it to the main function
+            if let Err(e) = self.skip_whitespace_and_comments() {
+                // only an unterminated block comment gets here; it ran to eof
+                tokens.push(Token::new(TokenKind::T_ERROR, e.line, e.col, e.span));
+                errors.push(e);
+                break;
+            }
             if self.eof() {
                 break;
             }
-
-            let ch = self.peek().unwrap(); // we safely can unwrap because we checked eof (unwrapping option returns the value inside or panics if None)
-            let (line, col) = (self.line, self.col);
-
-            // Strings (we emit T_QUOTES, T_STRINGLIT, T_QUOTES)
-            if ch == '"' {
-                tokens.push(Token::new(TokenKind::T_QUOTES, line, col));
-                self.advance(); // consume opening "
-                let s = self.read_string_contents()?;
-                tokens.push(Token::new(TokenKind::T_STRINGLIT(s), line, col));
-                // closing quote
-                if self.peek() == Some('"') {
-                    tokens.push(Token::new(TokenKind::T_QUOTES, self.line, self.col));
-                    self.advance();
-                } else {
-                    return Err(LexError::UnterminatedString(line, col));
-                }
-                continue; // we need to check eof again after reading string
+            let mark = tokens.len();
+            let saved = (self.pos, self.offset, self.line, self.col);
+            if let Err(e) = self.lex_token(&mut tokens) {
+                tokens.truncate(mark); // a broken string may already have pushed its opening quote
+                (self.pos, self.offset, self.line, self.col) = saved;
+                let (_, start, line, col) = saved;
+                self.resync(&e);
+                tokens.push(Token::new(TokenKind::T_ERROR, line, col, self.span_from(start)));
+                errors.push(e);
             }
+        }
 
-            // two-char operators (longest-match)
-            if let (Some(a), Some(b)) = (self.peek(), self.peek_n(1)) {
-                match (a, b) {
-                    ('=', '=') => {
-                        tokens.push(Token::new(TokenKind::T_EQUALSOP, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
-                    }
-                    ('!', '=') => {
-                        tokens.push(Token::new(TokenKind::T_NEQ, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
-                    }
-                    ('<', '=') => {
-                        tokens.push(Token::new(TokenKind::T_LTE, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
-                    }
-                    ('>', '=') => {
-                        tokens.push(Token::new(TokenKind::T_GTE, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
-                    }
-                    ('&', '&') => {
-                        tokens.push(Token::new(TokenKind::T_ANDAND, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
-                    }
-                    ('|', '|') => {
-                        tokens.push(Token::new(TokenKind::T_OROR, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
+        tokens.push(Token::new(TokenKind::T_EOF, self.line, self.col, self.span_from(self.offset)));
+        (tokens, errors)
+    }
+
+    // skips past the input that caused `err` so lexing can continue after it
+    fn resync(&mut self, err: &LexError) {
+        let start = self.offset;
+        match err.kind {
+            LexErrorKind::UnterminatedString | LexErrorKind::InvalidEscape(_) |
+            LexErrorKind::UnterminatedChar | LexErrorKind::InvalidCharLiteral(_) => {
+                // drop the whole literal, stopping at the closing quote or the end of the line
+                let quote = self.advance().unwrap(); // opening " or '
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
                     }
-                    ('<', '<') => {
-                        tokens.push(Token::new(TokenKind::T_LSHIFT, line, col));
-                        self.advance();
-                        self.advance();
-                        continue;
+                    self.advance();
+                    if c == quote {
+                        break;
                     }
-                    ('>', '>') => {
-                        tokens.push(Token::new(TokenKind::T_RSHIFT, line, col));
-                        self.advance();
+                    if c == '\\' && self.peek().is_some_and(|n| n != '\n') {
                         self.advance();
-                        continue;
                     }
-                    _ => {}
                 }
             }
-
-            // single-char punctuation & operators
-            match ch {
-                '(' => {
-                    tokens.push(Token::new(TokenKind::T_PARENL, line, col));
-                    self.advance();
-                    continue;
-                }
-                ')' => {
-                    tokens.push(Token::new(TokenKind::T_PARENR, line, col));
-                    self.advance();
-                    continue;
-                }
-                '{' => {
-                    tokens.push(Token::new(TokenKind::T_BRACEL, line, col));
-                    self.advance();
-                    continue;
-                }
-                '}' => {
-                    tokens.push(Token::new(TokenKind::T_BRACER, line, col));
-                    self.advance();
-                    continue;
-                }
-                '[' => {
-                    tokens.push(Token::new(TokenKind::T_BRACKETL, line, col));
-                    self.advance();
-                    continue;
-                }
-                ']' => {
-                    tokens.push(Token::new(TokenKind::T_BRACKETR, line, col));
-                    self.advance();
-                    continue;
-                }
-                ',' => {
-                    tokens.push(Token::new(TokenKind::T_COMMA, line, col));
-                    self.advance();
-                    continue;
-                }
-                ';' => {
-                    tokens.push(Token::new(TokenKind::T_SEMICOLON, line, col));
-                    self.advance();
-                    continue;
-                }
-                ':' => {
-                    tokens.push(Token::new(TokenKind::T_COLON, line, col));
-                    self.advance();
-                    continue;
-                }
-                '.' => {
-                    tokens.push(Token::new(TokenKind::T_DOT, line, col));
-                    self.advance();
-                    continue;
-                }
-                '=' => {
-                    tokens.push(Token::new(TokenKind::T_ASSIGNOP, line, col));
-                    self.advance();
-                    continue;
-                }
-                '+' => {
-                    tokens.push(Token::new(TokenKind::T_PLUS, line, col));
-                    self.advance();
-                    continue;
-                }
-                '-' => {
-                    tokens.push(Token::new(TokenKind::T_MINUS, line, col));
-                    self.advance();
-                    continue;
-                }
-                '*' => {
-                    tokens.push(Token::new(TokenKind::T_STAR, line, col));
-                    self.advance();
-                    continue;
-                }
-                '/' => {
-                    tokens.push(Token::new(TokenKind::T_SLASH, line, col));
-                    self.advance();
-                    continue;
-                }
-                '%' => {
-                    tokens.push(Token::new(TokenKind::T_PERCENT, line, col));
-                    self.advance();
-                    continue;
-                }
-                '^' => {
-                    tokens.push(Token::new(TokenKind::T_CARET, line, col));
-                    self.advance();
-                    continue;
-                }
-                '&' => {
-                    tokens.push(Token::new(TokenKind::T_AMP, line, col));
-                    self.advance();
-                    continue;
-                }
-                '|' => {
-                    tokens.push(Token::new(TokenKind::T_PIPE, line, col));
-                    self.advance();
-                    continue;
-                }
-                '~' => {
-                    tokens.push(Token::new(TokenKind::T_TILDE, line, col));
-                    self.advance();
-                    continue;
-                }
-                '!' => {
-                    tokens.push(Token::new(TokenKind::T_NOT, line, col));
-                    self.advance();
-                    continue;
-                }
-                '<' => {
-                    tokens.push(Token::new(TokenKind::T_LT, line, col));
+            LexErrorKind::InvalidNumber | LexErrorKind::MalformedNumber(_) | LexErrorKind::NumberOverflow(_) => {
+                // drop the rest of the malformed literal (e.g. `12abc`, `1.5x`, `0o78`, `1e+`)
+                while self.offset < err.span.end {
                     self.advance();
-                    continue;
                 }
-                '>' => {
-                    tokens.push(Token::new(TokenKind::T_GT, line, col));
+                while matches!(self.peek(), Some(c) if is_identifier_part(c) || c == '.') {
                     self.advance();
-                    continue;
                 }
-                _ => {}
             }
+            _ => {}
+        }
+        if self.offset == start {
+            self.advance(); // always make progress
+        }
+    }
 
-            // numbers (digit start) first read the number if it starts with a digit
-            if ch.is_ascii_digit() {
-                let tok = self.read_number()?;
-                tokens.push(tok);
-                continue;
-            }
+    // lexes the token(s) at the current position; strings push three tokens
+    fn lex_token(&mut self, tokens: &mut Vec<Token>) -> Result<(), LexError> {
+        let ch = self.peek().unwrap(); // we safely can unwrap because we checked eof (unwrapping option returns the value inside or panics if None)
+        let (line, col) = (self.line, self.col);
+        let start = self.offset;
 
-            // identifier or keyword (unicode friendly)
-            if is_identifier_start(ch) {
-                let tok = self.read_identifier_or_keyword();
-                tokens.push(tok);
-                continue;
+        // Strings (we emit T_QUOTES, T_STRINGLIT, T_QUOTES)
+        if ch == '"' {
+            self.advance(); // consume opening "
+            tokens.push(Token::new(TokenKind::T_QUOTES, line, col, self.span_from(start)));
+            let contents_start = self.offset;
+            let s = self.read_string_contents().map_err(|e| match e.kind {
+                // report a runaway string at its opening quote, not at eof
+                LexErrorKind::UnterminatedString => {
+                    LexError::new(LexErrorKind::UnterminatedString, line, col, self.span_from(start))
+                }
+                _ => e,
+            })?;
+            tokens.push(Token::new(TokenKind::T_STRINGLIT(s), line, col, self.span_from(contents_start)));
+            // closing quote
+            if self.peek() == Some('"') {
+                let (close_line, close_col, close_start) = (self.line, self.col, self.offset);
+                self.advance();
+                tokens.push(Token::new(TokenKind::T_QUOTES, close_line, close_col, self.span_from(close_start)));
+            } else {
+                return Err(LexError::new(LexErrorKind::UnterminatedString, line, col, self.span_from(start)));
             }
+            return Ok(());
+        }
 
-            return Err(LexError::UnexpectedChar(ch, line, col));
+        // Character literals
+        if ch == '\'' {
+            let tok = self.read_char()?;
+            tokens.push(tok);
+            return Ok(());
         }
 
-        tokens.push(Token::new(TokenKind::T_EOF, self.line, self.col));
-        Ok(tokens)
+        // two-char operators (longest-match)
+        let two_char = match (self.peek(), self.peek_n(1)) {
+            (Some('='), Some('=')) => Some(TokenKind::T_EQUALSOP),
+            (Some('!'), Some('=')) => Some(TokenKind::T_NEQ),
+            (Some('<'), Some('=')) => Some(TokenKind::T_LTE),
+            (Some('>'), Some('=')) => Some(TokenKind::T_GTE),
+            (Some('&'), Some('&')) => Some(TokenKind::T_ANDAND),
+            (Some('|'), Some('|')) => Some(TokenKind::T_OROR),
+            (Some('<'), Some('<')) => Some(TokenKind::T_LSHIFT),
+            (Some('>'), Some('>')) => Some(TokenKind::T_RSHIFT),
+            _ => None,
+        };
+        if let Some(kind) = two_char {
+            self.advance();
+            self.advance();
+            tokens.push(Token::new(kind, line, col, self.span_from(start)));
+            return Ok(());
+        }
+
+        // single-char punctuation & operators
+        let one_char = match ch {
+            '(' => Some(TokenKind::T_PARENL),
+            ')' => Some(TokenKind::T_PARENR),
+            '{' => Some(TokenKind::T_BRACEL),
+            '}' => Some(TokenKind::T_BRACER),
+            '[' => Some(TokenKind::T_BRACKETL),
+            ']' => Some(TokenKind::T_BRACKETR),
+            ',' => Some(TokenKind::T_COMMA),
+            ';' => Some(TokenKind::T_SEMICOLON),
+            ':' => Some(TokenKind::T_COLON),
+            '.' => Some(TokenKind::T_DOT),
+            '=' => Some(TokenKind::T_ASSIGNOP),
+            '+' => Some(TokenKind::T_PLUS),
+            '-' => Some(TokenKind::T_MINUS),
+            '*' => Some(TokenKind::T_STAR),
+            '/' => Some(TokenKind::T_SLASH),
+            '%' => Some(TokenKind::T_PERCENT),
+            '^' => Some(TokenKind::T_CARET),
+            '&' => Some(TokenKind::T_AMP),
+            '|' => Some(TokenKind::T_PIPE),
+            '~' => Some(TokenKind::T_TILDE),
+            '!' => Some(TokenKind::T_NOT),
+            '<' => Some(TokenKind::T_LT),
+            '>' => Some(TokenKind::T_GT),
+            _ => None,
+        };
+        if let Some(kind) = one_char {
+            self.advance();
+            tokens.push(Token::new(kind, line, col, self.span_from(start)));
+            return Ok(());
+        }
+
+        // numbers (digit start) first read the number if it starts with a digit
+        if ch.is_ascii_digit() {
+            let tok = self.read_number()?;
+            tokens.push(tok);
+            return Ok(());
+        }
+
+        // identifier or keyword (unicode friendly)
+        if is_identifier_start(ch) {
+            let tok = self.read_identifier_or_keyword();
+            tokens.push(tok);
+            return Ok(());
+        }
+
+        Err(self.error_here(LexErrorKind::UnexpectedChar(ch)))
     }
 
     fn read_identifier_or_keyword(&mut self) -> Token {
         let start_line = self.line;
         let start_col = self.col;
+        let start = self.offset;
         let mut s = String::new();
         s.push(self.advance().unwrap()); // first char guaranteed valid by caller
         while let Some(ch) = self.peek() {
@@ -344,6 +364,8 @@ impl<'a> HandLexer<'a> {
             "float" => TokenKind::T_FLOAT,
             "bool" => TokenKind::T_BOOL,
             "string" => TokenKind::T_STRING,
+            "char" => TokenKind::T_CHAR,
+            "struct" => TokenKind::T_STRUCT,
             "return" => TokenKind::T_RETURN,
             "break" => TokenKind::T_BREAK,
             "if" => TokenKind::T_IF,
@@ -355,53 +377,73 @@ impl<'a> HandLexer<'a> {
             _ => TokenKind::T_IDENTIFIER(s),
         };
 
-        Token::new(kind, start_line, start_col)
+        Token::new(kind, start_line, start_col, self.span_from(start))
     }
 
+    // consumes characters while `keep` holds
+    fn take_while(&mut self, s: &mut String, keep: impl Fn(char) -> bool) {
+        while let Some(ch) = self.peek() {
+            if !keep(ch) {
+                break;
+            }
+            s.push(self.advance().unwrap());
+        }
+    }
+
+    // Finds the end of the literal here; turning its text into a value (and rejecting bad
+    // digits, missing digits or overflow) is left to `number` so RegexLexer agrees with us.
     fn read_number(&mut self) -> Result<Token, LexError> {
         let start_line = self.line;
         let start_col = self.col;
+        let start = self.offset;
         let mut s = String::new();
+        let decimal_run = |c: char| c.is_ascii_digit() || c == '_';
 
-        while let Some(ch) = self.peek() {
-            if ch.is_ascii_digit() {
-                s.push(self.advance().unwrap());
+        let prefixed = self.peek() == Some('0') && matches!(self.peek_n(1), Some('x' | 'o' | 'b'));
+        let mut is_float = false;
+        if prefixed {
+            s.push(self.advance().unwrap()); // '0'
+            let radix = self.advance().unwrap();
+            s.push(radix);
+            if radix == 'x' {
+                self.take_while(&mut s, |c| c.is_ascii_hexdigit() || c == '_');
             } else {
-                break;
+                self.take_while(&mut s, decimal_run);
             }
-        }
-
-        // float?
-        if self.peek() == Some('.') && self.peek_n(1).map(|c| c.is_ascii_digit()).unwrap_or(false) {
-            s.push(self.advance().unwrap()); // consume '.'
-            while let Some(ch) = self.peek() {
-                if ch.is_ascii_digit() {
-                    s.push(self.advance().unwrap());
-                } else {
-                    break;
-                }
-            }
-            // next char shouldn't be ident-start
-            if let Some(nxt) = self.peek() {
-                if nxt == '_' || nxt.is_alphabetic() {
-                    return Err(LexError::InvalidNumber(start_line, start_col));
-                }
-            }
-            let v: f64 = s
-                .parse()
-                .map_err(|_| LexError::InvalidNumber(start_line, start_col))?;
-            Ok(Token::new(TokenKind::T_FLOATLIT(v), start_line, start_col))
         } else {
-            if let Some(nxt) = self.peek() {
-                if nxt == '_' || nxt.is_alphabetic() {
-                    return Err(LexError::InvalidNumber(start_line, start_col));
+            self.take_while(&mut s, decimal_run);
+            // fraction
+            if self.peek() == Some('.') && self.peek_n(1).map(|c| c.is_ascii_digit()).unwrap_or(false) {
+                is_float = true;
+                s.push(self.advance().unwrap()); // consume '.'
+                self.take_while(&mut s, decimal_run);
+            }
+            // exponent
+            if matches!(self.peek(), Some('e' | 'E')) {
+                is_float = true;
+                s.push(self.advance().unwrap());
+                if matches!(self.peek(), Some('+' | '-')) {
+                    s.push(self.advance().unwrap());
                 }
+                self.take_while(&mut s, decimal_run);
             }
-            let v: i64 = s
-                .parse()
-                .map_err(|_| LexError::InvalidNumber(start_line, start_col))?;
-            Ok(Token::new(TokenKind::T_INTLIT(v), start_line, start_col))
         }
+
+        let error = |lexer: &Self, kind| LexError::new(kind, start_line, start_col, lexer.span_from(start));
+
+        // next char shouldn't be ident-start
+        if let Some(nxt) = self.peek()
+            && (nxt == '_' || nxt.is_alphabetic())
+        {
+            return Err(error(self, LexErrorKind::InvalidNumber));
+        }
+        let kind = if is_float {
+            number::parse_float(&s).map(TokenKind::T_FLOATLIT)
+        } else {
+            number::parse_int(&s).map(TokenKind::T_INTLIT)
+        };
+        let kind = kind.map_err(|k| error(self, k))?;
+        Ok(Token::new(kind, start_line, start_col, self.span_from(start)))
     }
 
     fn read_string_contents(&mut self) -> Result<String, LexError> {
@@ -412,87 +454,127 @@ impl<'a> HandLexer<'a> {
                 return Ok(buf);
             }
             if ch == '\\' {
-                self.advance(); // consume '\'
-                let esc = self
-                    .peek()
-                    .ok_or(LexError::UnterminatedString(self.line, self.col))?;
-                match esc {
-                    'n' => {
-                        self.advance();
-                        buf.push('\n');
-                    }
-                    't' => {
-                        self.advance();
-                        buf.push('\t');
-                    }
-                    'r' => {
-                        self.advance();
-                        buf.push('\r');
-                    }
-                    '\\' => {
-                        self.advance();
-                        buf.push('\\');
-                    }
-                    '"' => {
-                        self.advance();
-                        buf.push('"');
-                    }
-                    'x' => {
-                        // \xHH
-                        self.advance(); // x
-                        let h1 = self.advance().ok_or(LexError::InvalidEscape(
-                            "\\x (truncated)".into(),
-                            self.line,
-                            self.col,
-                        ))?;
-                        let h2 = self.advance().ok_or(LexError::InvalidEscape(
-                            "\\x (truncated)".into(),
-                            self.line,
-                            self.col,
-                        ))?;
-                        let hs = format!("{}{}", h1, h2);
-                        let byte = u8::from_str_radix(&hs, 16).map_err(|_| {
-                            LexError::InvalidEscape(format!("\\x{}", hs), self.line, self.col)
-                        })?;
-                        buf.push(byte as char);
-                    }
-                    'u' => {
-                        // \uXXXX (4 hex)
-                        self.advance(); // u
-                        let mut hexs = String::new();
-                        for _ in 0..4 {
-                            let h = self.advance().ok_or(LexError::InvalidEscape(
-                                "\\u (truncated)".into(),
-                                self.line,
-                                self.col,
-                            ))?;
-                            hexs.push(h);
-                        }
-                        let cp = u32::from_str_radix(&hexs, 16).map_err(|_| {
-                            LexError::InvalidEscape(format!("\\u{}", hexs), self.line, self.col)
-                        })?;
-                        if let Some(c) = std::char::from_u32(cp) {
-                            buf.push(c);
-                        } else {
-                            return Err(LexError::InvalidEscape(
-                                format!("\\u{}", hexs),
-                                self.line,
-                                self.col,
-                            ));
-                        }
-                    }
-                    other => {
-                        // unknown escape: push literally (or you can return Err)
-                        self.advance();
-                        buf.push(other);
-                    }
-                }
+                buf.push(self.read_escape()?);
             } else {
                 buf.push(ch);
                 self.advance();
             }
         }
-        Err(LexError::UnterminatedString(self.line, self.col))
+        Err(self.error_here(LexErrorKind::UnterminatedString))
+    }
+
+    // decodes one escape sequence starting at the '\'; shared by string and char literals
+    fn read_escape(&mut self) -> Result<char, LexError> {
+        let (esc_line, esc_col, esc_start) = (self.line, self.col, self.offset);
+        let bad_escape = |lexer: &Self, text: String| {
+            LexError::new(LexErrorKind::InvalidEscape(text), esc_line, esc_col, lexer.span_from(esc_start))
+        };
+        self.advance(); // consume '\'
+        let esc = self
+            .peek()
+            .ok_or_else(|| self.error_here(LexErrorKind::UnterminatedString))?;
+        match esc {
+            'n' => {
+                self.advance();
+                Ok('\n')
+            }
+            't' => {
+                self.advance();
+                Ok('\t')
+            }
+            'r' => {
+                self.advance();
+                Ok('\r')
+            }
+            '\\' => {
+                self.advance();
+                Ok('\\')
+            }
+            '"' => {
+                self.advance();
+                Ok('"')
+            }
+            'x' => {
+                // \xHH
+                self.advance(); // x
+                let h1 = self.advance().ok_or_else(|| bad_escape(self, "\\x (truncated)".into()))?;
+                let h2 = self.advance().ok_or_else(|| bad_escape(self, "\\x (truncated)".into()))?;
+                let hs = format!("{}{}", h1, h2);
+                let byte = u8::from_str_radix(&hs, 16)
+                    .map_err(|_| bad_escape(self, format!("\\x{}", hs)))?;
+                Ok(byte as char)
+            }
+            'u' => {
+                // \uXXXX (4 hex)
+                self.advance(); // u
+                let mut hexs = String::new();
+                for _ in 0..4 {
+                    let h = self.advance().ok_or_else(|| bad_escape(self, "\\u (truncated)".into()))?;
+                    hexs.push(h);
+                }
+                let cp = u32::from_str_radix(&hexs, 16)
+                    .map_err(|_| bad_escape(self, format!("\\u{}", hexs)))?;
+                std::char::from_u32(cp).ok_or_else(|| bad_escape(self, format!("\\u{}", hexs)))
+            }
+            other => {
+                // unknown escape: push literally (or you can return Err)
+                self.advance();
+                Ok(other)
+            }
+        }
+    }
+
+    // 'c' or an escape like '\n'; the literal must hold exactly one character and end on
+    // the line it started
+    fn read_char(&mut self) -> Result<Token, LexError> {
+        let (line, col, start) = (self.line, self.col, self.offset);
+        let error = |lexer: &Self, kind| LexError::new(kind, line, col, lexer.span_from(start));
+        self.advance(); // opening '
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('\'') => {
+                    self.advance();
+                    break;
+                }
+                None | Some('\n') => return Err(error(self, LexErrorKind::UnterminatedChar)),
+                Some('\\') => {
+                    let c = self.read_escape().map_err(|e| match e.kind {
+                        // a '\' right before eof
+                        LexErrorKind::UnterminatedString => error(self, LexErrorKind::UnterminatedChar),
+                        _ => e,
+                    })?;
+                    value.push(c);
+                }
+                Some(c) => {
+                    self.advance();
+                    value.push(c);
+                }
+            }
+        }
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Token::new(TokenKind::T_CHARLIT(c), line, col, self.span_from(start))),
+            (None, _) => Err(error(self, LexErrorKind::InvalidCharLiteral("empty character literal".into()))),
+            _ => Err(error(self, LexErrorKind::InvalidCharLiteral("more than one character".into()))),
+        }
+    }
+}
+
+impl Lexer for HandLexer<'_> {
+    fn tokenize(&mut self) -> Result<Vec<Token>, LexError> {
+        let mut tokens = Vec::new();
+
+        while !self.eof() {
+            self.skip_whitespace_and_comments()?; // ? means if error return it to the main function
+            if self.eof() {
+                break;
+            }
+            self.lex_token(&mut tokens)?;
+        }
+
+        tokens.push(Token::new(TokenKind::T_EOF, self.line, self.col, self.span_from(self.offset)));
+        Ok(tokens)
     }
 }
 
@@ -0,0 +1,67 @@
+// Conversion of numeric literal text into values, shared by HandLexer and RegexLexer so
+// both accept exactly the same literals. The lexers only find where a literal ends:
+//   int   := [0-9][0-9_]* | 0x[0-9a-fA-F_]* | 0o[0-9_]* | 0b[0-9_]*
+//   float := [0-9][0-9_]* "." [0-9][0-9_]* ([eE][+-]?[0-9_]*)? | [0-9][0-9_]* [eE][+-]?[0-9_]*
+// `_` separators are allowed anywhere after the first digit (or prefix) and are ignored.
+use crate::lexer::LexErrorKind;
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        16 => "hexadecimal",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    }
+}
+
+// strips the separators from a run of digits and checks every digit fits `radix`
+fn digits(run: &str, radix: u32, missing: &str) -> Result<String, LexErrorKind> {
+    let mut out = String::with_capacity(run.len());
+    for c in run.chars().filter(|&c| c != '_') {
+        if !c.is_digit(radix) {
+            return Err(LexErrorKind::MalformedNumber(format!("invalid digit '{}' in {} literal", c, radix_name(radix))));
+        }
+        out.push(c);
+    }
+    if out.is_empty() {
+        return Err(LexErrorKind::MalformedNumber(missing.to_string()));
+    }
+    Ok(out)
+}
+
+pub fn parse_int(text: &str) -> Result<i64, LexErrorKind> {
+    let (radix, body) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    let missing = format!("missing digits after {}", &text[..text.len() - body.len()]);
+    let body = digits(body, radix, &missing)?;
+    // the digits are valid, so the only way this can fail is by not fitting in an i64
+    i64::from_str_radix(&body, radix).map_err(|_| LexErrorKind::NumberOverflow(text.to_string()))
+}
+
+pub fn parse_float(text: &str) -> Result<f64, LexErrorKind> {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let mut normalized: String = mantissa.chars().filter(|&c| c != '_').collect();
+    if let Some(exp) = exponent {
+        let (sign, run) = match exp.strip_prefix(['+', '-']) {
+            Some(run) => (&exp[..1], run),
+            None => ("", exp),
+        };
+        normalized.push('e');
+        normalized.push_str(sign);
+        normalized.push_str(&digits(run, 10, "missing digits in exponent")?);
+    }
+    let value: f64 = normalized
+        .parse()
+        .map_err(|_| LexErrorKind::MalformedNumber(format!("cannot read '{}' as a float", text)))?;
+    if value.is_infinite() {
+        return Err(LexErrorKind::NumberOverflow(text.to_string()));
+    }
+    Ok(value)
+}
@@ -1,176 +1,328 @@
 #![allow(unused)]
-use regex::Regex;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex, RegexSet};
 use crate::token::{Token, TokenKind};
-use crate::lexer::LexError;
-pub struct Rule {
-    regex: Regex,
-    kind: fn(&str) -> TokenKind, // function to convert matched text into TokenKind
+use crate::lexer::{number, LexError, LexErrorKind, Lexer};
+use crate::span::{FileId, Span};
+
+// What to do with the text a rule matched
+#[derive(Clone, Copy)]
+enum Action {
+    Skip,                                               // whitespace and comments
+    Token(fn(&str) -> Result<TokenKind, LexErrorKind>), // convert matched text into a TokenKind
+    StringLit,                                          // "..." split into T_QUOTES, T_STRINGLIT, T_QUOTES
+    CharLit,                                            // '...', possibly unterminated or of the wrong length
+    Fail(fn() -> LexErrorKind),                         // an opener that never got closed
+}
+
+struct Rule {
+    pattern: &'static str,
+    action: Action,
+}
+
+// All rules compiled once into a single RegexSet (one automaton tells which rules match at
+// the cursor) plus the individual regexes to measure how long each of those matches is.
+pub struct Rules {
+    set: RegexSet,
+    regexes: Vec<Regex>,
+    actions: Vec<Action>,
 }
 
-pub fn build_rules() -> Vec<Rule> {
+static RULES: Lazy<Rules> = Lazy::new(build_rules);
+
+// Rules are tried all at once and the longest match wins (maximal munch). On a tie the
+// rule listed first wins, which is what gives keywords priority over identifiers:
+// `fn` is T_FUNCTION but `fnord` is one identifier. Every pattern is anchored with ^.
+fn rule_table() -> Vec<Rule> {
     vec![
-        // Keywords
-        Rule { regex: Regex::new(r"^fn\b").unwrap(), kind: |_| TokenKind::T_FUNCTION },
-        Rule { regex: Regex::new(r"^int\b").unwrap(), kind: |_| TokenKind::T_INT },
-        Rule { regex: Regex::new(r"^float\b").unwrap(), kind: |_| TokenKind::T_FLOAT },
-        Rule { regex: Regex::new(r"^bool\b").unwrap(), kind: |_| TokenKind::T_BOOL },
-        Rule { regex: Regex::new(r"^string\b").unwrap(), kind: |_| TokenKind::T_STRING },
-        Rule { regex: Regex::new(r"^return\b").unwrap(), kind: |_| TokenKind::T_RETURN },
-        Rule { regex: Regex::new(r"^if\b").unwrap(), kind: |_| TokenKind::T_IF },
-        Rule { regex: Regex::new(r"^else\b").unwrap(), kind: |_| TokenKind::T_ELSE },
-        Rule { regex: Regex::new(r"^for\b").unwrap(), kind: |_| TokenKind::T_FOR },
-        Rule { regex: Regex::new(r"^while\b").unwrap(), kind: |_| TokenKind::T_WHILE },
-
-        // Identifiers
-        Rule { regex: Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*").unwrap(),
-               kind: |s| TokenKind::T_IDENTIFIER(s.to_string()) },
-
-        // Literals
-        Rule { regex: Regex::new(r"^\d+\.\d+").unwrap(),
-               kind: |s| TokenKind::T_FLOATLIT(s.parse().unwrap()) },
-        Rule { regex: Regex::new(r"^\d+").unwrap(),
-               kind: |s| TokenKind::T_INTLIT(s.parse().unwrap()) },
-        Rule { regex: Regex::new(r#"^"([^"\\]|\\.)*""#).unwrap(),
-               kind: |s| TokenKind::T_STRINGLIT(s.to_string()) },
+        // Trivia
+        Rule { pattern: r"^\s+", action: Action::Skip },
+        Rule { pattern: r"^//[^\n]*", action: Action::Skip },
+        // written without a lazy `.*?`: a RegexSet ignores laziness and would keep scanning
+        // to the end of the input on every call
+        Rule { pattern: r"^/\*([^*]|\*+[^*/])*\*+/", action: Action::Skip },
+        Rule { pattern: r"^/\*", action: Action::Fail(|| LexErrorKind::UnterminatedComment) },
+
+        // Strings; \x and \u take their 2/4 hex digits verbatim (even a quote), exactly like HandLexer
+        Rule { pattern: r#"^"([^"\\]|\\x(?s:.){2}|\\u(?s:.){4}|\\(?s:.))*""#, action: Action::StringLit },
+        Rule { pattern: r#"^""#, action: Action::Fail(|| LexErrorKind::UnterminatedString) },
+
+        // Char literals. A single rule so it always wins at a quote: the contents, a '\' cut
+        // off by eof and the closing quote are captured separately and checked in lex_char
+        Rule { pattern: r#"^'((?:[^'\\\n]|\\x(?s:.){2}|\\u(?s:.){4}|\\(?s:.))*)(\\?)(')?"#, action: Action::CharLit },
+
+        // Keywords (listed before identifiers so they win ties)
+        Rule { pattern: r"^fn", action: Action::Token(|_| Ok(TokenKind::T_FUNCTION)) },
+        Rule { pattern: r"^int", action: Action::Token(|_| Ok(TokenKind::T_INT)) },
+        Rule { pattern: r"^float", action: Action::Token(|_| Ok(TokenKind::T_FLOAT)) },
+        Rule { pattern: r"^bool", action: Action::Token(|_| Ok(TokenKind::T_BOOL)) },
+        Rule { pattern: r"^string", action: Action::Token(|_| Ok(TokenKind::T_STRING)) },
+        Rule { pattern: r"^char", action: Action::Token(|_| Ok(TokenKind::T_CHAR)) },
+        Rule { pattern: r"^struct", action: Action::Token(|_| Ok(TokenKind::T_STRUCT)) },
+        Rule { pattern: r"^return", action: Action::Token(|_| Ok(TokenKind::T_RETURN)) },
+        Rule { pattern: r"^break", action: Action::Token(|_| Ok(TokenKind::T_BREAK)) },
+        Rule { pattern: r"^if", action: Action::Token(|_| Ok(TokenKind::T_IF)) },
+        Rule { pattern: r"^else", action: Action::Token(|_| Ok(TokenKind::T_ELSE)) },
+        Rule { pattern: r"^for", action: Action::Token(|_| Ok(TokenKind::T_FOR)) },
+        Rule { pattern: r"^while", action: Action::Token(|_| Ok(TokenKind::T_WHILE)) },
+        Rule { pattern: r"^true", action: Action::Token(|_| Ok(TokenKind::T_BOOLLIT(true))) },
+        Rule { pattern: r"^false", action: Action::Token(|_| Ok(TokenKind::T_BOOLLIT(false))) },
+
+        // Identifiers (same character classes as HandLexer's is_identifier_start/part)
+        Rule { pattern: r"^[_a-zA-Z\x{80}-\x{10FFFF}][_a-zA-Z0-9\x{80}-\x{10FFFF}]*",
+               action: Action::Token(|s| Ok(TokenKind::T_IDENTIFIER(s.to_string()))) },
+
+        // Literals; these only find where a number ends, `number` checks digits and range
+        Rule { pattern: r"^0x[0-9a-fA-F_]*", action: Action::Token(int_literal) },
+        Rule { pattern: r"^0[ob][0-9_]*", action: Action::Token(int_literal) },
+        Rule { pattern: r"^[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9_]*)?", action: Action::Token(float_literal) },
+        Rule { pattern: r"^[0-9][0-9_]*[eE][+-]?[0-9_]*", action: Action::Token(float_literal) },
+        Rule { pattern: r"^[0-9][0-9_]*", action: Action::Token(int_literal) },
 
         // Operators & punctuation
-        Rule { regex: Regex::new(r"^==").unwrap(), kind: |_| TokenKind::T_EQUALSOP },
-        Rule { regex: Regex::new(r"^!=").unwrap(), kind: |_| TokenKind::T_NEQ },
-        Rule { regex: Regex::new(r"^<=").unwrap(), kind: |_| TokenKind::T_LTE },
-        Rule { regex: Regex::new(r"^>=").unwrap(), kind: |_| TokenKind::T_GTE },
-        Rule { regex: Regex::new(r"^&&").unwrap(), kind: |_| TokenKind::T_ANDAND },
-        Rule { regex: Regex::new(r"^\|\|").unwrap(), kind: |_| TokenKind::T_OROR },
-        Rule { regex: Regex::new(r"^<<").unwrap(), kind: |_| TokenKind::T_LSHIFT },
-        Rule { regex: Regex::new(r"^>>").unwrap(), kind: |_| TokenKind::T_RSHIFT },
-
-        Rule { regex: Regex::new(r"^=").unwrap(), kind: |_| TokenKind::T_ASSIGNOP },
-        Rule { regex: Regex::new(r"^<").unwrap(), kind: |_| TokenKind::T_LT },
-        Rule { regex: Regex::new(r"^>").unwrap(), kind: |_| TokenKind::T_GT },
-        Rule { regex: Regex::new(r"^\+").unwrap(), kind: |_| TokenKind::T_PLUS },
-        Rule { regex: Regex::new(r"^-").unwrap(), kind: |_| TokenKind::T_MINUS },
-        Rule { regex: Regex::new(r"^\*").unwrap(), kind: |_| TokenKind::T_STAR },
-        Rule { regex: Regex::new(r"^/").unwrap(), kind: |_| TokenKind::T_SLASH },
-        Rule { regex: Regex::new(r"^%").unwrap(), kind: |_| TokenKind::T_PERCENT },
-        Rule { regex: Regex::new(r"^\^").unwrap(), kind: |_| TokenKind::T_CARET },
-        Rule { regex: Regex::new(r"^&").unwrap(), kind: |_| TokenKind::T_AMP },
-        Rule { regex: Regex::new(r"^\|").unwrap(), kind: |_| TokenKind::T_PIPE },
-        Rule { regex: Regex::new(r"^~").unwrap(), kind: |_| TokenKind::T_TILDE },
-        Rule { regex: Regex::new(r"^!").unwrap(), kind: |_| TokenKind::T_NOT },
-
-        Rule { regex: Regex::new(r"^\(").unwrap(), kind: |_| TokenKind::T_PARENL },
-        Rule { regex: Regex::new(r"^\)").unwrap(), kind: |_| TokenKind::T_PARENR },
-        Rule { regex: Regex::new(r"^\{").unwrap(), kind: |_| TokenKind::T_BRACEL },
-        Rule { regex: Regex::new(r"^\}").unwrap(), kind: |_| TokenKind::T_BRACER },
-        Rule { regex: Regex::new(r"^\[").unwrap(), kind: |_| TokenKind::T_BRACKETL },
-        Rule { regex: Regex::new(r"^\]").unwrap(), kind: |_| TokenKind::T_BRACKETR },
-        Rule { regex: Regex::new(r"^,").unwrap(), kind: |_| TokenKind::T_COMMA },
-        Rule { regex: Regex::new(r"^;").unwrap(), kind: |_| TokenKind::T_SEMICOLON },
-        Rule { regex: Regex::new(r"^:").unwrap(), kind: |_| TokenKind::T_COLON },
-        Rule { regex: Regex::new(r"^\.").unwrap(), kind: |_| TokenKind::T_DOT },
+        Rule { pattern: r"^==", action: Action::Token(|_| Ok(TokenKind::T_EQUALSOP)) },
+        Rule { pattern: r"^!=", action: Action::Token(|_| Ok(TokenKind::T_NEQ)) },
+        Rule { pattern: r"^<=", action: Action::Token(|_| Ok(TokenKind::T_LTE)) },
+        Rule { pattern: r"^>=", action: Action::Token(|_| Ok(TokenKind::T_GTE)) },
+        Rule { pattern: r"^&&", action: Action::Token(|_| Ok(TokenKind::T_ANDAND)) },
+        Rule { pattern: r"^\|\|", action: Action::Token(|_| Ok(TokenKind::T_OROR)) },
+        Rule { pattern: r"^<<", action: Action::Token(|_| Ok(TokenKind::T_LSHIFT)) },
+        Rule { pattern: r"^>>", action: Action::Token(|_| Ok(TokenKind::T_RSHIFT)) },
+
+        Rule { pattern: r"^=", action: Action::Token(|_| Ok(TokenKind::T_ASSIGNOP)) },
+        Rule { pattern: r"^<", action: Action::Token(|_| Ok(TokenKind::T_LT)) },
+        Rule { pattern: r"^>", action: Action::Token(|_| Ok(TokenKind::T_GT)) },
+        Rule { pattern: r"^\+", action: Action::Token(|_| Ok(TokenKind::T_PLUS)) },
+        Rule { pattern: r"^-", action: Action::Token(|_| Ok(TokenKind::T_MINUS)) },
+        Rule { pattern: r"^\*", action: Action::Token(|_| Ok(TokenKind::T_STAR)) },
+        Rule { pattern: r"^/", action: Action::Token(|_| Ok(TokenKind::T_SLASH)) },
+        Rule { pattern: r"^%", action: Action::Token(|_| Ok(TokenKind::T_PERCENT)) },
+        Rule { pattern: r"^\^", action: Action::Token(|_| Ok(TokenKind::T_CARET)) },
+        Rule { pattern: r"^&", action: Action::Token(|_| Ok(TokenKind::T_AMP)) },
+        Rule { pattern: r"^\|", action: Action::Token(|_| Ok(TokenKind::T_PIPE)) },
+        Rule { pattern: r"^~", action: Action::Token(|_| Ok(TokenKind::T_TILDE)) },
+        Rule { pattern: r"^!", action: Action::Token(|_| Ok(TokenKind::T_NOT)) },
+
+        Rule { pattern: r"^\(", action: Action::Token(|_| Ok(TokenKind::T_PARENL)) },
+        Rule { pattern: r"^\)", action: Action::Token(|_| Ok(TokenKind::T_PARENR)) },
+        Rule { pattern: r"^\{", action: Action::Token(|_| Ok(TokenKind::T_BRACEL)) },
+        Rule { pattern: r"^\}", action: Action::Token(|_| Ok(TokenKind::T_BRACER)) },
+        Rule { pattern: r"^\[", action: Action::Token(|_| Ok(TokenKind::T_BRACKETL)) },
+        Rule { pattern: r"^\]", action: Action::Token(|_| Ok(TokenKind::T_BRACKETR)) },
+        Rule { pattern: r"^,", action: Action::Token(|_| Ok(TokenKind::T_COMMA)) },
+        Rule { pattern: r"^;", action: Action::Token(|_| Ok(TokenKind::T_SEMICOLON)) },
+        Rule { pattern: r"^:", action: Action::Token(|_| Ok(TokenKind::T_COLON)) },
+        Rule { pattern: r"^\.", action: Action::Token(|_| Ok(TokenKind::T_DOT)) },
     ]
 }
 
-pub struct RegexLexer {
-    rules: Vec<Rule>,
+fn int_literal(s: &str) -> Result<TokenKind, LexErrorKind> {
+    number::parse_int(s).map(TokenKind::T_INTLIT)
+}
+
+fn float_literal(s: &str) -> Result<TokenKind, LexErrorKind> {
+    number::parse_float(s).map(TokenKind::T_FLOATLIT)
 }
 
-impl RegexLexer {
-    pub fn new() -> Self {
-        Self { rules: build_rules() }
+fn build_rules() -> Rules {
+    let table = rule_table();
+    let patterns: Vec<&str> = table.iter().map(|r| r.pattern).collect();
+    Rules {
+        set: RegexSet::new(&patterns).unwrap(),
+        regexes: patterns.iter().map(|p| Regex::new(p).unwrap()).collect(),
+        actions: table.iter().map(|r| r.action).collect(),
     }
+}
+
+impl Rules {
+    // longest rule match at the start of `input` as (rule index, length); ties go to the lower index
+    fn longest_match(&self, input: &str) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for idx in self.set.matches(input).iter() {
+            let len = self.regexes[idx].find(input).map(|m| m.end()).unwrap_or(0);
+            if best.is_none_or(|(_, best_len)| len > best_len) {
+                best = Some((idx, len));
+            }
+        }
+        best
+    }
+}
+
+// a number running straight into an identifier (`12abc`) is malformed
+static BAD_NUMBER_TAIL: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[_\p{Alphabetic}]").unwrap());
+
+pub struct RegexLexer<'a> {
+    input: &'a str,
+    file: FileId,
+}
+
+// line/col bookkeeping while walking the input
+struct Cursor {
+    line: usize,
+    col: usize,
+    offset: usize, // byte offset into the whole input
+}
+
+impl Cursor {
+    fn advance_over(&mut self, lexeme: &str) {
+        for ch in lexeme.chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.offset += lexeme.len();
+    }
+}
 
-    pub fn tokenize(&self, mut input: &str) -> Result<Vec<Token>, LexError> {
+impl<'a> RegexLexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::with_file(input, 0)
+    }
+
+    pub fn with_file(input: &'a str, file: FileId) -> Self {
+        Self { input, file }
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(self.file, start, end)
+    }
+
+    // a complete "..." literal: pushes T_QUOTES, T_STRINGLIT, T_QUOTES like HandLexer
+    fn lex_string(&self, literal: &str, cur: &mut Cursor, tokens: &mut Vec<Token>) -> Result<(), LexError> {
+        let (line, col, start) = (cur.line, cur.col, cur.offset);
+        let contents = &literal[1..literal.len() - 1];
+        tokens.push(Token::new(TokenKind::T_QUOTES, line, col, self.span(start, start + 1)));
+        cur.advance_over("\"");
+        let value = unescape(contents, cur, self.file)?;
+        tokens.push(Token::new(TokenKind::T_STRINGLIT(value), line, col, self.span(cur.offset, cur.offset + contents.len())));
+        cur.advance_over(contents);
+        tokens.push(Token::new(TokenKind::T_QUOTES, cur.line, cur.col, self.span(cur.offset, cur.offset + 1)));
+        cur.advance_over("\"");
+        Ok(())
+    }
+
+    // a char literal as matched by the CharLit rule; mirrors HandLexer::read_char
+    fn lex_char(&self, caps: &Captures, cur: &mut Cursor, tokens: &mut Vec<Token>) -> Result<(), LexError> {
+        let lexeme = caps.get(0).unwrap().as_str();
+        let span = self.span(cur.offset, cur.offset + lexeme.len());
+        let error = |kind| LexError::new(kind, cur.line, cur.col, span);
+        let contents = Cursor { line: cur.line, col: cur.col + 1, offset: cur.offset + 1 };
+        let value = unescape(caps.get(1).unwrap().as_str(), &contents, self.file)?;
+        if caps.get(3).is_none() {
+            return Err(error(LexErrorKind::UnterminatedChar));
+        }
+        let mut chars = value.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            (None, _) => return Err(error(LexErrorKind::InvalidCharLiteral("empty character literal".into()))),
+            _ => return Err(error(LexErrorKind::InvalidCharLiteral("more than one character".into()))),
+        };
+        tokens.push(Token::new(TokenKind::T_CHARLIT(c), cur.line, cur.col, span));
+        cur.advance_over(lexeme);
+        Ok(())
+    }
+}
+
+impl Lexer for RegexLexer<'_> {
+    fn tokenize(&mut self) -> Result<Vec<Token>, LexError> {
+        let rules = &*RULES;
         let mut tokens = Vec::new();
-        let mut line = 1;
-        let mut col = 1;
-    
-        let ws_re = Regex::new(r"^\s+").unwrap();
-        let slc_re = Regex::new(r"^//[^\n]*").unwrap();
-        let mlc_re = Regex::new(r"^/\*([^*]|\*+[^*/])*\*+/").unwrap();
-    
-        while !input.is_empty() {
-            // Skip whitespace
-            if let Some(m) = ws_re.find(input) {
-                let lexeme = &input[..m.end()];
-                for ch in lexeme.chars() {
-                    if ch == '\n' {
-                        line += 1;
-                        col = 1;
-                    } else {
-                        col += 1;
-                    }
+        let mut cur = Cursor { line: 1, col: 1, offset: 0 };
+
+        while cur.offset < self.input.len() {
+            let rest = &self.input[cur.offset..];
+            let Some((idx, len)) = rules.longest_match(rest) else {
+                let ch = rest.chars().next().unwrap();
+                let span = self.span(cur.offset, cur.offset + ch.len_utf8());
+                return Err(LexError::new(LexErrorKind::UnexpectedChar(ch), cur.line, cur.col, span));
+            };
+            let lexeme = &rest[..len];
+
+            match rules.actions[idx] {
+                Action::Skip => cur.advance_over(lexeme),
+                Action::StringLit => self.lex_string(lexeme, &mut cur, &mut tokens)?,
+                Action::CharLit => {
+                    let caps = rules.regexes[idx].captures(rest).unwrap();
+                    self.lex_char(&caps, &mut cur, &mut tokens)?
                 }
-                input = &input[m.end()..];
-                continue;
-            }
-    
-            // Skip single-line comments
-            if let Some(m) = slc_re.find(input) {
-                let lexeme = &input[..m.end()];
-                for ch in lexeme.chars() {
-                    if ch == '\n' {
-                        line += 1;
-                        col = 1;
-                    } else {
-                        col += 1;
-                    }
+                Action::Fail(kind) => {
+                    let span = self.span(cur.offset, self.input.len());
+                    return Err(LexError::new(kind(), cur.line, cur.col, span));
                 }
-                input = &input[m.end()..];
-                continue;
-            }
-    
-            // Skip multi-line comments
-            if let Some(m) = mlc_re.find(input) {
-                let lexeme = &input[..m.end()];
-                for ch in lexeme.chars() {
-                    if ch == '\n' {
-                        line += 1;
-                        col = 1;
-                    } else {
-                        col += 1;
+                Action::Token(make) => {
+                    let span = self.span(cur.offset, cur.offset + len);
+                    let numeric = lexeme.starts_with(|c: char| c.is_ascii_digit());
+                    if numeric && BAD_NUMBER_TAIL.is_match(&rest[len..]) {
+                        return Err(LexError::new(LexErrorKind::InvalidNumber, cur.line, cur.col, span));
                     }
+                    let kind = make(lexeme).map_err(|kind| LexError::new(kind, cur.line, cur.col, span))?;
+                    tokens.push(Token::new(kind, cur.line, cur.col, span));
+                    cur.advance_over(lexeme);
                 }
-                input = &input[m.end()..];
-                continue;
             }
-    
-            // --- Try token rules ---
-            let mut matched = false;
-            for rule in &self.rules {
-                if let Some(m) = rule.regex.find(input) {
-                    let lexeme = &input[..m.end()];
-                    let kind = (rule.kind)(lexeme);
-    
-                    tokens.push(Token {
-                        kind,
-                        line,
-                        col,
-                    });
-    
-                    // Update line/col
-                    for ch in lexeme.chars() {
-                        if ch == '\n' {
-                            line += 1;
-                            col = 1;
-                        } else {
-                            col += 1;
+        }
+
+        tokens.push(Token::new(TokenKind::T_EOF, cur.line, cur.col, self.span(cur.offset, cur.offset)));
+        Ok(tokens)
+    }
+}
+
+// Decodes the escapes of a string literal body, following the same rules as
+// HandLexer::read_string_contents. `cur` points at the first byte of `contents`.
+fn unescape(contents: &str, cur: &Cursor, file: FileId) -> Result<String, LexError> {
+    let mut out = String::new();
+    let mut pos = Cursor { line: cur.line, col: cur.col, offset: cur.offset };
+    let mut chars = contents.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            pos.advance_over(&contents[i..i + ch.len_utf8()]);
+            continue;
+        }
+        let (esc_line, esc_col, esc_start) = (pos.line, pos.col, pos.offset);
+        let bad_escape = |text: String, end: usize| {
+            LexError::new(LexErrorKind::InvalidEscape(text), esc_line, esc_col, Span::new(file, esc_start, pos.offset + end - i))
+        };
+        // the literal regex guarantees a character follows every backslash
+        let (_, esc) = chars.next().unwrap();
+        let mut end = i + 1 + esc.len_utf8();
+        match esc {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            'x' | 'u' => {
+                let width = if esc == 'x' { 2 } else { 4 };
+                let mut hexs = String::new();
+                for _ in 0..width {
+                    match chars.next() {
+                        Some((j, h)) => {
+                            hexs.push(h);
+                            end = j + h.len_utf8();
+                        }
+                        None => {
+                            let end = contents.len();
+                            return Err(bad_escape(format!("\\{} (truncated)", esc), end));
                         }
                     }
-    
-                    input = &input[m.end()..];
-                    matched = true;
-                    break;
+                }
+                let decoded = if esc == 'x' {
+                    u8::from_str_radix(&hexs, 16).ok().map(|b| b as char)
+                } else {
+                    u32::from_str_radix(&hexs, 16).ok().and_then(std::char::from_u32)
+                };
+                match decoded {
+                    Some(c) => out.push(c),
+                    None => return Err(bad_escape(format!("\\{}{}", esc, hexs), end)),
                 }
             }
-    
-            if !matched {
-                return Err(LexError::UnexpectedChar(input.chars().next().unwrap(), line, col));
-            }
+            other => out.push(other), // unknown escape: kept literally
         }
-    
-        tokens.push(Token { kind: TokenKind::T_EOF, line, col });
-        Ok(tokens)
+        pos.advance_over(&contents[i..end]);
     }
-    
-    
-}
\ No newline at end of file
+    Ok(out)
+}
@@ -0,0 +1,129 @@
+// Library half of the crate: every stage of the compiler, the diagnostics they report
+// through, and the C ABI (`ffi`) that lets the Bison parser in grammar/ run on top of the
+// lexer. The compiler binary is a client of the functions below; tools that embed the
+// front end should use them too rather than the stage modules directly.
+pub mod span;
+pub mod diagnostic;
+pub mod token;
+pub mod lexer;
+pub mod parser;
+pub mod semantics;
+pub mod interpreter;
+pub mod bytecode;
+pub mod ir;
+pub mod ffi;
+
+use crate::bytecode::chunk::Module;
+use crate::bytecode::vm::Vm;
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::error::RuntimeError;
+use crate::interpreter::eval::Interpreter;
+use crate::interpreter::value::Value;
+use crate::lexer::HandLexer;
+use crate::parser::ast::Program;
+use crate::parser::error::ParseErrorKind;
+use crate::parser::parser::Parser;
+use crate::semantics::analysis::perform_semantic_analysis;
+use crate::semantics::typechecker::TypeTable;
+use crate::token::Token;
+
+/// Splits `src` into tokens, comments and whitespace dropped. Lexing errors don't stop it:
+/// the offending text becomes a `T_ERROR` token and a diagnostic, and lexing resumes
+/// after it.
+pub fn lex(src: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let (tokens, errors) = HandLexer::new(src).tokenize_recovering();
+    (tokens, errors.iter().map(Diagnostic::from).collect())
+}
+
+/// Lexes and parses `src`. The program holds everything that parsed; statements the
+/// parser had to skip are `Error` nodes. Diagnostics are lexer errors, then parser errors.
+pub fn parse(src: &str) -> (Program, Vec<Diagnostic>) {
+    let (tokens, mut diagnostics) = lex(src);
+    let (program, parse_errors) = parse_tokens(&tokens);
+    diagnostics.extend(parse_errors);
+    (program, diagnostics)
+}
+
+/// Parses tokens from any lexer (e.g. the trivia-keeping one `fmt` needs). Errors on
+/// `T_ERROR` tokens are left out, the lexer has already reported them.
+pub fn parse_tokens(tokens: &[Token]) -> (Program, Vec<Diagnostic>) {
+    let (program, errors) = on_deep_stack(|| Parser::new(tokens).parse_program());
+    let diagnostics = errors
+        .iter()
+        .filter(|e| !matches!(e.kind, ParseErrorKind::InvalidToken))
+        .map(Diagnostic::from)
+        .collect();
+    (program, diagnostics)
+}
+
+/// Runs scope and type checking and flow analysis over a program, which may be one that
+/// parsed with errors. Returns the type of every expression that checked, by its span,
+/// and the diagnostics: scope errors, then type errors, then warnings.
+pub fn check(program: &Program) -> (TypeTable, Vec<Diagnostic>) {
+    let analysis = on_deep_stack(|| perform_semantic_analysis(program));
+    let mut diagnostics: Vec<Diagnostic> = analysis.scope_errors.iter().map(Diagnostic::from).collect();
+    diagnostics.extend(analysis.typechk_errors.iter().map(Diagnostic::from));
+    diagnostics.extend(analysis.warnings.iter().map(Diagnostic::from));
+    (analysis.types, diagnostics)
+}
+
+/// Compiles a program that checked without errors to bytecode, for `execute`.
+pub fn compile(program: &Program) -> Module {
+    on_deep_stack(|| bytecode::codegen::compile(program))
+}
+
+/// Lowers a program that checked without errors to three-address code, using the types
+/// `check` returned for it.
+pub fn lower(program: &Program, types: &TypeTable) -> ir::tac::Module {
+    on_deep_stack(|| ir::lower::lower(program, types))
+}
+
+/// Optimises lowered code at `-O<level>` (see `ir::opt`): puts each function in SSA form,
+/// runs the level's passes and takes the result back out of SSA form. Level 0 leaves the
+/// code as it is.
+pub fn optimize(module: &mut ir::tac::Module, level: u8) {
+    let pipeline = ir::opt::Pipeline::for_level(level);
+    if pipeline.passes.is_empty() {
+        return;
+    }
+    for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+        ir::ssa::construct(function);
+    }
+    pipeline.run(module, |_, _, _| {});
+    for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+        ir::ssa::destruct(function);
+    }
+}
+
+/// Runs compiled bytecode on the VM. Gives the same result, and the same runtime errors,
+/// as `run` on the program it was compiled from, only faster.
+pub fn execute(module: &Module) -> Result<Value, RuntimeError> {
+    Vm::new(module).run()
+}
+
+/// Runs a program that checked without errors on the tree-walking interpreter: global initializers and top-level
+/// statements in source order, then `main` if the file defines one without parameters.
+/// The result is the value of a top-level `return`, else what `main` returns, else
+/// `Value::Void`. A runtime error carries the calls that were active when it happened.
+pub fn run(program: &Program) -> Result<Value, RuntimeError> {
+    // each call of the program nests several calls of the interpreter, so it needs a stack
+    // deep enough for MAX_CALL_DEPTH calls rather than the caller's
+    on_deep_stack(|| Interpreter::new(program).run())
+}
+
+/// Runs `f` on a thread with a stack of DEEP_STACK bytes and returns what it returns. The
+/// stages recurse over the tree, so a long expression such as `1+1+…+1` nests as deep as
+/// it has terms and would overflow an ordinary thread's stack. The stage functions above
+/// use it already; callers that walk or drop the tree themselves can use it too.
+pub fn on_deep_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(DEEP_STACK)
+            .spawn_scoped(scope, f)
+            .expect("failed to start a compiler thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+const DEEP_STACK: usize = 256 * 1024 * 1024;
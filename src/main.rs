@@ -1,432 +1,516 @@
-#![allow(unused)]
 #![allow(non_snake_case)]
-mod lexer;
-mod token;
-mod parser;  // Add parser module
-mod semantics;
-use crate::semantics::scope;
-use crate::semantics::typechecker::{TypeChkError};  // Import TypeChecker and TypeChkError
-use crate::token::{Token, TokenKind};
-use crate::lexer::{HandLexer, RegexLexer};
-use crate::parser::parser::Parser;  // Import parser type
-use crate::parser::ast::{Expr, Stmt, Decl, Param, Program};  // Import AST types
-use crate::semantics::scope::scope::{ScopeStack, Symbol, SymbolKind, Type, ScopeError};
+use compiler::{diagnostic, lexer, parser, span};
+use compiler::diagnostic::{Diagnostic, ErrorFormat};
+use compiler::lexer::{HandLexer, Lexer, RegexLexer};
+use compiler::bytecode::{self, disasm};
+use compiler::ir;
+use compiler::ir::opt::{Pass, Pipeline};
+use compiler::interpreter::value::Value;
+use compiler::parser::ast::Expr;
+use compiler::parser::{dump, pretty};
 use std::env;
 use std::fs;
+use std::io::Read;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let path = args.get(1).map(|s| s.as_str()).unwrap_or("sample.src");
-    let src = fs::read_to_string(path).expect("failed to read source file");
+const USAGE: &str = "\
+usage: compiler <command> [options] [files...]
 
-    println!("LEXING");
-    let mut HandLexer = HandLexer::new(&src);
-    let tokens = match HandLexer.tokenize() {
-        Ok(tokens) => {
-            let reprs: Vec<String> = tokens.iter().map(|t| format!("{}", t)).collect();
-            println!("Tokens: [{}]", reprs.join(", "));
-            tokens
-        }
-        Err(e) => {
-            eprintln!("Lexing error: {}", e);
-            return;
-        }
-    };
+commands:
+  lex     split the input into tokens and print them
+  parse   parse the input and print its syntax tree
+  check   parse and type-check the input and report every problem
+  fmt     print the input reformatted
+  run     check the input, then run it and print its result
 
-    println!("\n PARSING ");
-    let mut parser = Parser::new(&tokens);
-    match parser.parse_program() {
-        Ok(program) => {
-            println!("Parsing successful!");
-            println!("{:#?}", program);
+options:
+  --lexer=hand|regex                which lexer to use (default: hand)
+  --emit=tokens|ast|typed-ast|ir|ssa|bytecode
+                                    what to print; typed-ast, ir and ssa (the IR in
+                                    SSA form) need `check`, bytecode `check` or `run`
+  --engine=vm|ast                   what `run` runs on: the bytecode VM (default) or
+                                    the tree-walking interpreter
+  -O0|-O1|-O2                       how much to optimise the IR before emitting it
+                                    (default: -O0)
+  --passes=NAME,...                 run exactly these passes, in this order, instead of
+                                    the ones the -O level picks
+  --no-pass=NAME,...                leave these passes out
+  --dump-passes                     print the IR before the passes and after each one
+  --error-format=human|json|sarif   how diagnostics are written to stderr (default: human)
 
-            println!("\n=== PROGRAM STRUCTURE ===");
-            print_program(&program);
+The passes are sccp, copy-prop, local-cse, gcse, dce, unreachable and merge-blocks.
 
-            println!("\n=== SEMANTIC ANALYSIS ===");
-            let (scope_errors, typechk_errors) = perform_semantic_analysis(&program);
+With no files, or with `-`, the input is read from stdin.
 
-            if scope_errors.is_empty() {
-                println!("Scope analysis successful! No errors found.");
-            } else {
-                println!("Scope analysis found {} errors:", scope_errors.len());
-                for error in &scope_errors {
-                    println!("  {:?}", error);
-                }
-            }
+development tools, given instead of a command:
+  --diff-lexers [FILES...]          run the hand and regex lexers over the files (default:
+                                    sample.src) and generated inputs, report where they differ
+  --check-trivia [FILES...]         check that lexing the files (default: sample.src) with
+                                    their comments and whitespace gives back the same text
+  --gen-grammar [PATH]              regenerate the expression grammar in grammar.md
+  --bench-lexers [MB]               time both lexers on MB megabytes of input (default: 4)
+  --bench-vm [N]                    time the VM and the interpreter, best of N runs (default: 3)
 
-            if typechk_errors.is_empty() {
-                println!("Type checking successful! No errors found.");
-            } else {
-                println!("Type checking found {} errors:", typechk_errors.len());
-                for error in &typechk_errors {
-                    println!("  {:?}", error);
-                }
-            }
+exit status: 0 success, 1 errors in the input, 2 bad command line, 3 an input could not be read";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Lex,
+    Parse,
+    Check,
+    Fmt,
+    Run,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Tokens,
+    Ast,
+    TypedAst,
+    Ir,
+    Ssa,
+    Bytecode,
+}
+
+struct Options {
+    command: Command,
+    regex_lexer: bool,
+    emit: Option<Emit>,
+    ast_engine: bool, // run on the interpreter instead of the VM
+    pipeline: Pipeline,
+    dump_passes: bool,
+    format: ErrorFormat,
+    files: Vec<String>, // "-" is stdin
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    // development tools
+    if args.get(1).map(|s| s.as_str()) == Some("--diff-lexers") {
+        std::process::exit(diff_lexers(&args[2..]));
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("--check-trivia") {
+        std::process::exit(check_trivia(&args[2..]));
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("--gen-grammar") {
+        let path = args.get(2).map(|s| s.as_str()).unwrap_or("grammar/grammar.md");
+        gen_grammar(path);
+        return;
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("--bench-vm") {
+        let iterations: usize = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(3);
+        bench_vm(iterations);
+        return;
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("--bench-lexers") {
+        let mb: f64 = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(4.0);
+        bench_lexers(mb);
+        return;
+    }
+
+    if matches!(args.get(1).map(|s| s.as_str()), Some("-h" | "--help")) {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) if message.is_empty() => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
         }
-        Err(e) => {
-            eprintln!("Parse error: {}", e);
-            return;
+        Err(message) => {
+            eprintln!("error: {}\nrun with --help for usage", message);
+            std::process::exit(2);
         }
-    }
+    };
+    // dumping, formatting and dropping a program recurse over it too
+    std::process::exit(compiler::on_deep_stack(|| drive(&options)));
 }
 
-// Helper function to pretty print the program
-fn print_program(program: &Program) {
-    println!("Program with {} declarations:", program.declarations.len());
-
-    for (i, decl) in program.declarations.iter().enumerate() {
-        println!("\nDeclaration {}:", i + 1);
-        match decl {
-            Decl::Function { name, params, return_type, body } => {
-                print!("  Function: {}(", name);
-                for (j, param) in params.iter().enumerate() {
-                    if j > 0 { print!(", ") }
-                    print!("{}: {:?}", param.name, param.param_type);
-                }
-                println!(")");
-                if let Some(rt) = return_type {
-                    println!("    Return type: {:?}", rt);
-                }
-                println!("    Body: {:#?}", body);
-            }
-            Decl::GlobalVar { name, type_annot, value } => {
-                print!("  Global variable: {}", name);
-                if let Some(ty) = type_annot {
-                    print!(": {:?}", ty);
-                }
-                if let Some(val) = value {
-                    println!(" = {:#?}", val);
-                } else {
-                    println!(" (uninitialized)");
-                }
-            }
-            Decl::Stmt(stmt) => {
-                println!("  Global statement: {:#?}", stmt);
-            }
+// an empty error means there was no command at all
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let command = match args.first().map(|s| s.as_str()) {
+        Some("lex") => Command::Lex,
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some("fmt") => Command::Fmt,
+        Some("run") => Command::Run,
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err(String::new()),
+    };
+    let mut options = Options {
+        command,
+        regex_lexer: false,
+        emit: None,
+        ast_engine: false,
+        pipeline: Pipeline::for_level(0),
+        dump_passes: false,
+        format: ErrorFormat::Human,
+        files: Vec::new(),
+    };
+    let (mut level, mut passes, mut excluded) = (None, None, Vec::new());
+    for arg in &args[1..] {
+        if let Some(value) = arg.strip_prefix("--lexer=") {
+            options.regex_lexer = match value {
+                "hand" => false,
+                "regex" => true,
+                _ => return Err(format!("unknown lexer '{}' (expected hand or regex)", value)),
+            };
+        } else if let Some(value) = arg.strip_prefix("--emit=") {
+            options.emit = Some(match value {
+                "tokens" => Emit::Tokens,
+                "ast" => Emit::Ast,
+                "typed-ast" => Emit::TypedAst,
+                "ir" => Emit::Ir,
+                "ssa" => Emit::Ssa,
+                "bytecode" => Emit::Bytecode,
+                _ => return Err(format!("unknown --emit value '{}' (expected tokens, ast, typed-ast, ir, ssa or bytecode)", value)),
+            });
+        } else if let Some(value) = arg.strip_prefix("--engine=") {
+            options.ast_engine = match value {
+                "vm" => false,
+                "ast" => true,
+                _ => return Err(format!("unknown engine '{}' (expected vm or ast)", value)),
+            };
+        } else if let Some(value) = arg.strip_prefix("-O") {
+            level = Some(match value {
+                "0" => 0,
+                "1" => 1,
+                "2" => 2,
+                _ => return Err(format!("unknown optimisation level '{}' (expected -O0, -O1 or -O2)", arg)),
+            });
+        } else if let Some(value) = arg.strip_prefix("--passes=") {
+            passes = Some(pass_list(value)?);
+        } else if let Some(value) = arg.strip_prefix("--no-pass=") {
+            excluded.extend(pass_list(value)?);
+        } else if arg == "--dump-passes" {
+            options.dump_passes = true;
+        } else if let Some(value) = arg.strip_prefix("--error-format=") {
+            options.format = ErrorFormat::parse(value)
+                .ok_or_else(|| format!("unknown error format '{}' (expected human, json or sarif)", value))?;
+        } else if arg.starts_with("--") {
+            return Err(format!("unknown option '{}'", arg));
+        } else {
+            options.files.push(arg.clone());
         }
     }
+    // each command stops after its own stage, so it can only emit what that stage made
+    match (command, options.emit) {
+        (Command::Lex, Some(Emit::Ast | Emit::TypedAst | Emit::Ir | Emit::Ssa | Emit::Bytecode)) => return Err("`lex` can only emit tokens".into()),
+        (Command::Parse, Some(Emit::TypedAst)) => return Err("--emit=typed-ast needs the `check` command".into()),
+        (Command::Parse | Command::Run, Some(Emit::Ir)) => return Err("--emit=ir needs the `check` command".into()),
+        (Command::Parse | Command::Run, Some(Emit::Ssa)) => return Err("--emit=ssa needs the `check` command".into()),
+        (Command::Parse, Some(Emit::Bytecode)) => return Err("--emit=bytecode needs the `check` or `run` command".into()),
+        (Command::Fmt, Some(_)) => return Err("`fmt` only prints the formatted source".into()),
+        (Command::Run, Some(Emit::Tokens | Emit::Ast | Emit::TypedAst)) => return Err("`run` can only emit bytecode".into()),
+        (Command::Lex, None) => options.emit = Some(Emit::Tokens),
+        (Command::Parse, None) => options.emit = Some(Emit::Ast),
+        _ => {}
+    }
+    if options.ast_engine && command != Command::Run {
+        return Err("--engine is for the `run` command".into());
+    }
+    let optimising = level.is_some() || passes.is_some() || !excluded.is_empty() || options.dump_passes;
+    if optimising && !matches!(options.emit, Some(Emit::Ir | Emit::Ssa)) {
+        return Err("-O and the pass options need --emit=ir or --emit=ssa".into());
+    }
+    options.pipeline = match passes {
+        Some(passes) => Pipeline { passes, max_rounds: 1 },
+        None => Pipeline::for_level(level.unwrap_or(0)),
+    };
+    for pass in excluded {
+        options.pipeline = options.pipeline.without(pass);
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+    Ok(options)
 }
 
-// Perform combined scope and type checking, collect errors from both
-fn perform_semantic_analysis(program: &Program) -> (Vec<ScopeError>, Vec<TypeChkError>) {
-    let mut scope_stack = ScopeStack::new();
-    let mut scope_errors = Vec::new();
-    let mut typechk_errors = Vec::new();
-
-    scope_stack.enter_scope();
+// `sccp,dce` as passes
+fn pass_list(names: &str) -> Result<Vec<Pass>, String> {
+    names
+        .split(',')
+        .map(|name| {
+            Pass::from_name(name).ok_or_else(|| {
+                let known: Vec<&str> = Pass::ALL.iter().map(|pass| pass.name()).collect();
+                format!("unknown pass '{}' (expected {})", name, known.join(", "))
+            })
+        })
+        .collect()
+}
 
-    for decl in &program.declarations {
-        match decl {
-            Decl::GlobalVar { name, type_annot, value } => {
-                let ty = if let Some(t) = type_annot { token_to_type(t.clone()) } else { Type::Void };
-                let initialized = value.is_some();
+// Runs the command over every input; returns the exit status
+fn drive(options: &Options) -> i32 {
+    // an input that cannot be read is reported and skipped; the others are still processed
+    // so that a JSON or SARIF document always comes out
+    let mut inputs = Vec::new();
+    for file in &options.files {
+        let (name, text) = if file == "-" {
+            let mut text = String::new();
+            ("<stdin>".to_string(), std::io::stdin().read_to_string(&mut text).map(|_| text))
+        } else {
+            (file.clone(), fs::read_to_string(file))
+        };
+        inputs.push((name, text.map_err(|e| e.to_string())));
+    }
 
-                if scope_stack.insert_variable(name.clone(), ty.clone(), true, initialized).is_err() {
-                    scope_errors.push(ScopeError::VariableRedefinition);
-                }
-                if let Some(val) = value {
-                    analyze_expr(val, &mut scope_stack, &mut scope_errors, &mut typechk_errors);
+    // with several inputs, whatever is printed for each one comes under its name; plain
+    // `check` prints nothing but diagnostics, which carry the name already
+    let headers = inputs.len() > 1 && (options.command != Command::Check || options.emit.is_some());
+    let mut reports = Vec::new();
+    for (i, (name, text)) in inputs.iter().enumerate() {
+        let src = match text {
+            Ok(src) => src,
+            Err(e) => {
+                if options.format == ErrorFormat::Human {
+                    eprintln!("error: cannot read {}: {}", name, e);
                 }
+                reports.push(Vec::new());
+                continue;
             }
-            Decl::Function { name, params, return_type, body } => {
-                let param_types: Vec<Type> = params.iter().map(|p| token_to_type(p.param_type.clone())).collect();
-                let ret_ty = if let Some(t) = return_type { token_to_type(t.clone()) } else { Type::Void };
-                if scope_stack.insert_function_definition(name.clone(), param_types.clone(), ret_ty.clone()).is_err() {
-                    scope_errors.push(ScopeError::FunctionRedefinition);
-                } else {
-                    scope_stack.enter_scope();
-                    for (p, ty) in params.iter().zip(param_types.iter()) {
-                        let _ = scope_stack.insert_variable(p.name.clone(), ty.clone(), false, true);
-                    }
-                    analyze_stmt(body, &mut scope_stack, &mut scope_errors, &mut typechk_errors, Some(&ret_ty));
-                    scope_stack.exit_scope();
-                }
+        };
+        if headers {
+            println!("{}==> {} <==", if i > 0 { "\n" } else { "" }, name);
+        }
+        let diagnostics = process(options, src);
+        if options.format == ErrorFormat::Human {
+            for d in &diagnostics {
+                eprintln!("{}", d.render(name, src));
             }
-            Decl::Stmt(stmt) => {
-                analyze_stmt(stmt, &mut scope_stack, &mut scope_errors, &mut typechk_errors, None);
+            if let Some(summary) = diagnostic::summary(&diagnostics) {
+                eprintln!("{}: {} generated", name, summary);
             }
         }
+        reports.push(diagnostics);
     }
 
-    scope_stack.exit_scope();
-
-    (scope_errors, typechk_errors)
+    let files: Vec<diagnostic::FileDiagnostics> = inputs
+        .iter()
+        .zip(&reports)
+        .map(|((name, text), diagnostics)| diagnostic::FileDiagnostics {
+            name,
+            src: text.as_deref().unwrap_or(""),
+            diagnostics,
+            read_error: text.as_ref().err().map(String::as_str),
+        })
+        .collect();
+    match options.format {
+        ErrorFormat::Human => {}
+        ErrorFormat::Json => eprint!("{}", diagnostic::to_json(&files)),
+        ErrorFormat::Sarif => eprint!("{}", diagnostic::to_sarif(&files)),
+    }
+    if inputs.iter().any(|(_, text)| text.is_err()) {
+        return 3;
+    }
+    // warnings alone don't fail the run
+    if reports.iter().flatten().any(Diagnostic::is_error) {
+        return 1;
+    }
+    0
 }
 
-fn analyze_stmt(stmt: &Stmt,
-                scope_stack: &mut ScopeStack,
-                scope_errors: &mut Vec<ScopeError>,
-                typechk_errors: &mut Vec<TypeChkError>,
-                current_return_type: Option<&Type>) {
-    match stmt {
-        Stmt::Expr(expr) => {
-            analyze_expr(expr, scope_stack, scope_errors, typechk_errors);
-        }
-        Stmt::Let { name, type_annot, value } => {
-            analyze_expr(value, scope_stack, scope_errors, typechk_errors);
-            let ty = type_annot.as_ref().map(|t| token_to_type(t.clone())).unwrap_or(Type::Void);
-            if let Err(e) = scope_stack.insert_variable(name.clone(), ty, false, true) {
-                scope_errors.push(e);
-            }
+// Runs one input through the stages the command needs, printing what it asked for on
+// stdout; returns every diagnostic in stage order
+fn process(options: &Options, src: &str) -> Vec<Diagnostic> {
+    let (tokens, mut diagnostics) = if options.command == Command::Fmt {
+        // fmt needs the comments, which only the trivia mode of HandLexer keeps
+        match HandLexer::new(src).tokenize_with_trivia() {
+            Ok(tokens) => (tokens, Vec::new()),
+            Err(e) => (Vec::new(), vec![Diagnostic::from(&e)]),
         }
-        Stmt::Block(stmts) => {
-            scope_stack.enter_scope();
-            for s in stmts {
-                analyze_stmt(s, scope_stack, scope_errors, typechk_errors, current_return_type);
-            }
-            scope_stack.exit_scope();
-        }
-        Stmt::Return(expr_opt) => {
-            if let Some(expr) = expr_opt {
-                let ty = match visit_expr(expr, scope_stack) {
-                    Ok(t) => t,
-                    Err(e) => {
-                        typechk_errors.push(e);
-                        Type::Void
-                    }
-                };
-                if let Some(expected) = current_return_type {
-                    if *expected != Type::Unknown && *expected != Type::Void && *expected != ty {
-                        typechk_errors.push(TypeChkError::ErroneousReturnType);
-                    }
-                }
-            } else {
-                if let Some(expected) = current_return_type {
-                    if *expected != Type::Void && *expected != Type::Unknown {
-                        typechk_errors.push(TypeChkError::ErroneousReturnType);
-                    }
-                }
-            }
-        }
-        Stmt::Break => {
-            if !scope_stack.in_loop() {
-                typechk_errors.push(TypeChkError::ErroneousBreak);
-            }
+    } else if options.regex_lexer {
+        // RegexLexer has no recovery: it stops at the first error
+        match RegexLexer::new(src).tokenize() {
+            Ok(tokens) => (tokens, Vec::new()),
+            Err(e) => (Vec::new(), vec![Diagnostic::from(&e)]),
         }
-        Stmt::If { condition, then_branch, else_branch } => {
-            match visit_expr(condition, scope_stack) {
-                Ok(t) => if t != Type::Bool {
-                    typechk_errors.push(TypeChkError::NonBooleanCondStmt);
-                },
-                Err(e) => typechk_errors.push(e),
-            }
-            analyze_stmt(then_branch, scope_stack, scope_errors, typechk_errors, current_return_type);
-            if let Some(else_stmt) = else_branch {
-                analyze_stmt(else_stmt, scope_stack, scope_errors, typechk_errors, current_return_type);
-            }
-        }
-        Stmt::While { condition, body } => {
-            match visit_expr(condition, scope_stack) {
-                Ok(t) => if t != Type::Bool {
-                    typechk_errors.push(TypeChkError::NonBooleanCondStmt);
-                },
-                Err(e) => typechk_errors.push(e),
-            }
-            scope_stack.enter_loop();
-            analyze_stmt(body, scope_stack, scope_errors, typechk_errors, current_return_type);
-            scope_stack.exit_loop();
+    } else {
+        compiler::lex(src)
+    };
+    if options.emit == Some(Emit::Tokens) {
+        for token in &tokens {
+            println!("{}:{} {}", token.line, token.col, token.kind);
         }
-        Stmt::For { init, condition, increment, body } => {
-            scope_stack.enter_scope();
-            scope_stack.enter_loop();
+    }
+    if options.command == Command::Lex || tokens.is_empty() {
+        return diagnostics;
+    }
 
-            if let Some(init_stmt) = init {
-                analyze_stmt(init_stmt, scope_stack, scope_errors, typechk_errors, current_return_type);
-            }
-            if let Some(cond) = condition {
-                match visit_expr(cond, scope_stack) {
-                    Ok(t) => if t != Type::Bool {
-                        typechk_errors.push(TypeChkError::NonBooleanCondStmt);
-                    },
-                    Err(e) => typechk_errors.push(e),
-                }
-            }
-            if let Some(incr) = increment {
-                analyze_expr(incr, scope_stack, scope_errors, typechk_errors);
+    let (program, parse_diagnostics) = compiler::parse_tokens(&tokens);
+    diagnostics.extend(parse_diagnostics);
+    if options.emit == Some(Emit::Ast) {
+        print!("{}", dump::dump_program(&program, src, &|_| None));
+    }
+    match options.command {
+        Command::Parse => return diagnostics,
+        Command::Fmt => {
+            // a program that didn't parse has lost pieces of its source
+            if diagnostics.is_empty() {
+                print!("{}", pretty::format_program(&program, &tokens, src));
             }
-            analyze_stmt(body, scope_stack, scope_errors, typechk_errors, current_return_type);
-
-            scope_stack.exit_scope();
-            scope_stack.exit_loop();
-        }
-        Stmt::Function { name, params, return_type, body } => {
-            // Nested functions ignored here as they are handled via declaration processing
+            return diagnostics;
         }
+        _ => {}
     }
-}
 
-fn analyze_expr(expr: &Expr, scope_stack: &mut ScopeStack, scope_errors: &mut Vec<ScopeError>, typechk_errors: &mut Vec<TypeChkError>) {
-    // Scope error checks
-    match expr {
-        Expr::Identifier(name) => {
-            if let Err(e) = scope_stack.lookup_variable(name) {
-                scope_errors.push(e);
+    // semantic analysis still runs on the parts that did parse
+    let (types, check_diagnostics) = compiler::check(&program);
+    diagnostics.extend(check_diagnostics);
+    if options.emit == Some(Emit::TypedAst) {
+        let annotate = |e: &Expr| types.get(&e.span).map(|t| t.to_string());
+        print!("{}", dump::dump_program(&program, src, &annotate));
+    }
+    // only a program that checked cleanly is compiled and run; warnings don't stop it
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return diagnostics;
+    }
+    if matches!(options.emit, Some(Emit::Ir | Emit::Ssa)) {
+        let mut module = compiler::lower(&program, &types);
+        // the passes work on SSA form; --emit=ir takes the result back out of it
+        let optimise = !options.pipeline.passes.is_empty();
+        if options.emit == Some(Emit::Ssa) || optimise {
+            for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+                ir::ssa::construct(function);
             }
         }
-        Expr::Binary { left, right, .. } => {
-            analyze_expr(left, scope_stack, scope_errors, typechk_errors);
-            analyze_expr(right, scope_stack, scope_errors, typechk_errors);
+        if options.dump_passes {
+            print!("=== before optimisation ===\n{}", ir::dump::dump_module(&module));
         }
-        Expr::Unary { expr: inner, .. } => {
-            analyze_expr(inner, scope_stack, scope_errors, typechk_errors);
-        }
-        Expr::Call { callee, args } => {
-            analyze_expr(callee, scope_stack, scope_errors, typechk_errors);
-            for arg in args {
-                analyze_expr(arg, scope_stack, scope_errors, typechk_errors);
-            }
-            if let Expr::Identifier(name) = &**callee {
-                if let Err(e) = scope_stack.lookup_function(name) {
-                    scope_errors.push(e);
-                }
+        options.pipeline.run(&mut module, |pass, changed, module| match (options.dump_passes, changed) {
+            (false, _) => {}
+            (true, true) => print!("=== after {} ===\n{}", pass.name(), ir::dump::dump_module(module)),
+            (true, false) => println!("=== after {}: no change ===", pass.name()),
+        });
+        if options.emit == Some(Emit::Ir) && optimise {
+            for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+                ir::ssa::destruct(function);
             }
         }
-        Expr::Grouping(inner) => {
-            analyze_expr(inner, scope_stack, scope_errors, typechk_errors);
+        if options.dump_passes {
+            println!("=== result ===");
         }
-        Expr::Integer(_) | Expr::Float(_) | Expr::Boolean(_) | Expr::StringLit(_) => {}
+        print!("{}", ir::dump::dump_module(&module));
     }
-
-    // Type checking errors
-    match visit_expr(expr, scope_stack) {
-        Ok(_) => {}
-        Err(e) => typechk_errors.push(e)
+    let module = (options.emit == Some(Emit::Bytecode) || (options.command == Command::Run && !options.ast_engine))
+        .then(|| compiler::compile(&program));
+    if let Some(module) = &module
+        && options.emit == Some(Emit::Bytecode)
+    {
+        print!("{}", disasm::disassemble(module, src));
     }
+    if options.command == Command::Run {
+        // --emit=bytecode compiles even for --engine=ast, which still runs the tree
+        let result = match &module {
+            Some(module) if !options.ast_engine => compiler::execute(module),
+            _ => compiler::run(&program),
+        };
+        match result {
+            Ok(Value::Void) => {}
+            Ok(value) => println!("{}", value),
+            Err(e) => diagnostics.push(Diagnostic::from(&e)),
+        }
+    }
+    diagnostics
 }
 
-// Core type checker logic for expressions - returns type or TypeChkError
-fn visit_expr(expr: &Expr, scope_stack: &mut ScopeStack) -> Result<Type, TypeChkError> {
-    match expr {
-        Expr::Identifier(name) => {
-            match scope_stack.lookup_variable(name) {
-                Ok(sym) => {
-                    if let Some(ty) = &sym.ty {
-                        Ok(ty.clone())
-                    } else {
-                        Ok(Type::Unknown)
-                    }
-                }
-                Err(_) => Err(TypeChkError::ErroneousVarDecl)
+// Runs HandLexer and RegexLexer side by side over the given files (sample.src by default)
+// plus generated inputs; returns the process exit code
+fn diff_lexers(paths: &[String]) -> i32 {
+    let paths: Vec<String> = if paths.is_empty() { vec!["sample.src".to_string()] } else { paths.to_vec() };
+    let mut corpus: Vec<(String, String)> = Vec::new();
+    for path in &paths {
+        match fs::read_to_string(path) {
+            Ok(src) => corpus.push((path.clone(), src)),
+            Err(e) => {
+                eprintln!("error: cannot read {}: {}", path, e);
+                return 3;
             }
         }
-        Expr::Integer(_) => Ok(Type::Int),
-        Expr::Float(_) => Ok(Type::Float),
-        Expr::Boolean(_) => Ok(Type::Bool),
-        Expr::StringLit(_) => Ok(Type::String),
+    }
+    let generated = 500;
+    let failures = lexer::differential::run(&corpus, generated, 1);
 
-        Expr::Unary { operator, expr: inner } => {
-            let t = visit_expr(inner, scope_stack)?;
-            match operator {
-                TokenKind::T_MINUS => {
-                    if matches!(t, Type::Int | Type::Float) {
-                        Ok(t)
-                    } else {
-                        Err(TypeChkError::AttemptedAddOpOnNonNumeric)
-                    }
-                }
-                TokenKind::T_NOT => {
-                    if t == Type::Bool {
-                        Ok(Type::Bool)
-                    } else {
-                        Err(TypeChkError::AttemptedBoolOpOnNonBools)
-                    }
-                }
-                _ => Ok(t),
-            }
+    println!("Compared lexers on {} inputs", corpus.len() + generated);
+    if failures.is_empty() {
+        println!("No divergences found.");
+        return 0;
+    }
+    for (name, src, d) in &failures {
+        println!("{}: {}", name, d);
+        if name.starts_with("generated") {
+            println!("  input: {:?}", src);
         }
+    }
+    println!("{} inputs diverged", failures.len());
+    1
+}
 
-        Expr::Binary { left, operator, right } => {
-            let lt = visit_expr(left, scope_stack)?;
-            let rt = visit_expr(right, scope_stack)?;
-
-            match operator {
-                TokenKind::T_PLUS | TokenKind::T_MINUS | TokenKind::T_STAR | TokenKind::T_SLASH | TokenKind::T_PERCENT => {
-                    if !matches!(lt, Type::Int | Type::Float) || !matches!(rt, Type::Int | Type::Float) {
-                        return Err(TypeChkError::AttemptedAddOpOnNonNumeric);
-                    }
-                    if lt == Type::Float || rt == Type::Float { Ok(Type::Float) } else { Ok(Type::Int) }
-                }
-                TokenKind::T_CARET => {
-                    if !matches!(lt, Type::Int | Type::Float) || !matches!(rt, Type::Int | Type::Float) {
-                        return Err(TypeChkError::AttemptedExponentiationOfNonNumeric);
-                    }
-                    if lt == Type::Float || rt == Type::Float { Ok(Type::Float) } else { Ok(Type::Int) }
-                }
-                TokenKind::T_ANDAND | TokenKind::T_OROR => {
-                    if lt != Type::Bool || rt != Type::Bool {
-                        return Err(TypeChkError::AttemptedBoolOpOnNonBools);
-                    }
-                    Ok(Type::Bool)
-                }
-                TokenKind::T_LSHIFT | TokenKind::T_RSHIFT => {
-                    if lt != Type::Int || rt != Type::Int {
-                        return Err(TypeChkError::AttemptedShiftOnNonInt);
-                    }
-                    Ok(Type::Int)
-                }
-                TokenKind::T_EQUALSOP | TokenKind::T_NEQ | TokenKind::T_LT | TokenKind::T_GT | TokenKind::T_LTE | TokenKind::T_GTE => {
-                    if lt == Type::Unknown || rt == Type::Unknown {
-                        if lt != rt && lt != Type::Unknown && rt != Type::Unknown {
-                            return Err(TypeChkError::ExpressionTypeMismatch);
-                        } else {
-                            Ok(Type::Bool)
-                        }
-                    } else if lt != rt {
-                        return Err(TypeChkError::ExpressionTypeMismatch);
-                    } else {
-                        Ok(Type::Bool)
-                    }
-                }
-                _ => Ok(Type::Unknown),
+// Lexes each file (sample.src by default) in trivia-preserving mode and checks that
+// writing the tokens back out reproduces the file; returns the process exit code
+fn check_trivia(paths: &[String]) -> i32 {
+    let paths: Vec<String> = if paths.is_empty() { vec!["sample.src".to_string()] } else { paths.to_vec() };
+    let mut failed = 0;
+    for path in &paths {
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("error: cannot read {}: {}", path, e);
+                return 3;
             }
-        }
-
-            Expr::Call { callee, args } => {
-                if let Expr::Identifier(name) = &**callee {
-                    let func_sym = match scope_stack.lookup_function(name) {
-                        Ok(sym) => sym,
-                        Err(_) => return Err(TypeChkError::ErroneousVarDecl),
-                    };
-                    let (param_types, ret_type) = if let SymbolKind::Function { params, return_type, .. } = &func_sym.kind {
-                        (params.clone(), return_type.clone())
-                    } else {
-                        return Err(TypeChkError::ErroneousVarDecl);
-                    };
-                    drop(func_sym);
-
-                    if param_types.len() != args.len() {
-                        return Err(TypeChkError::FnCallParamCount);
-                    }
-
-                    for (i, arg) in args.iter().enumerate() {
-                        let arg_ty = visit_expr(arg, scope_stack)?;
-                        let param_ty = param_types.get(i).cloned().unwrap_or(Type::Unknown);
-                        if param_ty != Type::Unknown && arg_ty != Type::Unknown && param_ty != arg_ty {
-                            return Err(TypeChkError::FnCallParamType);
-                        }
-                    }
-                    return Ok(ret_type);
-                }
-                let _ = visit_expr(callee, scope_stack)?;
-                for a in args {
-                    let _ = visit_expr(a, scope_stack)?;
-                }
-                Ok(Type::Unknown)
+        };
+        let tokens = match HandLexer::new(&src).tokenize_with_trivia() {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                println!("{}: lexing error: {}", path, e);
+                failed += 1;
+                continue;
             }
+        };
+        let out = lexer::to_source(&tokens, &src);
+        if out == src {
+            let trivia: usize = tokens.iter().map(|t| t.leading.len() + t.trailing.len()).sum();
+            println!("{}: lossless ({} tokens, {} trivia)", path, tokens.len(), trivia);
+        } else {
+            let at = out.bytes().zip(src.bytes()).take_while(|(a, b)| a == b).count();
+            let (line, col) = span::line_col(&src, at);
+            println!("{}: output differs from the input at {}:{}", path, line, col);
+            failed += 1;
+        }
+    }
+    if failed == 0 { 0 } else { 1 }
+}
 
-        Expr::Grouping(inner) => visit_expr(inner, scope_stack),
+// Regenerates the expression section of grammar.md (between the GENERATED markers)
+// from the operator table in parser/precedence.rs
+fn gen_grammar(path: &str) {
+    const BEGIN: &str = "<!-- BEGIN GENERATED: expressions (cargo run -- --gen-grammar) -->\n";
+    const END: &str = "<!-- END GENERATED: expressions -->\n";
+    let doc = fs::read_to_string(path).expect("failed to read grammar file");
+    let (Some(begin), Some(end)) = (doc.find(BEGIN), doc.find(END)) else {
+        eprintln!("{}: generated-section markers not found", path);
+        std::process::exit(1);
+    };
+    let updated = format!("{}{}{}{}", &doc[..begin + BEGIN.len()], parser::precedence::render_markdown(), END, &doc[end + END.len()..]);
+    fs::write(path, updated).expect("failed to write grammar file");
+    println!("Updated {}", path);
+}
 
-        _ => Ok(Type::Unknown),
+// Times both lexers on a generated source of roughly `mb` megabytes
+fn bench_lexers(mb: f64) {
+    let results = lexer::bench::run((mb * 1024.0 * 1024.0) as usize, 3);
+    println!("Lexing {:.2} MB (best of 3)", results[0].bytes as f64 / (1024.0 * 1024.0));
+    for r in &results {
+        println!("  {}", r);
+    }
+    if results[0].tokens != results[1].tokens {
+        println!("warning: token counts differ, run --diff-lexers");
     }
 }
 
-fn token_to_type(token: TokenKind) -> Type {
-    match token {
-        TokenKind::T_INT => Type::Int,
-        TokenKind::T_FLOAT => Type::Float,
-        TokenKind::T_BOOL => Type::Bool,
-        TokenKind::T_STRING => Type::Custom("String".to_string()), 
-        _ => Type::Void, // Default fallback type
+// Times the VM against the tree-walking interpreter on the programs in bytecode/bench.rs
+fn bench_vm(iterations: usize) {
+    println!("Running {} programs (best of {})", bytecode::bench::PROGRAMS.len(), iterations);
+    for r in &bytecode::bench::run(iterations) {
+        println!("  {}", r);
     }
 }
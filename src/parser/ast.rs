@@ -1,11 +1,21 @@
 use crate::token::TokenKind;
+use crate::span::Span;
+
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     Identifier(String),
     Integer(i64),
     Float(f64),
     Boolean(bool),
     StringLit(String),
+    Char(char),
+    Array(Vec<Expr>), // [1, 2, 3]
     Binary {
         left: Box<Expr>, //Box is a smart pointer that allocates data on heap, here particularly pointer to another expression on the heap
         operator: TokenKind,
@@ -20,15 +30,38 @@ pub enum Expr {
         args: Vec<Expr>,
     },
     Grouping(Box<Expr>), // just a wrapper around another expression
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Field {
+        object: Box<Expr>,
+        field: String,
+    },
+    StructLit {
+        name: String,
+        fields: Vec<FieldInit>, // in source order, duplicates included
+    },
+    Cast {
+        target: TokenKind, // T_INT, T_CHAR, T_STRING, etc. as in `int('a')`
+        expr: Box<Expr>,
+    },
+    Error, // placeholder for an expression that could not be lexed or parsed
 }
 
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
     Expr(Expr),
     Let {
         name: String,
-        type_annot: Option<TokenKind>, // T_INT, T_FLOAT, etc.
-        value: Expr,
+        type_annot: Option<TypeAnnot>,
+        value: Option<Expr>, // None: must be assigned before it is read
     },
     Block(Vec<Stmt>),
     Return(Option<Expr>),
@@ -51,34 +84,93 @@ pub enum Stmt {
     Function {
         name: String,
         params: Vec<Param>,
-        return_type: Option<TokenKind>,
+        return_type: Option<TypeAnnot>,
         body: Box<Stmt>,
     },
+    Error, // placeholder for a statement skipped during error recovery
+}
+
+// A type as written in the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAnnot {
+    Named(TokenKind),             // T_INT, T_FLOAT, T_BOOL, T_STRING or T_CHAR
+    Array(Box<TypeAnnot>, usize), // element type and length: int[10]
+    Struct(String),               // name of a struct declared with `struct Name { ... }`
+}
+
+// `x: 1` inside a struct literal
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub name: String,
+    pub value: Expr,
+    pub span: Span,
+}
+
+// `int x;` inside a struct declaration
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub field_type: TypeAnnot,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Param {
     pub name: String,
-    pub param_type: TokenKind, // T_INT, T_FLOAT, etc.
+    pub param_type: TypeAnnot,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Decl {
+    pub kind: DeclKind,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum Decl {
+pub enum DeclKind {
     Function {
         name: String,
         params: Vec<Param>,
-        return_type: Option<TokenKind>,
+        return_type: Option<TypeAnnot>,
         body: Box<Stmt>,
     },
+    Prototype { // `fn name(params): type;` declares a function defined elsewhere in the file
+        name: String,
+        params: Vec<Param>,
+        return_type: Option<TypeAnnot>,
+    },
     GlobalVar {
         name: String,
-        type_annot: Option<TokenKind>,
+        type_annot: Option<TypeAnnot>,
         value: Option<Expr>,
     },
+    Struct {
+        name: String,
+        fields: Vec<Field>,
+    },
     Stmt(Stmt),
 }
 
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+impl Decl {
+    pub fn new(kind: DeclKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub declarations: Vec<Decl>,
-}
\ No newline at end of file
+}
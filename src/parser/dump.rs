@@ -0,0 +1,199 @@
+// Indented tree view of a Program for `--emit=ast` and `--emit=typed-ast`: one node per
+// line with its source position, e.g.
+//
+//   Let x: int @4:1
+//     Binary + : int @4:9
+//       Integer 1 : int @4:9
+//
+// `annotate` supplies the text after an expression (its type for typed-ast); it returns
+// None for expressions that have nothing to show.
+use crate::parser::ast::{Decl, DeclKind, Expr, ExprKind, Param, Program, Stmt, StmtKind, TypeAnnot};
+use crate::parser::pretty::{expr_text, type_keyword, type_text};
+use crate::parser::precedence;
+use crate::span::Span;
+
+pub fn dump_program(program: &Program, src: &str, annotate: &dyn Fn(&Expr) -> Option<String>) -> String {
+    let mut d = Dumper { src, annotate, out: String::new(), depth: 0 };
+    d.node("Program", None);
+    d.depth += 1;
+    for decl in &program.declarations {
+        d.decl(decl);
+    }
+    d.out
+}
+
+struct Dumper<'a> {
+    src: &'a str,
+    annotate: &'a dyn Fn(&Expr) -> Option<String>,
+    out: String,
+    depth: usize,
+}
+
+impl Dumper<'_> {
+    fn node(&mut self, text: impl AsRef<str>, span: Option<Span>) {
+        self.out.push_str(&"  ".repeat(self.depth));
+        self.out.push_str(text.as_ref());
+        if let Some(span) = span {
+            let (line, col) = span.line_col(self.src);
+            self.out.push_str(&format!(" @{}:{}", line, col));
+        }
+        self.out.push('\n');
+    }
+
+    fn child(&mut self, f: impl FnOnce(&mut Self)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match &decl.kind {
+            DeclKind::Function { name, params, return_type, body } => {
+                self.node(format!("Function {}{}", name, signature(params, return_type.as_ref())), Some(decl.span));
+                self.child(|d| d.stmt(body));
+            }
+            DeclKind::Prototype { name, params, return_type } => {
+                self.node(format!("Prototype {}{}", name, signature(params, return_type.as_ref())), Some(decl.span));
+            }
+            DeclKind::GlobalVar { name, type_annot, value } => {
+                self.node(format!("GlobalVar {}{}", name, annot(type_annot.as_ref())), Some(decl.span));
+                if let Some(value) = value {
+                    self.child(|d| d.expr(value));
+                }
+            }
+            DeclKind::Struct { name, fields } => {
+                self.node(format!("Struct {}", name), Some(decl.span));
+                self.child(|d| {
+                    for field in fields {
+                        d.node(format!("Field {}: {}", field.name, type_text(&field.field_type)), Some(field.span));
+                    }
+                });
+            }
+            DeclKind::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let span = Some(stmt.span);
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.node("ExprStmt", span);
+                self.child(|d| d.expr(expr));
+            }
+            StmtKind::Let { name, type_annot, value } => {
+                self.node(format!("Let {}{}", name, annot(type_annot.as_ref())), span);
+                if let Some(value) = value {
+                    self.child(|d| d.expr(value));
+                }
+            }
+            StmtKind::Block(stmts) => {
+                self.node("Block", span);
+                self.child(|d| stmts.iter().for_each(|s| d.stmt(s)));
+            }
+            StmtKind::Return(value) => {
+                self.node("Return", span);
+                if let Some(value) = value {
+                    self.child(|d| d.expr(value));
+                }
+            }
+            StmtKind::Break => self.node("Break", span),
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.node("If", span);
+                self.child(|d| {
+                    d.expr(condition);
+                    d.stmt(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        d.node("Else", None);
+                        d.child(|d| d.stmt(else_branch));
+                    }
+                });
+            }
+            StmtKind::While { condition, body } => {
+                self.node("While", span);
+                self.child(|d| {
+                    d.expr(condition);
+                    d.stmt(body);
+                });
+            }
+            StmtKind::For { init, condition, increment, body } => {
+                self.node("For", span);
+                self.child(|d| {
+                    if let Some(init) = init {
+                        d.stmt(init);
+                    }
+                    if let Some(condition) = condition {
+                        d.expr(condition);
+                    }
+                    if let Some(increment) = increment {
+                        d.expr(increment);
+                    }
+                    d.stmt(body);
+                });
+            }
+            StmtKind::Function { name, params, return_type, body } => {
+                self.node(format!("Function {}{}", name, signature(params, return_type.as_ref())), span);
+                self.child(|d| d.stmt(body));
+            }
+            StmtKind::Error => self.node("Error", span),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let label = match &expr.kind {
+            ExprKind::Identifier(name) => format!("Identifier {}", name),
+            ExprKind::Integer(_) => format!("Integer {}", expr_text(expr)),
+            ExprKind::Float(_) => format!("Float {}", expr_text(expr)),
+            ExprKind::Boolean(_) => format!("Boolean {}", expr_text(expr)),
+            ExprKind::StringLit(_) => format!("String {}", expr_text(expr)),
+            ExprKind::Char(_) => format!("Char {}", expr_text(expr)),
+            ExprKind::Array(_) => "Array".to_string(),
+            ExprKind::Binary { operator, .. } => format!("Binary {}", precedence::spelling(operator).unwrap_or("?")),
+            ExprKind::Unary { operator, .. } => format!("Unary {}", precedence::spelling(operator).unwrap_or("?")),
+            ExprKind::Call { .. } => "Call".to_string(),
+            ExprKind::Grouping(_) => "Grouping".to_string(),
+            ExprKind::Index { .. } => "Index".to_string(),
+            ExprKind::Field { field, .. } => format!("Field .{}", field),
+            ExprKind::StructLit { name, .. } => format!("StructLit {}", name),
+            ExprKind::Cast { target, .. } => format!("Cast {}", type_keyword(target)),
+            ExprKind::Error => "Error".to_string(),
+        };
+        let label = match (self.annotate)(expr) {
+            Some(note) => format!("{} : {}", label, note),
+            None => label,
+        };
+        self.node(label, Some(expr.span));
+        self.child(|d| match &expr.kind {
+            ExprKind::Array(elements) => elements.iter().for_each(|e| d.expr(e)),
+            ExprKind::Binary { left, right, .. } => {
+                d.expr(left);
+                d.expr(right);
+            }
+            ExprKind::Unary { expr: inner, .. } | ExprKind::Grouping(inner) | ExprKind::Cast { expr: inner, .. } => d.expr(inner),
+            ExprKind::Call { callee, args } => {
+                d.expr(callee);
+                args.iter().for_each(|a| d.expr(a));
+            }
+            ExprKind::Index { array, index } => {
+                d.expr(array);
+                d.expr(index);
+            }
+            ExprKind::Field { object, .. } => d.expr(object),
+            ExprKind::StructLit { fields, .. } => {
+                for field in fields {
+                    d.node(format!("FieldInit {}", field.name), Some(field.span));
+                    d.child(|d| d.expr(&field.value));
+                }
+            }
+            _ => {}
+        });
+    }
+}
+
+fn annot(type_annot: Option<&TypeAnnot>) -> String {
+    type_annot.map_or(String::new(), |t| format!(": {}", type_text(t)))
+}
+
+fn signature(params: &[Param], return_type: Option<&TypeAnnot>) -> String {
+    let params: Vec<String> = params.iter().map(|p| format!("{}: {}", p.name, type_text(&p.param_type))).collect();
+    format!("({}){}", params.join(", "), annot(return_type))
+}
@@ -1,4 +1,6 @@
 use crate::token::TokenKind;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
 use std::fmt;
 
 #[derive(Debug)]
@@ -6,6 +8,7 @@ pub struct ParseError {
     pub kind: ParseErrorKind,
     pub line: usize,
     pub col: usize,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -17,6 +20,7 @@ pub enum ParseErrorKind {
     UnexpectedToken(TokenKind),
     Expected(String), 
     UnexpectedStmt,
+    InvalidToken, // parser ran into a T_ERROR token; the lexer already reported it
 }
 
 impl fmt::Display for ParseError {
@@ -29,7 +33,27 @@ impl fmt::Display for ParseError {
             ParseErrorKind::UnexpectedToken(kind) => write!(f, "Unexpected token: {}", kind),
             ParseErrorKind::UnexpectedStmt => write!(f, "Expected statement"),
             ParseErrorKind::Expected(msg) => write!(f, "{}", msg),
+            ParseErrorKind::InvalidToken => write!(f, "Invalid token"),
         }?;
         write!(f, " at line {}, column {}", self.line, self.col)
     }
 }
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Self {
+        let span = e.span;
+        match &e.kind {
+            ParseErrorKind::UnexpectedEOF => Diagnostic::error("E0201", "unexpected end of file", span),
+            ParseErrorKind::ExpectedIdentifier => Diagnostic::error("E0202", "expected identifier", span),
+            ParseErrorKind::ExpectedTypeToken => Diagnostic::error("E0203", "expected a type", span)
+                .with_note("types are int, float, bool, char, string or a struct name"),
+            ParseErrorKind::ExpectedExpr => Diagnostic::error("E0204", "expected expression", span),
+            ParseErrorKind::UnexpectedToken(kind) => {
+                Diagnostic::error("E0205", format!("unexpected token {}", kind), span)
+            }
+            ParseErrorKind::Expected(what) => Diagnostic::error("E0206", format!("expected {}", what), span),
+            ParseErrorKind::UnexpectedStmt => Diagnostic::error("E0207", "expected statement", span),
+            ParseErrorKind::InvalidToken => Diagnostic::error("E0208", "invalid token", span),
+        }
+    }
+}
@@ -1,4 +1,7 @@
 pub mod ast;
+#[allow(clippy::module_inception)]
 pub mod parser;
 pub mod error;
-    
\ No newline at end of file
+pub mod precedence;
+pub mod pretty;
+pub mod dump;
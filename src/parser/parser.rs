@@ -1,15 +1,18 @@
 use crate::token::{Token, TokenKind};
+use crate::span::Span;
 use crate::parser::error::{ParseError, ParseErrorKind};
-use crate::parser::ast::{Expr, Stmt, Decl, Param, Program};
+use crate::parser::precedence::{self, Assoc};
+use crate::parser::ast::{Expr, ExprKind, Stmt, StmtKind, Decl, DeclKind, Param, Program, TypeAnnot, Field, FieldInit};
 
 pub struct Parser<'a> {
     tokens: &'a [Token],  //not Vec<Token> because we dont need to own the tokens, just borrow them
     current: usize,
+    errors: Vec<ParseError>, // errors recovered from so far
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Parser { tokens, current: 0 }
+        Parser { tokens, current: 0, errors: Vec::new() }
     }
 
     // UTILITY func stuff
@@ -27,7 +30,41 @@ impl<'a> Parser<'a> {
 
     fn is_at_end(&self) -> bool {
         //on peeking if no next token (None), return true (or invoked) -- we are at the verge and matches is true if the token kind is T_EOF, |t| holds the token from Some(token). -- idiomatic feel 
-        self.peek().map_or(true, |t| matches!(t.kind, TokenKind::T_EOF))
+        self.peek().is_none_or(|t| matches!(t.kind, TokenKind::T_EOF))
+    }
+
+    // span of the token about to be consumed (start of the node being parsed)
+    fn peek_span(&self) -> Span {
+        self.peek().or_else(|| self.tokens.last()).map(|t| t.span).unwrap_or_default()
+    }
+
+    // span of the last consumed token (end of the node being parsed)
+    fn previous_span(&self) -> Span {
+        self.previous().map(|t| t.span).unwrap_or_default()
+    }
+
+    // span from `start` up to and including the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    fn error_at(token: Option<&Token>, kind: ParseErrorKind) -> ParseError {
+        let (line, col, span) = token.map(|t| (t.line, t.col, t.span)).unwrap_or((0, 0, Span::default()));
+        // an error token already has a LexError behind it, don't pretend it is a new syntax problem
+        let kind = if token.is_some_and(|t| t.kind == TokenKind::T_ERROR) {
+            ParseErrorKind::InvalidToken
+        } else {
+            kind
+        };
+        ParseError { kind, line, col, span }
+    }
+
+    fn error_at_previous(&self, kind: ParseErrorKind) -> ParseError {
+        Self::error_at(self.previous(), kind)
+    }
+
+    fn error_at_current(&self, kind: ParseErrorKind) -> ParseError {
+        Self::error_at(self.peek(), kind)
     }
 
     fn advance(&mut self) -> Option<&Token> {
@@ -57,166 +94,172 @@ impl<'a> Parser<'a> {
         if self.check(kind) {
             self.advance();
             Ok(())
+        } else if self.check(&TokenKind::T_ERROR) {
+            Err(self.error_at_current(ParseErrorKind::InvalidToken))
         } else {
-            let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-            Err(ParseError {
-                kind: ParseErrorKind::Expected(error_msg.to_string()),
-                line,
-                col,
-            })
+            Err(self.error_at_previous(ParseErrorKind::Expected(error_msg.to_string())))
         }
     }
 
-    // expression parsing 
-    pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
-        self.parse_assignment()
-    }
-
-    fn parse_assignment(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.parse_bitwise_or()?; // Changed to parse bitwise_or before assignment for precedence
-        if self.match_token(&TokenKind::T_ASSIGNOP) {
-            let value = self.parse_assignment()?; // Right-associative
-            if let Expr::Identifier(name) = expr {
-                return Ok(Expr::Binary {
-                    left: Box::new(Expr::Identifier(name)),
-                    operator: TokenKind::T_ASSIGNOP,
-                    right: Box::new(value),
-                });
-            }
-            let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-            return Err(ParseError { kind: ParseErrorKind::Expected("variable name".to_string()), line, col });
-        }
-        Ok(expr)
-    }
+    // ERROR RECOVERY (panic mode)
 
-    fn parse_bitwise_or(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_bitwise_and()?;
-        while self.match_token(&TokenKind::T_PIPE) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_bitwise_and()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-        Ok(expr)
+    // records `err`, skips to the next statement/declaration boundary and returns a
+    // Stmt::Error covering whatever was skipped since token index `start`
+    fn recover(&mut self, err: ParseError, start: usize) -> Stmt {
+        self.errors.push(err);
+        self.synchronize(start);
+        let first = self.tokens.get(start).map(|t| t.span).unwrap_or_default();
+        Stmt::new(StmtKind::Error, self.span_from(first))
     }
 
-    fn parse_bitwise_and(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_equality()?;
-        while self.match_token(&TokenKind::T_AMP) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_equality()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+    fn synchronize(&mut self, start: usize) {
+        // the construct failed on its very first token: drop it so we always make progress
+        if self.current == start {
+            self.advance();
+        }
+        while !self.is_at_end() {
+            if self.previous().is_some_and(|t| t.kind == TokenKind::T_SEMICOLON) {
+                return;
+            }
+            if matches!(self.peek().map(|t| &t.kind), Some(
+                TokenKind::T_BRACER | TokenKind::T_FUNCTION | TokenKind::T_STRUCT |
+                TokenKind::T_INT | TokenKind::T_FLOAT | TokenKind::T_BOOL | TokenKind::T_STRING | TokenKind::T_CHAR
+            )) {
+                return;
+            }
+            self.advance();
         }
-        Ok(expr)
     }
 
-    fn parse_equality(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_comparison()?;
-
-        while self.match_token(&TokenKind::T_EQUALSOP) || self.match_token(&TokenKind::T_NEQ) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_comparison()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+    // skips the rest of a broken parenthesised condition, leaving the closing ')' unconsumed
+    fn skip_to_closing_paren(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::T_PARENL => depth += 1,
+                TokenKind::T_PARENR if depth == 0 => return,
+                TokenKind::T_PARENR => depth -= 1,
+                TokenKind::T_BRACEL | TokenKind::T_BRACER | TokenKind::T_SEMICOLON | TokenKind::T_EOF => return,
+                _ => {}
+            }
+            self.advance();
         }
-        Ok(expr)
     }
 
-    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_term()?;
-
-        while self.match_token(&TokenKind::T_GT) || self.match_token(&TokenKind::T_GTE) ||
-              self.match_token(&TokenKind::T_LT) || self.match_token(&TokenKind::T_LTE) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_term()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+    // skips the rest of a broken initializer, leaving the ';' that ends the declaration
+    // unconsumed
+    fn skip_to_semicolon(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::T_PARENL | TokenKind::T_BRACKETL | TokenKind::T_BRACEL => depth += 1,
+                TokenKind::T_BRACER if depth == 0 => return,
+                TokenKind::T_PARENR | TokenKind::T_BRACKETR | TokenKind::T_BRACER => depth -= 1,
+                TokenKind::T_SEMICOLON | TokenKind::T_EOF => return,
+                _ => {}
+            }
+            self.advance();
         }
-        Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_shift()?;
-
-        while self.match_token(&TokenKind::T_PLUS) || self.match_token(&TokenKind::T_MINUS) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_shift()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+    // parses the value after `=` in a declaration; a broken one becomes Expr::Error so the
+    // variable is still declared and its uses don't report it missing
+    fn parse_initializer(&mut self) -> Expr {
+        let start = self.peek_span();
+        match self.parse_expression() {
+            Ok(value) => value,
+            Err(e) => {
+                self.errors.push(e);
+                self.skip_to_semicolon();
+                Expr::new(ExprKind::Error, self.span_from(start))
+            }
         }
-        Ok(expr)
     }
 
-    fn parse_shift(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_factor()?;
-
-        while self.match_token(&TokenKind::T_LSHIFT) || self.match_token(&TokenKind::T_RSHIFT) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_factor()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
+    // parses `( expr )` after if/while; a broken condition becomes Expr::Error so the body is still parsed
+    fn parse_condition(&mut self, keyword: &str) -> Result<Expr, ParseError> {
+        self.consume(&TokenKind::T_PARENL, &format!("'(' after '{}'", keyword))?;
+        let start = self.peek_span();
+        match self.parse_expression() {
+            Ok(condition) => {
+                self.consume(&TokenKind::T_PARENR, "')' after condition")?;
+                Ok(condition)
+            }
+            Err(e) => {
+                self.errors.push(e);
+                self.skip_to_closing_paren();
+                let condition = Expr::new(ExprKind::Error, self.span_from(start));
+                self.match_token(&TokenKind::T_PARENR);
+                Ok(condition)
+            }
         }
-        Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_caret()?;
-
-        while self.match_token(&TokenKind::T_STAR) || self.match_token(&TokenKind::T_SLASH) ||
-              self.match_token(&TokenKind::T_PERCENT) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_caret()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                operator,
-                right: Box::new(right),
-            };
-        }
-        Ok(expr)
+    // expression parsing 
+    pub fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        self.parse_expr_bp(0)
     }
 
-    fn parse_caret(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.parse_unary()?;
+    // Pratt loop: parses an expression whose binary operators all bind at least as tightly
+    // as `min_bp`; binding powers and associativity come from the table in precedence.rs
+    fn parse_expr_bp(&mut self, min_bp: u8) -> Result<Expr, ParseError> {
+        let start = self.peek_span();
+        let mut expr = self.parse_prefix()?;
 
-        while self.match_token(&TokenKind::T_CARET) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_unary()?;
-            expr = Expr::Binary {
+        while let Some(token) = self.peek() {
+            if let Some(bp) = precedence::postfix(&token.kind) {
+                if bp < min_bp {
+                    break;
+                }
+                let kind = if self.advance().unwrap().kind == TokenKind::T_DOT {
+                    let field = self.expect_identifier()?;
+                    ExprKind::Field { object: Box::new(expr), field }
+                } else {
+                    let index = self.parse_expression()?;
+                    self.consume(&TokenKind::T_BRACKETR, "']' after index")?;
+                    ExprKind::Index { array: Box::new(expr), index: Box::new(index) }
+                };
+                let span = self.span_from(start);
+                expr = Expr::new(kind, span);
+                continue;
+            }
+            let Some((bp, assoc)) = precedence::infix(&token.kind) else {
+                break;
+            };
+            if bp < min_bp {
+                break;
+            }
+            let operator = token.kind.clone();
+            let op_index = self.current;
+            self.advance();
+            // a left-associative operator must not take another of its level as right operand
+            let right_bp = match assoc {
+                Assoc::Left => bp + 1,
+                Assoc::Right => bp,
+            };
+            let right = self.parse_expr_bp(right_bp)?;
+            if operator == TokenKind::T_ASSIGNOP && !matches!(expr.kind, ExprKind::Identifier(_) | ExprKind::Index { .. } | ExprKind::Field { .. }) {
+                return Err(Self::error_at(self.tokens.get(op_index), ParseErrorKind::Expected("variable name".to_string())));
+            }
+            let span = expr.span.to(right.span);
+            expr = Expr::new(ExprKind::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
-            };
+            }, span);
         }
         Ok(expr)
     }
 
-    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
-        if self.match_token(&TokenKind::T_MINUS) || self.match_token(&TokenKind::T_NOT) {
-            let operator = self.previous().unwrap().kind.clone();
-            let right = self.parse_unary()?;
-            return Ok(Expr::Unary {
+    fn parse_prefix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.peek_span();
+        if let Some(bp) = self.peek().and_then(|t| precedence::prefix(&t.kind)) {
+            let operator = self.advance().unwrap().kind.clone();
+            let right = self.parse_expr_bp(bp)?;
+            return Ok(Expr::new(ExprKind::Unary {
                 operator,
                 expr: Box::new(right),
-            });
+            }, self.span_from(start)));
         }
         self.parse_primary()
     }
@@ -232,34 +275,45 @@ impl<'a> Parser<'a> {
         }
 
         if let Some(token) = self.peek() {
+            let span = token.span;
             match &token.kind {
                 TokenKind::T_BOOLLIT(b) => {
                     let b = *b;
                     self.advance();
-                    return Ok(Expr::Boolean(b));
+                    return Ok(Expr::new(ExprKind::Boolean(b), span));
                 }
                 TokenKind::T_INTLIT(n) => {
                     let n = *n;
                     self.advance();
-                    return Ok(Expr::Integer(n));
+                    return Ok(Expr::new(ExprKind::Integer(n), span));
                 }
                 TokenKind::T_FLOATLIT(n) => {
                     let n = *n;
                     self.advance();
-                    return Ok(Expr::Float(n));
+                    return Ok(Expr::new(ExprKind::Float(n), span));
                 }
                 TokenKind::T_STRINGLIT(s) => {
                     let s = s.clone();
                     self.advance();
 
                     // Skip over optional ending T_QUOTES tokens
-                    if let Some(token) = self.peek() {
-                        if token.kind == TokenKind::T_QUOTES {
-                            self.advance();
-                        }
+                    if let Some(token) = self.peek()
+                        && token.kind == TokenKind::T_QUOTES
+                    {
+                        self.advance();
                     }
 
-                    return Ok(Expr::StringLit(s));
+                    return Ok(Expr::new(ExprKind::StringLit(s), span));
+                }
+                TokenKind::T_CHARLIT(c) => {
+                    let c = *c;
+                    self.advance();
+                    return Ok(Expr::new(ExprKind::Char(c), span));
+                }
+                TokenKind::T_ERROR => {
+                    // the lexer reported this one; keep parsing around it
+                    self.advance();
+                    return Ok(Expr::new(ExprKind::Error, span));
                 }
                 TokenKind::T_IDENTIFIER(name) => {
                     let name = name.clone();
@@ -267,25 +321,82 @@ impl<'a> Parser<'a> {
 
                     // Check if it's a function call
                     if self.match_token(&TokenKind::T_PARENL) {
-                        return self.parse_call_expr(name);
+                        return self.parse_call_expr(name, span);
                     }
-                    return Ok(Expr::Identifier(name));
+                    if self.is_struct_literal_start() {
+                        return self.parse_struct_literal(name, span);
+                    }
+                    return Ok(Expr::new(ExprKind::Identifier(name), span));
                 }
                 _ => {}
             }
         }
 
+        let start = self.peek_span();
+
+        // conversion such as `int('a')` or `string(c)`: a type name used like a function
+        if self.is_type_token(self.peek()) {
+            let target = self.advance().unwrap().kind.clone();
+            self.consume(&TokenKind::T_PARENL, "'(' after type name in conversion")?;
+            let expr = self.parse_expression()?;
+            self.consume(&TokenKind::T_PARENR, "')' after conversion")?;
+            return Ok(Expr::new(ExprKind::Cast { target, expr: Box::new(expr) }, self.span_from(start)));
+        }
+
+        // array literal
+        if self.match_token(&TokenKind::T_BRACKETL) {
+            let mut elements = Vec::new();
+            if !self.check(&TokenKind::T_BRACKETR) {
+                loop {
+                    elements.push(self.parse_expression()?);
+                    if !self.match_token(&TokenKind::T_COMMA) {
+                        break;
+                    }
+                }
+            }
+            self.consume(&TokenKind::T_BRACKETR, "']' after array elements")?;
+            return Ok(Expr::new(ExprKind::Array(elements), self.span_from(start)));
+        }
+
         if self.match_token(&TokenKind::T_PARENL) {
             let expr = self.parse_expression()?;
             self.consume(&TokenKind::T_PARENR, "')'")?;
-            return Ok(Expr::Grouping(Box::new(expr)));
+            return Ok(Expr::new(ExprKind::Grouping(Box::new(expr)), self.span_from(start)));
+        }
+
+        Err(self.error_at_current(ParseErrorKind::ExpectedExpr))
+    }
+
+    // `{ }` or `{ name:` after an identifier; conditions are always parenthesised, so a
+    // brace right after a name cannot be the start of a block
+    fn is_struct_literal_start(&self) -> bool {
+        let kind_at = |offset: usize| self.tokens.get(self.current + offset).map(|t| &t.kind);
+        matches!(kind_at(0), Some(TokenKind::T_BRACEL))
+            && matches!(
+                (kind_at(1), kind_at(2)),
+                (Some(TokenKind::T_BRACER), _) | (Some(TokenKind::T_IDENTIFIER(_)), Some(TokenKind::T_COLON))
+            )
+    }
+
+    fn parse_struct_literal(&mut self, name: String, name_span: Span) -> Result<Expr, ParseError> {
+        self.consume(&TokenKind::T_BRACEL, "'{' after struct name")?;
+        let mut fields = Vec::new();
+        while !self.check(&TokenKind::T_BRACER) {
+            let field_start = self.peek_span();
+            let field = self.expect_identifier()?;
+            self.consume(&TokenKind::T_COLON, "':' after field name")?;
+            let value = self.parse_expression()?;
+            fields.push(FieldInit { name: field, value, span: self.span_from(field_start) });
+            // a trailing comma is allowed
+            if !self.match_token(&TokenKind::T_COMMA) {
+                break;
+            }
         }
-
-        let (line, col) = self.peek().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-        Err(ParseError { kind: ParseErrorKind::ExpectedExpr, line, col })
+        self.consume(&TokenKind::T_BRACER, "'}' after struct fields")?;
+        Ok(Expr::new(ExprKind::StructLit { name, fields }, self.span_from(name_span)))
     }
 
-    fn parse_call_expr(&mut self, callee: String) -> Result<Expr, ParseError> {
+    fn parse_call_expr(&mut self, callee: String, callee_span: Span) -> Result<Expr, ParseError> {
         let mut args = Vec::new();
         
         if !self.check(&TokenKind::T_PARENR) {
@@ -298,21 +409,17 @@ impl<'a> Parser<'a> {
         }
         
         self.consume(&TokenKind::T_PARENR, "')' after arguments")?;
-        Ok(Expr::Call {
-            callee: Box::new(Expr::Identifier(callee)),
+        Ok(Expr::new(ExprKind::Call {
+            callee: Box::new(Expr::new(ExprKind::Identifier(callee), callee_span)),
             args,
-        })
+        }, self.span_from(callee_span)))
     }
 
     // statement parsing
     fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
         // If next token is a closing brace, stop parsing — it's the end of a block
         if self.check(&TokenKind::T_BRACER) {
-            return Err(ParseError {
-                kind: ParseErrorKind::UnexpectedStmt,
-                line: self.peek().unwrap().line,
-                col: self.peek().unwrap().col,
-            });
+            return Err(self.error_at_current(ParseErrorKind::UnexpectedStmt));
         }
         if self.match_token(&TokenKind::T_RETURN) {
             return self.parse_return_statement();
@@ -334,7 +441,7 @@ impl<'a> Parser<'a> {
         }
         
         // Variable declaration or expression statement
-        if self.is_type_token(self.peek()) {
+        if self.is_declaration_start() {
             self.parse_declaration_statement()
         } else {
             self.parse_expression_statement()
@@ -342,25 +449,26 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_break_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span(); // 'break' already consumed
         self.consume(&TokenKind::T_SEMICOLON, "';' after break")?;
-        Ok(Stmt::Break)
+        Ok(Stmt::new(StmtKind::Break, self.span_from(start)))
     }
     
 
     fn parse_return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span(); // 'return' already consumed
         let value = if !self.check(&TokenKind::T_SEMICOLON) {
             Some(self.parse_expression()?)
         } else {
             None
         };
         self.consume(&TokenKind::T_SEMICOLON, "';' after return value")?;
-        Ok(Stmt::Return(value))
+        Ok(Stmt::new(StmtKind::Return(value), self.span_from(start)))
     }
 
     fn parse_if_statement(&mut self) -> Result<Stmt, ParseError> {
-        self.consume(&TokenKind::T_PARENL, "'(' after 'if'")?;
-        let condition = self.parse_expression()?;
-        self.consume(&TokenKind::T_PARENR, "')' after condition")?;
+        let start = self.previous_span(); // 'if' already consumed
+        let condition = self.parse_condition("if")?;
 
         let then_branch = Box::new(self.parse_statement()?);
 
@@ -370,34 +478,34 @@ impl<'a> Parser<'a> {
             None
         };
 
-        Ok(Stmt::If {
+        Ok(Stmt::new(StmtKind::If {
             condition,
             then_branch,
             else_branch,
-        })
+        }, self.span_from(start)))
     }
     
 
     fn parse_while_statement(&mut self) -> Result<Stmt, ParseError> {
-        self.consume(&TokenKind::T_PARENL, "'(' after 'while'")?;
-        let condition = self.parse_expression()?;
-        self.consume(&TokenKind::T_PARENR, "')' after condition")?;
+        let start = self.previous_span(); // 'while' already consumed
+        let condition = self.parse_condition("while")?;
         let body = if self.match_token(&TokenKind::T_BRACEL) {
             Box::new(self.parse_block_statement()?)
         } else {
             Box::new(self.parse_statement()?)
         };
 
-        Ok(Stmt::While { condition, body })
+        Ok(Stmt::new(StmtKind::While { condition, body }, self.span_from(start)))
     }
 
     fn parse_for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span(); // 'for' already consumed
         self.consume(&TokenKind::T_PARENL, "'(' after 'for'")?;
         
         // Initializer
         let init = if self.match_token(&TokenKind::T_SEMICOLON) {
             None
-        } else if self.is_type_token(self.peek()) {
+        } else if self.is_declaration_start() {
             Some(Box::new(self.parse_declaration_statement()?))
         } else {
             Some(Box::new(self.parse_expression_statement()?))
@@ -421,69 +529,91 @@ impl<'a> Parser<'a> {
         
         let body = Box::new(self.parse_statement()?);
         
-        Ok(Stmt::For {
+        Ok(Stmt::new(StmtKind::For {
             init,
             condition,
             increment,
             body,
-        })
+        }, self.span_from(start)))
     }
 
     fn parse_block_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span(); // '{' already consumed
         let mut statements = Vec::new();
 
         // Assume '{' already consumed by caller
 
         while !self.check(&TokenKind::T_BRACER) && !self.is_at_end() {
-            statements.push(self.parse_statement()?);
+            let stmt_start = self.current;
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => statements.push(self.recover(e, stmt_start)),
+            }
         }
 
         self.consume(&TokenKind::T_BRACER, "'}' after block")?;
-        Ok(Stmt::Block(statements))
+        Ok(Stmt::new(StmtKind::Block(statements), self.span_from(start)))
     }
     
 
     fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.peek_span();
         let expr = self.parse_expression()?;
         self.consume(&TokenKind::T_SEMICOLON, "';' after expression")?;
-        Ok(Stmt::Expr(expr))
+        Ok(Stmt::new(StmtKind::Expr(expr), self.span_from(start)))
     }
     
     fn parse_declaration_statement(&mut self) -> Result<Stmt, ParseError> {
-        let type_annot = self.advance().map(|t| t.kind.clone());
-        let name = if let Some(token) = self.advance() {
-            if let TokenKind::T_IDENTIFIER(n) = &token.kind {
-                n.clone()
-            } else {
-                let (line, col) = (token.line, token.col);
-                return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-            }
-        } else {
-            let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-            return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-        };
+        let start = self.peek_span();
+        let annot = self.parse_type()?;
+        let name = self.expect_identifier()?;
         
         let value = if self.match_token(&TokenKind::T_ASSIGNOP) {
-            self.parse_expression()?
+            Some(self.parse_initializer())
         } else {
-            match &type_annot {
-                Some(TokenKind::T_INT) => Expr::Integer(0),
-                Some(TokenKind::T_FLOAT) => Expr::Float(0.0),
-                Some(TokenKind::T_BOOL) => Expr::Boolean(false),
-                Some(TokenKind::T_STRING) => Expr::StringLit("".to_string()),
-                _ => {
-                    let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-                    return Err(ParseError { kind: ParseErrorKind::ExpectedTypeToken, line, col });
-                }
-            }
+            None
         };
         
         self.consume(&TokenKind::T_SEMICOLON, "';' after variable declaration")?;
-        Ok(Stmt::Let {
+        Ok(Stmt::new(StmtKind::Let {
             name,
-            type_annot,
+            type_annot: Some(annot),
             value,
-        })
+        }, self.span_from(start)))
+    }
+
+    // a type keyword or struct name followed by any number of array lengths;
+    // `int[2][3]` is two int[3]s
+    fn parse_type(&mut self) -> Result<TypeAnnot, ParseError> {
+        let base = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::T_IDENTIFIER(name)) => TypeAnnot::Struct(name.clone()),
+            _ if self.is_type_token(self.peek()) => TypeAnnot::Named(self.peek().unwrap().kind.clone()),
+            _ => return Err(self.error_at_current(ParseErrorKind::ExpectedTypeToken)),
+        };
+        self.advance();
+        let mut lengths = Vec::new();
+        while self.match_token(&TokenKind::T_BRACKETL) {
+            match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::T_INTLIT(n)) if *n >= 0 => lengths.push(*n as usize),
+                _ => return Err(self.error_at_current(ParseErrorKind::Expected("array length".to_string()))),
+            }
+            self.advance();
+            self.consume(&TokenKind::T_BRACKETR, "']' after array length")?;
+        }
+        Ok(lengths.into_iter().rev().fold(base, |elem, len| TypeAnnot::Array(Box::new(elem), len)))
+    }
+
+    // consumes an identifier token and returns its name
+    fn expect_identifier(&mut self) -> Result<String, ParseError> {
+        if let Some(token) = self.advance() {
+            if let TokenKind::T_IDENTIFIER(n) = &token.kind {
+                Ok(n.clone())
+            } else {
+                Err(Self::error_at(Some(token), ParseErrorKind::ExpectedIdentifier))
+            }
+        } else {
+            Err(self.error_at_previous(ParseErrorKind::ExpectedIdentifier))
+        }
     }
 
     // declaration parsing 
@@ -493,80 +623,45 @@ impl<'a> Parser<'a> {
             return self.parse_function_declaration();
         }
     
+        if self.match_token(&TokenKind::T_STRUCT) {
+            return self.parse_struct_declaration();
+        }
+
         //  Global variable declaration
-        if self.is_type_token(self.peek()) {
+        if self.is_declaration_start() {
             return self.parse_global_var_declaration();
         }
     
         // Top-level statements are invalid except top-level expressions
         if self.check(&TokenKind::T_EOF) {
-            return Err(ParseError {
-                kind: ParseErrorKind::UnexpectedEOF,
-                line: self.peek().unwrap().line,
-                col: self.peek().unwrap().col,
-            });
+            return Err(self.error_at_current(ParseErrorKind::UnexpectedEOF));
         }
     
-        // Attempt to parse a statement
+        // Allow statements at top level; they run in order with the global declarations
         let stmt = self.parse_statement()?;
- 
-        match stmt {
-            Stmt::Expr(expr) => {
-                // Optional: allow top-level expressions as global initializers
-                Ok(Decl::GlobalVar {
-                    name: "".to_string(),  // placeholder if needed
-                    type_annot: None,
-                    value: Some(expr),
-                })
-            }
-            _ => {
-                // Allow other statements at top level
-                Ok(Decl::Stmt(stmt))
-            }
-        }
+        let span = stmt.span;
+        Ok(Decl::new(DeclKind::Stmt(stmt), span))
     }
     
     
 
     fn parse_function_declaration(&mut self) -> Result<Decl, ParseError> {
-        let name = if let Some(token) = self.advance() {
-            if let TokenKind::T_IDENTIFIER(n) = &token.kind {
-                n.clone()
-            } else {
-                let (line, col) = (token.line, token.col);
-                return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-            }
-        } else {
-            let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-            return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-        };
+        let start = self.previous_span(); // 'fn' already consumed
+        let name = self.expect_identifier()?;
         
         self.consume(&TokenKind::T_PARENL, "'(' after function name")?;
         
         let mut params = Vec::new();
         if !self.check(&TokenKind::T_PARENR) {
             loop {
-                let param_type = if let Some(token) = self.advance() {
-                    token.kind.clone()
-                } else {
-                    let (line, col) = self.peek().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-                    return Err(ParseError { kind: ParseErrorKind::ExpectedTypeToken, line, col });
-                };
-                let param_name = if let Some(token) = self.advance() {
-                    if let TokenKind::T_IDENTIFIER(n) = &token.kind {
-                        n.clone()
-                    } else {
-                        let (line, col) = (token.line, token.col);
-                        return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-                    }
-                } else {
-                    let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-                    return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-                };
+                let param_start = self.peek_span();
+                let param_type = self.parse_type()?;
+                let param_name = self.expect_identifier()?;
                 
                 params.push(Param {
                     name: param_name,
                     param_type,
+                    span: self.span_from(param_start),
                 });
                 
                 if !self.match_token(&TokenKind::T_COMMA) {
@@ -579,97 +674,137 @@ impl<'a> Parser<'a> {
 
             // Return type
             let return_type = if self.match_token(&TokenKind::T_COLON) {
-                if let Some(token) = self.advance() {
-                    Some(token.kind.clone())
-                } else {
-                    let (line, col) = self.peek().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-                    return Err(ParseError { kind: ParseErrorKind::ExpectedTypeToken, line, col });
-                }
+                Some(self.parse_type()?)
             } else {
                 None
             };
+
+            if self.match_token(&TokenKind::T_SEMICOLON) {
+                return Ok(Decl::new(DeclKind::Prototype {
+                    name,
+                    params,
+                    return_type,
+                }, self.span_from(start)));
+            }
             
-            self.consume(&TokenKind::T_BRACEL, "'{' after function signature")?;
+            self.consume(&TokenKind::T_BRACEL, "'{' or ';' after function signature")?;
             let body = Box::new(self.parse_block_statement()?);
             
-            Ok(Decl::Function {
+            Ok(Decl::new(DeclKind::Function {
                 name,
                 params,
                 return_type,
                 body,
-            })
+            }, self.span_from(start)))
         }
 
-    fn parse_global_var_declaration(&mut self) -> Result<Decl, ParseError> {
-        if let Some(token) = self.peek() {
-            println!("parse_global_var_declaration at line {}, token: {:?}", token.line, token.kind);
-        } else {
-            println!("parse_global_var_declaration: No token peeked");
-        }
-
-        let type_annot = self.advance().map(|t| t.kind.clone());
+    fn parse_struct_declaration(&mut self) -> Result<Decl, ParseError> {
+        let start = self.previous_span(); // 'struct' already consumed
+        let name = self.expect_identifier()?;
+        self.consume(&TokenKind::T_BRACEL, "'{' after struct name")?;
 
-        if let Some(token) = self.peek() {
-            println!("After consuming type token, next token: {:?}", token.kind);
-        } else {
-            println!("After consuming type token, no next token");
+        let mut fields = Vec::new();
+        while !self.check(&TokenKind::T_BRACER) && !self.is_at_end() {
+            let field_start = self.peek_span();
+            let field_type = self.parse_type()?;
+            let field_name = self.expect_identifier()?;
+            self.consume(&TokenKind::T_SEMICOLON, "';' after field")?;
+            fields.push(Field {
+                name: field_name,
+                field_type,
+                span: self.span_from(field_start),
+            });
         }
 
-        let name = if let Some(token) = self.advance() {
-            if let TokenKind::T_IDENTIFIER(n) = &token.kind {
-                n.clone()
-            } else {
-                let (line, col) = (token.line, token.col);
-                return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-            }
-        } else {
-            let (line, col) = self.previous().map(|t| (t.line, t.col)).unwrap_or((0, 0));
-            return Err(ParseError { kind: ParseErrorKind::ExpectedIdentifier, line, col });
-        };
+        self.consume(&TokenKind::T_BRACER, "'}' after struct fields")?;
+        Ok(Decl::new(DeclKind::Struct { name, fields }, self.span_from(start)))
+    }
 
-        if let Some(token) = self.peek() {
-            println!("After consuming identifier, next token: {:?}", token.kind);
-        } else {
-            println!("After consuming identifier, no next token");
-        }
-        
+    fn parse_global_var_declaration(&mut self) -> Result<Decl, ParseError> {
+        let start = self.peek_span();
+        let type_annot = Some(self.parse_type()?);
+        let name = self.expect_identifier()?;
         let value = if self.match_token(&TokenKind::T_ASSIGNOP) {
-            println!("Assignment detected");
-            Some(self.parse_expression()?)
+            Some(self.parse_initializer())
         } else {
             None
         };
-
-        if let Some(token) = self.peek() {
-            println!("Before consuming semicolon, next token: {:?}", token.kind);
-        } else {
-            println!("Before consuming semicolon, no next token");
-        }
-        
         self.consume(&TokenKind::T_SEMICOLON, "';' after declaration")?;
         
-        Ok(Decl::GlobalVar {
+        Ok(Decl::new(DeclKind::GlobalVar {
             name,
             type_annot,
             value,
-        })
+        }, self.span_from(start)))
     }
 
     fn is_type_token(&self, token: Option<&Token>) -> bool {
         matches!(token.as_ref().map(|t| &t.kind), Some(
             TokenKind::T_INT | TokenKind::T_FLOAT | 
-            TokenKind::T_BOOL | TokenKind::T_STRING
+            TokenKind::T_BOOL | TokenKind::T_STRING | TokenKind::T_CHAR
         ))
     }
 
+    // a type name starts a declaration unless it is a conversion like `int(c)`; a struct
+    // name only does when a variable name follows it, possibly after array lengths
+    // (`Point[2] ps;`, unlike the assignment `ps[2] = p;`)
+    fn is_declaration_start(&self) -> bool {
+        let kind_at = |i: usize| self.tokens.get(i).map(|t| &t.kind);
+        if self.is_type_token(self.peek()) {
+            return !matches!(kind_at(self.current + 1), Some(TokenKind::T_PARENL));
+        }
+        if !matches!(kind_at(self.current), Some(TokenKind::T_IDENTIFIER(_))) {
+            return false;
+        }
+        let mut i = self.current + 1;
+        while matches!(
+            (kind_at(i), kind_at(i + 1), kind_at(i + 2)),
+            (Some(TokenKind::T_BRACKETL), Some(TokenKind::T_INTLIT(_)), Some(TokenKind::T_BRACKETR))
+        ) {
+            i += 3;
+        }
+        matches!(kind_at(i), Some(TokenKind::T_IDENTIFIER(_)))
+    }
+
     // TOP-LEVEL PARSING 
-    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+    // always returns a Program; declarations that failed to parse show up as Stmt::Error
+    // placeholders and their errors are returned alongside
+    pub fn parse_program(&mut self) -> (Program, Vec<ParseError>) {
         let mut declarations = Vec::new();
         
         while !self.is_at_end() {
-            declarations.push(self.parse_declaration()?);
+            let decl_start = self.current;
+            match self.parse_declaration() {
+                Ok(decl) => declarations.push(decl),
+                Err(e) => {
+                    let stmt = self.recover(e, decl_start);
+                    let span = stmt.span;
+                    declarations.push(Decl::new(DeclKind::Stmt(stmt), span));
+                }
+            }
         }
         
-        Ok(Program { declarations })
+        (Program { declarations }, std::mem::take(&mut self.errors))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use crate::parser::ast::{DeclKind, ExprKind, StmtKind};
+
+    #[test]
+    fn broken_initializer_keeps_the_declaration() {
+        let (program, diagnostics) = crate::parse("int b = ;\nfn f() { int d = (1 + ; d; }\n");
+        assert_eq!(diagnostics.len(), 2);
+        let DeclKind::GlobalVar { name, value: Some(value), .. } = &program.declarations[0].kind else {
+            panic!("global `b` was dropped");
+        };
+        assert_eq!(name, "b");
+        assert!(matches!(value.kind, ExprKind::Error));
+        let DeclKind::Function { body, .. } = &program.declarations[1].kind else {
+            panic!("function `f` was dropped");
+        };
+        let StmtKind::Block(stmts) = &body.kind else { unreachable!() };
+        assert!(matches!(&stmts[0].kind, StmtKind::Let { value: Some(value), .. } if matches!(value.kind, ExprKind::Error)));
+        assert!(matches!(stmts[1].kind, StmtKind::Expr(_)));
     }
-}
\ No newline at end of file
+}
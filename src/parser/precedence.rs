@@ -0,0 +1,160 @@
+// The single operator table used by the Pratt expression parser in parser.rs. The
+// expression part of grammar/grammar.md is generated from it (`--gen-grammar`), so the
+// parser and its documentation cannot drift apart.
+use crate::token::TokenKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    Infix(Assoc),
+    Prefix,
+    Postfix, // a suffix such as `a[i]` or `p.x`; see `suffix` for what follows the operator
+}
+
+pub struct Level {
+    pub name: &'static str, // nonterminal used for this level in grammar.md
+    pub fixity: Fixity,
+    pub ops: &'static [(TokenKind, &'static str)],
+}
+
+// Loosest binding first; an operator's binding power is its level's index + 1.
+// Follows C, except that `^` is exponentiation: right-associative and tighter than the
+// prefix operators, so `-x ^ 2` is `-(x ^ 2)`.
+pub const LEVELS: &[Level] = &[
+    Level { name: "assignment", fixity: Fixity::Infix(Assoc::Right), ops: &[(TokenKind::T_ASSIGNOP, "=")] },
+    Level { name: "logical-or", fixity: Fixity::Infix(Assoc::Left), ops: &[(TokenKind::T_OROR, "||")] },
+    Level { name: "logical-and", fixity: Fixity::Infix(Assoc::Left), ops: &[(TokenKind::T_ANDAND, "&&")] },
+    Level { name: "bitwise-or", fixity: Fixity::Infix(Assoc::Left), ops: &[(TokenKind::T_PIPE, "|")] },
+    Level { name: "bitwise-and", fixity: Fixity::Infix(Assoc::Left), ops: &[(TokenKind::T_AMP, "&")] },
+    Level {
+        name: "equality",
+        fixity: Fixity::Infix(Assoc::Left),
+        ops: &[(TokenKind::T_EQUALSOP, "=="), (TokenKind::T_NEQ, "!=")],
+    },
+    Level {
+        name: "comparison",
+        fixity: Fixity::Infix(Assoc::Left),
+        ops: &[(TokenKind::T_LT, "<"), (TokenKind::T_GT, ">"), (TokenKind::T_LTE, "<="), (TokenKind::T_GTE, ">=")],
+    },
+    Level {
+        name: "shift",
+        fixity: Fixity::Infix(Assoc::Left),
+        ops: &[(TokenKind::T_LSHIFT, "<<"), (TokenKind::T_RSHIFT, ">>")],
+    },
+    Level {
+        name: "term",
+        fixity: Fixity::Infix(Assoc::Left),
+        ops: &[(TokenKind::T_PLUS, "+"), (TokenKind::T_MINUS, "-")],
+    },
+    Level {
+        name: "factor",
+        fixity: Fixity::Infix(Assoc::Left),
+        ops: &[(TokenKind::T_STAR, "*"), (TokenKind::T_SLASH, "/"), (TokenKind::T_PERCENT, "%")],
+    },
+    Level {
+        name: "unary",
+        fixity: Fixity::Prefix,
+        ops: &[(TokenKind::T_MINUS, "-"), (TokenKind::T_NOT, "!"), (TokenKind::T_TILDE, "~")],
+    },
+    Level { name: "power", fixity: Fixity::Infix(Assoc::Right), ops: &[(TokenKind::T_CARET, "^")] },
+    Level {
+        name: "postfix",
+        fixity: Fixity::Postfix,
+        ops: &[(TokenKind::T_BRACKETL, "[ ]"), (TokenKind::T_DOT, ".")],
+    },
+];
+
+// binding power and fixity of the level that has `kind` among its operators and whose
+// fixity passes `want` (`-` is both a prefix and an infix operator)
+fn find(kind: &TokenKind, want: fn(Fixity) -> bool) -> Option<(u8, Fixity)> {
+    LEVELS.iter().enumerate().find_map(|(i, level)| {
+        let fits = want(level.fixity) && level.ops.iter().any(|(k, _)| k == kind);
+        fits.then_some((i as u8 + 1, level.fixity))
+    })
+}
+
+// binding power and associativity of `kind` used as a binary operator
+pub fn infix(kind: &TokenKind) -> Option<(u8, Assoc)> {
+    match find(kind, |f| matches!(f, Fixity::Infix(_))) {
+        Some((bp, Fixity::Infix(assoc))) => Some((bp, assoc)),
+        _ => None,
+    }
+}
+
+// binding power of `kind` used as a prefix operator; also the power its operand is parsed at
+pub fn prefix(kind: &TokenKind) -> Option<u8> {
+    find(kind, |f| f == Fixity::Prefix).map(|(bp, _)| bp)
+}
+
+// binding power of `kind` opening a postfix suffix
+pub fn postfix(kind: &TokenKind) -> Option<u8> {
+    find(kind, |f| f == Fixity::Postfix).map(|(bp, _)| bp)
+}
+
+// source text of a prefix or binary operator, for printing expressions back out
+pub fn spelling(kind: &TokenKind) -> Option<&'static str> {
+    LEVELS
+        .iter()
+        .filter(|level| level.fixity != Fixity::Postfix)
+        .flat_map(|level| level.ops.iter())
+        .find(|(k, _)| k == kind)
+        .map(|(_, s)| *s)
+}
+
+// grammar for a postfix operator together with what it applies to the operand
+fn suffix(kind: &TokenKind) -> &'static str {
+    match kind {
+        TokenKind::T_BRACKETL => "\"[\" <expression> \"]\"",
+        TokenKind::T_DOT => "\".\" IDENTIFIER",
+        _ => unreachable!("{} is not a postfix operator", kind),
+    }
+}
+
+fn alternatives(level: &Level) -> String {
+    let ops: Vec<String> = level.ops.iter().map(|(_, s)| format!("\"{}\"", s)).collect();
+    if ops.len() == 1 { ops[0].clone() } else { format!("( {} )", ops.join(" | ")) }
+}
+
+// Markdown for the expression section of grammar.md: one production per level plus a
+// precedence table
+pub fn render_markdown() -> String {
+    let mut out = String::new();
+    out.push_str("### Expression\n```\n<expression> ::= <assignment>\n```\n");
+    for (i, level) in LEVELS.iter().enumerate() {
+        let next = LEVELS.get(i + 1).map(|l| l.name).unwrap_or("primary");
+        let ops = alternatives(level);
+        let production = match level.fixity {
+            Fixity::Infix(Assoc::Left) => format!("<{n}> ::= <{next}> ( {ops} <{next}> )*", n = level.name),
+            Fixity::Infix(Assoc::Right) => format!("<{n}> ::= <{next}> [ {ops} <{n}> ]", n = level.name),
+            Fixity::Prefix => format!("<{n}> ::= {ops} <{n}>\n{pad}| <{next}>", n = level.name, pad = " ".repeat(level.name.len() + 5)),
+            Fixity::Postfix => {
+                let suffixes: Vec<&str> = level.ops.iter().map(|(k, _)| suffix(k)).collect();
+                format!("<{n}> ::= <{next}> ( {} )*", suffixes.join(" | "), n = level.name)
+            }
+        };
+        let mut title = level.name.replace('-', " ");
+        title[..1].make_ascii_uppercase();
+        out.push_str(&format!("\n### {}\n```\n{}\n```\n", title, production));
+    }
+
+    out.push_str("\n### Operator Precedence\n\n");
+    out.push_str("From loosest to tightest binding:\n\n");
+    out.push_str("| Level | Operators | Kind |\n|---|---|---|\n");
+    for (i, level) in LEVELS.iter().enumerate() {
+        let ops: Vec<String> = level.ops.iter().map(|(_, s)| format!("`{}`", s.replace('|', "\\|"))).collect();
+        let kind = match level.fixity {
+            Fixity::Infix(Assoc::Left) => "binary, left-associative",
+            Fixity::Infix(Assoc::Right) => "binary, right-associative",
+            Fixity::Prefix => "prefix",
+            Fixity::Postfix => "postfix (indexing, field access)",
+        };
+        out.push_str(&format!("| {} | {} | {} |\n", i + 1, ops.join(" "), kind));
+    }
+    out.push_str("\nA prefix operator may also start the operand of a tighter binary operator, e.g. `2 ^ -1`.\n");
+    out
+}
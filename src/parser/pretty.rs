@@ -0,0 +1,388 @@
+// Source formatter behind the `fmt` command: prints a Program back out as source with
+// one statement per line, two-space indentation and single spaces around binary
+// operators. Parentheses are printed exactly where the source had them (they are
+// Grouping nodes), so formatting never changes what an expression means.
+//
+// Comments are not in the AST; they come from the trivia the lexer kept on the tokens
+// (HandLexer::tokenize_with_trivia). A comment on a line of its own is printed before the
+// declaration or statement that followed it, one after code stays at the end of that
+// line. At most one blank line from the source is kept between two items.
+use crate::parser::ast::{Decl, DeclKind, Expr, ExprKind, Param, Program, Stmt, StmtKind, TypeAnnot};
+use crate::parser::precedence;
+use crate::span::Span;
+use crate::token::{Token, TokenKind, TriviaKind};
+
+struct Comment {
+    span: Span,
+    own_line: bool, // nothing but whitespace before it on its line
+}
+
+pub fn format_program(program: &Program, tokens: &[Token], src: &str) -> String {
+    let comments = tokens
+        .iter()
+        .flat_map(|t| t.leading.iter().chain(t.trailing.iter()))
+        .filter(|t| matches!(t.kind, TriviaKind::LineComment | TriviaKind::BlockComment))
+        .map(|t| {
+            let line_start = src[..t.span.start].rfind('\n').map_or(0, |i| i + 1);
+            Comment { span: t.span, own_line: src[line_start..t.span.start].trim().is_empty() }
+        })
+        .collect();
+    let mut p = Printer { src, comments, next: 0, out: Vec::new(), indent: 0, last_end: None };
+    for decl in &program.declarations {
+        p.decl(decl);
+    }
+    p.comments_before(src.len());
+    let mut out = p.out.join("\n");
+    out.push('\n');
+    out
+}
+
+struct Printer<'a> {
+    src: &'a str,
+    comments: Vec<Comment>,
+    next: usize,        // first comment not printed yet
+    out: Vec<String>,   // finished lines
+    indent: usize,
+    last_end: Option<usize>, // end of the last item or comment; None at the start of a block
+}
+
+impl Printer<'_> {
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push(format!("{}{}", "  ".repeat(self.indent), text.as_ref()));
+    }
+
+    // one blank line if the source had at least one between the last item and `start`
+    fn gap(&mut self, start: usize) {
+        if let Some(end) = self.last_end
+            && self.src[end..start].matches('\n').count() >= 2
+        {
+            self.out.push(String::new());
+        }
+    }
+
+    // prints the comments that start before `offset`
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(c) = self.comments.get(self.next)
+            && c.span.start < offset
+        {
+            let (span, own_line) = (c.span, c.own_line);
+            let text = self.src[span.start..span.end].trim_end().to_string();
+            match self.out.last_mut() {
+                Some(last) if !own_line && !last.is_empty() => {
+                    last.push(' ');
+                    last.push_str(&text);
+                }
+                _ => {
+                    self.gap(span.start);
+                    self.line(text);
+                }
+            }
+            self.last_end = Some(span.end);
+            self.next += 1;
+        }
+    }
+
+    // comments and the blank line that come before an item starting at `span`
+    fn start_item(&mut self, span: Span) {
+        self.comments_before(span.start);
+        self.gap(span.start);
+    }
+
+    fn end_item(&mut self, span: Span) {
+        self.last_end = Some(span.end);
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        if let DeclKind::Stmt(stmt) = &decl.kind {
+            return self.stmt(stmt);
+        }
+        self.start_item(decl.span);
+        match &decl.kind {
+            DeclKind::Function { name, params, return_type, body } => {
+                let header = self.signature(name, params, return_type.as_ref());
+                self.clause(header, body);
+            }
+            DeclKind::Prototype { name, params, return_type } => {
+                let header = self.signature(name, params, return_type.as_ref());
+                self.line(format!("{};", header));
+            }
+            DeclKind::GlobalVar { name, type_annot, value } => {
+                self.line(format!("{};", let_text(name, type_annot.as_ref(), value.as_ref())));
+            }
+            DeclKind::Struct { name, fields } => {
+                self.line(format!("struct {} {{", name));
+                self.indent += 1;
+                self.last_end = None;
+                for field in fields {
+                    self.start_item(field.span);
+                    self.line(format!("{} {};", type_text(&field.field_type), field.name));
+                    self.end_item(field.span);
+                }
+                self.comments_before(decl.span.end.saturating_sub(1));
+                self.indent -= 1;
+                self.line("}");
+            }
+            DeclKind::Stmt(_) => unreachable!(),
+        }
+        self.end_item(decl.span);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.start_item(stmt.span);
+        match &stmt.kind {
+            StmtKind::Expr(expr) => self.line(format!("{};", expr_text(expr))),
+            StmtKind::Let { name, type_annot, value } => {
+                self.line(format!("{};", let_text(name, type_annot.as_ref(), value.as_ref())));
+            }
+            StmtKind::Block(_) => {
+                self.line("{");
+                self.block_body(stmt);
+                self.line("}");
+            }
+            StmtKind::Return(Some(expr)) => self.line(format!("return {};", expr_text(expr))),
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Break => self.line("break;"),
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.if_chain(String::new(), condition, then_branch, else_branch.as_deref());
+            }
+            StmtKind::While { condition, body } => {
+                self.clause(format!("while ({})", expr_text(condition)), body);
+            }
+            StmtKind::For { init, condition, increment, body } => {
+                // `for (int i = 0; i < n; i = i + 1)`, `for (;;)`
+                let mut header = match init.as_deref().map(|s| &s.kind) {
+                    Some(StmtKind::Let { name, type_annot, value }) => let_text(name, type_annot.as_ref(), value.as_ref()),
+                    Some(StmtKind::Expr(expr)) => expr_text(expr),
+                    _ => String::new(),
+                };
+                for part in [condition, increment] {
+                    header.push(';');
+                    if let Some(expr) = part {
+                        header.push(' ');
+                        header.push_str(&expr_text(expr));
+                    }
+                }
+                self.clause(format!("for ({})", header), body);
+            }
+            StmtKind::Function { name, params, return_type, body } => {
+                let header = self.signature(name, params, return_type.as_ref());
+                self.clause(header, body);
+            }
+            StmtKind::Error => unreachable!("fmt only runs on programs without parse errors"),
+        }
+        self.end_item(stmt.span);
+    }
+
+    // the statements of a block, one level deeper, and the comments before its `}`
+    fn block_body(&mut self, block: &Stmt) {
+        let StmtKind::Block(stmts) = &block.kind else {
+            unreachable!()
+        };
+        self.indent += 1;
+        self.last_end = None;
+        for s in stmts {
+            self.stmt(s);
+        }
+        self.comments_before(block.span.end.saturating_sub(1));
+        self.indent -= 1;
+    }
+
+    // `header {` ... `}` for a block body, otherwise the header with the statement indented
+    // on the next line
+    fn clause(&mut self, header: String, body: &Stmt) {
+        if let StmtKind::Block(_) = body.kind {
+            self.line(format!("{} {{", header));
+            self.block_body(body);
+            self.line("}");
+        } else {
+            self.line(header);
+            self.indent += 1;
+            self.last_end = None;
+            self.stmt(body);
+            self.indent -= 1;
+        }
+    }
+
+    // `fn name(int a, ...): type`. Block comments among the parameters stay next to the
+    // parameter they follow, or precede before the first one.
+    fn signature(&mut self, name: &str, params: &[Param], return_type: Option<&TypeAnnot>) -> String {
+        let mut list = Vec::new();
+        for (i, p) in params.iter().enumerate() {
+            let mut text = String::new();
+            if i == 0 {
+                for comment in self.inline_comments(p.span.start) {
+                    text.push_str(&comment);
+                    text.push(' ');
+                }
+            }
+            text.push_str(&format!("{} {}", type_text(&p.param_type), p.name));
+            let until = match params.get(i + 1) {
+                Some(next) => next.span.start,
+                None => self.closing_paren(p.span.end),
+            };
+            for comment in self.inline_comments(until) {
+                text.push(' ');
+                text.push_str(&comment);
+            }
+            list.push(text);
+        }
+        match return_type {
+            Some(t) => format!("fn {}({}): {}", name, list.join(", "), type_text(t)),
+            None => format!("fn {}({})", name, list.join(", ")),
+        }
+    }
+
+    // the comments that start before `offset`, to print in the middle of a line; stops at
+    // a line comment, which can only end one and is left to comments_before
+    fn inline_comments(&mut self, offset: usize) -> Vec<String> {
+        let mut texts = Vec::new();
+        while let Some(c) = self.comments.get(self.next)
+            && c.span.start < offset
+        {
+            let text = &self.src[c.span.start..c.span.end];
+            if text.starts_with("//") {
+                break;
+            }
+            texts.push(text.to_string());
+            self.last_end = Some(c.span.end);
+            self.next += 1;
+        }
+        texts
+    }
+
+    // offset of the `)` after `from`, not counting one inside a comment
+    fn closing_paren(&self, from: usize) -> usize {
+        let mut at = from;
+        for c in &self.comments[self.next..] {
+            if c.span.start < at {
+                continue;
+            }
+            if self.src[at..c.span.start].contains(')') {
+                break;
+            }
+            at = c.span.end;
+        }
+        at + self.src[at..].find(')').unwrap_or(0)
+    }
+
+    // `if`, with `else` and `else if` joined onto the `}` before them
+    fn if_chain(&mut self, prefix: String, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        self.clause(format!("{}if ({})", prefix, expr_text(condition)), then_branch);
+        let Some(else_branch) = else_branch else {
+            return;
+        };
+        let closing = format!("{}}}", "  ".repeat(self.indent));
+        let prefix = if self.out.last() == Some(&closing) {
+            self.out.pop();
+            "} else ".to_string()
+        } else {
+            "else ".to_string()
+        };
+        match &else_branch.kind {
+            StmtKind::If { condition, then_branch, else_branch } => {
+                self.if_chain(prefix, condition, then_branch, else_branch.as_deref());
+            }
+            _ => self.clause(prefix.trim_end().to_string(), else_branch),
+        }
+    }
+}
+
+// `int x = 1` without the `;`
+fn let_text(name: &str, type_annot: Option<&TypeAnnot>, value: Option<&Expr>) -> String {
+    let mut text = match type_annot {
+        Some(t) => format!("{} {}", type_text(t), name),
+        None => name.to_string(),
+    };
+    if let Some(value) = value {
+        text.push_str(&format!(" = {}", expr_text(value)));
+    }
+    text
+}
+
+pub fn type_text(annot: &TypeAnnot) -> String {
+    match annot {
+        TypeAnnot::Named(kind) => type_keyword(kind).to_string(),
+        // lengths are written outermost first: int[2][3] is two arrays of three ints
+        TypeAnnot::Array(..) => {
+            let mut lengths = String::new();
+            let mut elem = annot;
+            while let TypeAnnot::Array(inner, len) = elem {
+                lengths.push_str(&format!("[{}]", len));
+                elem = inner;
+            }
+            format!("{}{}", type_text(elem), lengths)
+        }
+        TypeAnnot::Struct(name) => name.clone(),
+    }
+}
+
+pub fn type_keyword(kind: &TokenKind) -> &'static str {
+    match kind {
+        TokenKind::T_INT => "int",
+        TokenKind::T_FLOAT => "float",
+        TokenKind::T_BOOL => "bool",
+        TokenKind::T_STRING => "string",
+        TokenKind::T_CHAR => "char",
+        other => unreachable!("{} is not a type keyword", other),
+    }
+}
+
+pub fn expr_text(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Identifier(name) => name.clone(),
+        ExprKind::Integer(n) => n.to_string(),
+        ExprKind::Float(x) => format!("{:?}", x), // keeps the ".0" of whole numbers
+        ExprKind::Boolean(b) => b.to_string(),
+        ExprKind::StringLit(s) => format!("\"{}\"", escape(s, '"')),
+        ExprKind::Char(c) => format!("'{}'", escape(&c.to_string(), '\'')),
+        ExprKind::Array(elements) => format!("[{}]", list(elements)),
+        ExprKind::Binary { left, operator, right } => {
+            format!("{} {} {}", expr_text(left), op(operator), expr_text(right))
+        }
+        ExprKind::Unary { operator, expr: inner } => {
+            let operand = expr_text(inner);
+            // `- -x`, not `--x`
+            let space = if operand.starts_with(['-', '!', '~']) { " " } else { "" };
+            format!("{}{}{}", op(operator), space, operand)
+        }
+        ExprKind::Call { callee, args } => format!("{}({})", expr_text(callee), list(args)),
+        ExprKind::Grouping(inner) => format!("({})", expr_text(inner)),
+        ExprKind::Index { array, index } => format!("{}[{}]", expr_text(array), expr_text(index)),
+        ExprKind::Field { object, field } => format!("{}.{}", expr_text(object), field),
+        ExprKind::StructLit { name, fields } if fields.is_empty() => format!("{} {{}}", name),
+        ExprKind::StructLit { name, fields } => {
+            let fields: Vec<String> = fields.iter().map(|f| format!("{}: {}", f.name, expr_text(&f.value))).collect();
+            format!("{} {{ {} }}", name, fields.join(", "))
+        }
+        ExprKind::Cast { target, expr: inner } => format!("{}({})", type_keyword(target), expr_text(inner)),
+        ExprKind::Error => unreachable!("fmt only runs on programs without parse errors"),
+    }
+}
+
+fn list(exprs: &[Expr]) -> String {
+    exprs.iter().map(expr_text).collect::<Vec<_>>().join(", ")
+}
+
+fn op(kind: &TokenKind) -> &'static str {
+    precedence::spelling(kind).unwrap_or_else(|| unreachable!("{} is not an operator", kind))
+}
+
+// literal text for `s` between `quote`s, using the escapes the lexer reads back
+fn escape(s: &str, quote: char) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 || c == '\x7f' => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
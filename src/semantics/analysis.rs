@@ -0,0 +1,830 @@
+// Scope and type checking over a whole Program. Declarations are registered first (see
+// collect_declarations), then every initializer, body and top-level statement is
+// checked in source order.
+use crate::parser::ast::{DeclKind, Expr, ExprKind, Program, Stmt, StmtKind, TypeAnnot};
+use crate::semantics::flow::{self, Assigned, Flow, FlowWarning};
+use crate::semantics::scope::scope::{ScopeError, ScopeErrorKind, ScopeStack, Symbol, SymbolKind, Type};
+use crate::semantics::typechecker::{TypeChkError, TypeChkErrorKind, TypeTable};
+use crate::span::Span;
+use crate::token::TokenKind;
+
+// What semantic analysis found out about a program
+pub struct Analysis {
+    pub scope_errors: Vec<ScopeError>,
+    pub typechk_errors: Vec<TypeChkError>,
+    pub warnings: Vec<FlowWarning>,
+    pub types: TypeTable,
+}
+
+// Perform combined scope and type checking, collect errors from both
+pub fn perform_semantic_analysis(program: &Program) -> Analysis {
+    let mut scope_stack = ScopeStack::new();
+    let mut scope_errors = Vec::new();
+    let mut typechk_errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut types = TypeTable::new();
+
+    scope_stack.enter_scope();
+
+    let signatures = collect_declarations(program, &mut scope_stack, &mut scope_errors);
+    // top-level statements run in order, so they get one flow through the whole file
+    let mut flow = Flow::new();
+
+    for (decl, signature) in program.declarations.iter().zip(signatures) {
+        match &decl.kind {
+            DeclKind::GlobalVar { name, type_annot, value } => {
+                let ty = if let Some(t) = type_annot { resolve_annot(t, &scope_stack, decl.span, &mut scope_errors) } else { Type::Void };
+                let initialized = value.is_some() || zero_filled(&ty);
+                if let Err(e) = scope_stack.insert_variable(name.clone(), ty.clone(), true, initialized, decl.span) {
+                    scope_errors.push(redefinition(e, name, decl.span, &scope_stack));
+                }
+                if let Some(val) = value {
+                    analyze_expr(val, &mut scope_stack, &mut scope_errors, &mut typechk_errors, &mut types);
+                    check_aggregate_init(&ty, val, &mut typechk_errors, &types);
+                }
+            }
+            DeclKind::Function { params, body, .. } => {
+                // bodies of functions whose signature was rejected are not checked
+                if let Some((param_types, ret_ty)) = signature {
+                    // a function may be called at any point of the top-level code, so it can
+                    // rely on every global being assigned by then
+                    let top_level = scope_stack.assigned();
+                    scope_stack.assume_all_assigned();
+                    scope_stack.enter_scope();
+                    for (p, ty) in params.iter().zip(param_types) {
+                        if let Err(e) = scope_stack.insert_parameter(p.name.clone(), ty, p.span) {
+                            scope_errors.push(redefinition(e, &p.name, p.span, &scope_stack));
+                        }
+                    }
+                    let mut body_flow = Flow::new();
+                    analyze_stmt(body, &mut scope_stack, &mut scope_errors, &mut typechk_errors, &mut types, &mut body_flow, Some(&ret_ty));
+                    // falling off the end of the body is a path without a return; a
+                    // statement that did not parse may have been one
+                    if body_flow.reachable && !body_flow.incomplete && !matches!(ret_ty, Type::Void | Type::Unknown) {
+                        typechk_errors.push(TypeChkError::new(TypeChkErrorKind::ReturnStmtNotFound, decl.span));
+                    }
+                    warnings.append(&mut body_flow.warnings);
+                    scope_stack.exit_scope();
+                    scope_stack.set_assigned(&top_level);
+                }
+            }
+            DeclKind::Prototype { .. } | DeclKind::Struct { .. } => {} // registered by collect_declarations
+            DeclKind::Stmt(stmt) => {
+                analyze_stmt(stmt, &mut scope_stack, &mut scope_errors, &mut typechk_errors, &mut types, &mut flow, None);
+            }
+        }
+    }
+
+    scope_stack.exit_scope();
+    warnings.append(&mut flow.warnings);
+
+    Analysis { scope_errors, typechk_errors, warnings, types }
+}
+
+// First pass: registers every struct, prototype and function signature in source order
+// before any body or initializer is checked, so a function can call one defined further
+// down (and two functions can call each other). Returns, for each declaration, the
+// parameter and return types of a function definition that was registered.
+fn collect_declarations(program: &Program, scope_stack: &mut ScopeStack, scope_errors: &mut Vec<ScopeError>) -> Vec<Option<(Vec<Type>, Type)>> {
+    // struct names come first, so any type in a declaration can name any struct
+    for decl in &program.declarations {
+        if let DeclKind::Struct { name, .. } = &decl.kind
+            && let Err(e) = scope_stack.insert_struct(name.clone(), Vec::new(), decl.span)
+        {
+            scope_errors.push(redefinition(e, name, decl.span, scope_stack));
+        }
+    }
+    let mut signatures = Vec::new();
+    for decl in &program.declarations {
+        let mut signature = None;
+        match &decl.kind {
+            DeclKind::Function { name, params, return_type, .. } | DeclKind::Prototype { name, params, return_type } => {
+                let param_types: Vec<Type> = params.iter().map(|p| resolve_annot(&p.param_type, scope_stack, p.span, scope_errors)).collect();
+                let ret_ty = if let Some(t) = return_type { resolve_annot(t, scope_stack, decl.span, scope_errors) } else { Type::Void };
+                let result = if let DeclKind::Function { .. } = decl.kind {
+                    scope_stack.insert_function_definition(name.clone(), param_types.clone(), ret_ty.clone(), decl.span)
+                } else {
+                    scope_stack.insert_function_prototype(name.clone(), param_types.clone(), ret_ty.clone(), decl.span)
+                };
+                match result {
+                    Ok(()) if matches!(decl.kind, DeclKind::Function { .. }) => signature = Some((param_types, ret_ty)),
+                    Ok(()) => {}
+                    Err(e) => scope_errors.push(redefinition(e, name, decl.span, scope_stack)),
+                }
+            }
+            DeclKind::Struct { name, fields } => {
+                let mut field_types: Vec<(String, Type)> = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    let ty = resolve_annot(&field.field_type, scope_stack, field.span, scope_errors);
+                    if field_types.iter().any(|(f, _)| *f == field.name) {
+                        let first = fields[..i].iter().find(|f| f.name == field.name).map(|f| f.span);
+                        scope_errors.push(ScopeError::new(ScopeErrorKind::DuplicateField, field.span).named(&field.name).with_previous(first));
+                    } else {
+                        field_types.push((field.name.clone(), ty));
+                    }
+                }
+                // a second struct of the same name was reported when the names were registered
+                if scope_stack.lookup_struct(name).is_ok_and(|sym| sym.span == decl.span) {
+                    scope_stack.set_struct_fields(name, field_types);
+                }
+            }
+            DeclKind::GlobalVar { .. } | DeclKind::Stmt(_) => {}
+        }
+        signatures.push(signature);
+    }
+
+    for decl in &program.declarations {
+        if let DeclKind::Struct { name, fields } = &decl.kind
+            && scope_stack.lookup_struct(name).is_ok_and(|sym| sym.span == decl.span)
+            && let Some(field) = fields.iter().find(|f| {
+                let ty = scope_stack.struct_field(name, &f.name).cloned().unwrap_or(Type::Unknown);
+                contains_struct(&ty, name, scope_stack, &mut Vec::new())
+            })
+        {
+            scope_errors.push(ScopeError::new(ScopeErrorKind::RecursiveStruct, field.span).named(name).with_previous(Some(decl.span)));
+        }
+    }
+    signatures
+}
+
+// Whether a value of type `ty` holds a `target` struct somewhere inside it; `seen` are the
+// structs already being looked through, so other cycles end the search
+fn contains_struct(ty: &Type, target: &str, scope_stack: &ScopeStack, seen: &mut Vec<String>) -> bool {
+    match ty {
+        Type::Custom(name) if name == target => true,
+        Type::Custom(name) if !seen.contains(name) => {
+            seen.push(name.clone());
+            match scope_stack.lookup_struct(name).map(|sym| &sym.kind) {
+                Ok(SymbolKind::Struct { fields }) => fields.iter().any(|(_, field)| contains_struct(field, target, scope_stack, seen)),
+                _ => false,
+            }
+        }
+        Type::Array(elem, _) => contains_struct(elem, target, scope_stack, seen),
+        _ => false,
+    }
+}
+
+fn analyze_stmt(stmt: &Stmt,
+                scope_stack: &mut ScopeStack,
+                scope_errors: &mut Vec<ScopeError>,
+                typechk_errors: &mut Vec<TypeChkError>,
+                types: &mut TypeTable,
+                flow: &mut Flow,
+                current_return_type: Option<&Type>) {
+    match &stmt.kind {
+        StmtKind::Expr(expr) => {
+            analyze_expr(expr, scope_stack, scope_errors, typechk_errors, types);
+        }
+        StmtKind::Let { name, type_annot, value } => {
+            if let Some(value) = value {
+                analyze_expr(value, scope_stack, scope_errors, typechk_errors, types);
+            }
+            let ty = type_annot.as_ref().map(|t| resolve_annot(t, scope_stack, stmt.span, scope_errors)).unwrap_or(Type::Void);
+            if let Some(value) = value {
+                check_aggregate_init(&ty, value, typechk_errors, types);
+            }
+            let initialized = value.is_some() || zero_filled(&ty) || !flow.reachable;
+            if let Err(e) = scope_stack.insert_variable(name.clone(), ty, true, initialized, stmt.span) {
+                scope_errors.push(redefinition(e, name, stmt.span, scope_stack));
+            }
+        }
+        StmtKind::Block(stmts) => {
+            scope_stack.enter_scope();
+            let mut warned = false;
+            for s in stmts {
+                // one warning for the whole dead tail of the block
+                if !warned && !matches!(s.kind, StmtKind::Error) {
+                    warned = !flow.check_reachable(s.span);
+                }
+                analyze_stmt(s, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            }
+            scope_stack.exit_scope();
+        }
+        StmtKind::Return(expr_opt) => {
+            if let Some(expr) = expr_opt {
+                analyze_expr(expr, scope_stack, scope_errors, typechk_errors, types);
+                // errors inside the expression were reported by analyze_expr
+                if let Some(ty) = types.get(&expr.span).cloned()
+                    && let Some(expected) = current_return_type
+                    && *expected != Type::Unknown && *expected != Type::Void && ty != Type::Unknown && *expected != ty
+                {
+                    typechk_errors.push(TypeChkError::new(TypeChkErrorKind::ErroneousReturnType, expr.span).with_types(expected.clone(), ty));
+                }
+            } else if let Some(expected) = current_return_type
+                && *expected != Type::Void && *expected != Type::Unknown
+            {
+                typechk_errors.push(TypeChkError::new(TypeChkErrorKind::ErroneousReturnType, stmt.span).with_types(expected.clone(), Type::Void));
+            }
+            flow.record_return(scope_stack);
+        }
+        StmtKind::Break => {
+            if !scope_stack.in_loop() {
+                typechk_errors.push(TypeChkError::new(TypeChkErrorKind::ErroneousBreak, stmt.span));
+            }
+            flow.record_break(scope_stack);
+        }
+        StmtKind::If { condition, then_branch, else_branch } => {
+            check_condition(condition, scope_stack, scope_errors, typechk_errors, types);
+            let before = flow.snapshot(scope_stack);
+            analyze_stmt(then_branch, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            let after_then = flow.snapshot(scope_stack);
+            flow.restore(scope_stack, before);
+            if let Some(else_stmt) = else_branch {
+                analyze_stmt(else_stmt, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            }
+            let after_else = flow.snapshot(scope_stack);
+            flow.restore(scope_stack, flow::join(after_then, after_else));
+        }
+        StmtKind::While { condition, body } => {
+            check_condition(condition, scope_stack, scope_errors, typechk_errors, types);
+            // assignments only add to the state, so the first iteration is the one that
+            // sees the fewest variables assigned
+            let before = flow.snapshot(scope_stack);
+            scope_stack.enter_loop();
+            flow.enter_loop();
+            analyze_stmt(body, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            let breaks = flow.exit_loop();
+            scope_stack.exit_loop();
+            flow.restore(scope_stack, loop_exit(Some(condition), before, breaks));
+        }
+        StmtKind::For { init, condition, increment, body } => {
+            scope_stack.enter_scope();
+            scope_stack.enter_loop();
+
+            if let Some(init_stmt) = init {
+                analyze_stmt(init_stmt, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            }
+            if let Some(cond) = condition {
+                check_condition(cond, scope_stack, scope_errors, typechk_errors, types);
+            }
+            let before = flow.snapshot(scope_stack);
+            flow.enter_loop();
+            analyze_stmt(body, scope_stack, scope_errors, typechk_errors, types, flow, current_return_type);
+            let breaks = flow.exit_loop();
+            // the increment runs after a body that finished normally
+            if let Some(incr) = increment {
+                if !flow.reachable {
+                    flow.restore(scope_stack, before.clone());
+                }
+                analyze_expr(incr, scope_stack, scope_errors, typechk_errors, types);
+            }
+            flow.restore(scope_stack, loop_exit(condition.as_ref(), before, breaks));
+
+            scope_stack.exit_scope();
+            scope_stack.exit_loop();
+        }
+        StmtKind::Function { .. } => {
+            // Nested functions ignored here as they are handled via declaration processing
+        }
+        StmtKind::Error => flow.incomplete = true, // reported by the parser
+    }
+}
+
+// State after a loop: it is left when the condition is false, which can already happen
+// before the first iteration, or through a `break`. A missing or literal `true` condition
+// never ends the loop.
+fn loop_exit(condition: Option<&Expr>, before: Option<Assigned>, breaks: Option<Assigned>) -> Option<Assigned> {
+    match condition {
+        Some(cond) if !matches!(cond.kind, ExprKind::Boolean(true)) => flow::join(before, breaks),
+        _ => breaks,
+    }
+}
+
+// Arrays of scalars start out filled with zero values; other variables declared without
+// an initializer must be assigned before they are read
+fn zero_filled(ty: &Type) -> bool {
+    match ty {
+        Type::Array(elem, _) => matches!(**elem, Type::Int | Type::Float | Type::Bool | Type::Char | Type::String) || zero_filled(elem),
+        _ => false,
+    }
+}
+
+// Conditions of if/while/for must type check to bool
+fn check_condition(condition: &Expr, scope_stack: &mut ScopeStack, scope_errors: &mut Vec<ScopeError>, typechk_errors: &mut Vec<TypeChkError>, types: &mut TypeTable) {
+    analyze_expr(condition, scope_stack, scope_errors, typechk_errors, types);
+    // errors inside the condition were reported by analyze_expr
+    if let Some(t) = types.get(&condition.span).cloned()
+        && t != Type::Bool
+        && t != Type::Unknown
+    {
+        typechk_errors.push(TypeChkError::new(TypeChkErrorKind::NonBooleanCondStmt, condition.span).with_types(Type::Bool, t));
+    }
+}
+
+// Array- and struct-typed declarations must be initialized with a value of exactly that
+// type. Other initializers are not checked against their declared type yet.
+fn check_aggregate_init(declared: &Type, value: &Expr, typechk_errors: &mut Vec<TypeChkError>, types: &TypeTable) {
+    // errors inside the initializer itself are reported by analyze_expr
+    let Some(actual) = types.get(&value.span).cloned() else {
+        return;
+    };
+    let kind = match declared {
+        Type::Array(..) => array_mismatch(declared, &actual),
+        Type::Custom(_) if actual != Type::Unknown && actual != *declared => Some(TypeChkErrorKind::ExpressionTypeMismatch),
+        _ => None,
+    };
+    if let Some(kind) = kind {
+        typechk_errors.push(TypeChkError::new(kind, value.span).with_types(declared.clone(), actual));
+    }
+}
+
+// A declaration of `name` at `span` that the scope stack rejected; points back at the
+// declaration already in scope
+fn redefinition(kind: ScopeErrorKind, name: &str, span: Span, scope_stack: &ScopeStack) -> ScopeError {
+    let previous = scope_stack.lookup_symbol_any(name).map(|sym| sym.span).filter(|s| *s != span);
+    ScopeError::new(kind, span).named(name).with_previous(previous)
+}
+
+// Type named by an annotation; a struct name that is not in scope is reported and
+// becomes Unknown so its uses don't pile up more errors
+fn resolve_annot(annot: &TypeAnnot, scope_stack: &ScopeStack, span: Span, scope_errors: &mut Vec<ScopeError>) -> Type {
+    match annot {
+        TypeAnnot::Struct(name) => match scope_stack.lookup_struct(name) {
+            Ok(_) => Type::Custom(name.clone()),
+            Err(e) => {
+                scope_errors.push(ScopeError::new(e, span).named(name));
+                Type::Unknown
+            }
+        },
+        TypeAnnot::Array(elem, len) => Type::Array(Box::new(resolve_annot(elem, scope_stack, span, scope_errors)), *len),
+        TypeAnnot::Named(_) => annot_to_type(annot),
+    }
+}
+
+// Why a value of type `actual` cannot be stored where an array of type `expected` lives
+fn array_mismatch(expected: &Type, actual: &Type) -> Option<TypeChkErrorKind> {
+    match (expected, actual) {
+        (_, Type::Unknown) => None,
+        (Type::Array(expected_elem, expected_len), Type::Array(actual_elem, actual_len)) => {
+            // `[]` has an unknown element type and fits only a zero-length array
+            if expected_len != actual_len {
+                Some(TypeChkErrorKind::ArrayLengthMismatch)
+            } else if **actual_elem == Type::Unknown {
+                None
+            } else if matches!(**expected_elem, Type::Array(..)) {
+                array_mismatch(expected_elem, actual_elem)
+            } else if expected_elem != actual_elem {
+                Some(TypeChkErrorKind::ArrayElementTypeMismatch)
+            } else {
+                None
+            }
+        }
+        (Type::Array(..), _) => Some(TypeChkErrorKind::ExpressionTypeMismatch),
+        _ => None,
+    }
+}
+
+// Value of an index expression known at compile time: an integer literal, possibly
+// negated or parenthesized
+fn constant_int(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::Integer(n) => Some(*n),
+        ExprKind::Grouping(inner) => constant_int(inner),
+        ExprKind::Unary { operator: TokenKind::T_MINUS, expr: inner } => constant_int(inner).map(|n| n.wrapping_neg()),
+        _ => None,
+    }
+}
+
+fn analyze_expr(expr: &Expr, scope_stack: &mut ScopeStack, scope_errors: &mut Vec<ScopeError>, typechk_errors: &mut Vec<TypeChkError>, types: &mut TypeTable) {
+    // Scope error checks
+    match &expr.kind {
+        ExprKind::Identifier(name) => {
+            if let Err(e) = scope_stack.lookup_variable(name) {
+                let declared = scope_stack.lookup_symbol_any(name).map(|sym| sym.span);
+                scope_errors.push(ScopeError::new(e, expr.span).named(name).with_previous(declared));
+            }
+        }
+        ExprKind::Binary { left, operator: TokenKind::T_ASSIGNOP, right } => {
+            analyze_expr(right, scope_stack, scope_errors, typechk_errors, types);
+            match &left.kind {
+                // storing into a variable is not a use of it: it only has to be declared.
+                // Assigning to a function is reported by the type checker.
+                ExprKind::Identifier(name) => {
+                    if let Err(e @ ScopeErrorKind::UndeclaredIdentifier) = scope_stack.mark_initialized(name) {
+                        scope_errors.push(ScopeError::new(e, left.span).named(name));
+                    }
+                }
+                // `xs[i] = v` and `p.x = v` read xs and p
+                _ => analyze_expr(left, scope_stack, scope_errors, typechk_errors, types),
+            }
+        }
+        ExprKind::Binary { left, operator: TokenKind::T_ANDAND | TokenKind::T_OROR, right } => {
+            analyze_expr(left, scope_stack, scope_errors, typechk_errors, types);
+            // the right operand may not run, so what it assigns doesn't count afterwards
+            let before = scope_stack.assigned();
+            analyze_expr(right, scope_stack, scope_errors, typechk_errors, types);
+            scope_stack.set_assigned(&before);
+        }
+        ExprKind::Binary { left, right, .. } => {
+            analyze_expr(left, scope_stack, scope_errors, typechk_errors, types);
+            analyze_expr(right, scope_stack, scope_errors, typechk_errors, types);
+        }
+        ExprKind::Unary { expr: inner, .. } => {
+            analyze_expr(inner, scope_stack, scope_errors, typechk_errors, types);
+        }
+        ExprKind::Call { callee, args } => {
+            // a callee name is a function, not a variable; it is resolved below
+            if !matches!(callee.kind, ExprKind::Identifier(_)) {
+                analyze_expr(callee, scope_stack, scope_errors, typechk_errors, types);
+            }
+            for arg in args {
+                analyze_expr(arg, scope_stack, scope_errors, typechk_errors, types);
+            }
+            if let ExprKind::Identifier(name) = &callee.kind
+                && let Err(e) = scope_stack.lookup_function(name)
+            {
+                let declared = scope_stack.lookup_symbol_any(name).map(|sym| sym.span);
+                scope_errors.push(ScopeError::new(e, callee.span).named(name).with_previous(declared));
+            }
+        }
+        ExprKind::Grouping(inner) | ExprKind::Cast { expr: inner, .. } => {
+            analyze_expr(inner, scope_stack, scope_errors, typechk_errors, types);
+        }
+        ExprKind::Array(elements) => {
+            for element in elements {
+                analyze_expr(element, scope_stack, scope_errors, typechk_errors, types);
+            }
+        }
+        ExprKind::Index { array, index } => {
+            analyze_expr(array, scope_stack, scope_errors, typechk_errors, types);
+            analyze_expr(index, scope_stack, scope_errors, typechk_errors, types);
+        }
+        ExprKind::Field { object, .. } => {
+            analyze_expr(object, scope_stack, scope_errors, typechk_errors, types);
+        }
+        ExprKind::StructLit { name, fields } => {
+            if let Err(e) = scope_stack.lookup_struct(name) {
+                let declared = scope_stack.lookup_symbol_any(name).map(|sym| sym.span);
+                scope_errors.push(ScopeError::new(e, expr.span).named(name).with_previous(declared));
+            }
+            for field in fields {
+                analyze_expr(&field.value, scope_stack, scope_errors, typechk_errors, types);
+            }
+        }
+        ExprKind::Integer(_) | ExprKind::Float(_) | ExprKind::Boolean(_) | ExprKind::StringLit(_) | ExprKind::Char(_) => {}
+        ExprKind::Error => {} // already reported by the lexer or parser
+    }
+
+    // Type checking errors. The operands have their types by now; an expression with one
+    // that failed to check is not checked itself, its error has been reported.
+    if operands(expr).iter().all(|operand| types.contains_key(&operand.span)) {
+        match visit_expr(expr, scope_stack, types) {
+            Ok(ty) => {
+                types.insert(expr.span, ty);
+            }
+            Err(e) => typechk_errors.push(e),
+        }
+    }
+}
+
+// The subexpressions an expression's value is computed from, and so its type: all of
+// them but callee names and the variable an assignment stores into
+fn operands(expr: &Expr) -> Vec<&Expr> {
+    match &expr.kind {
+        ExprKind::Binary { left, operator: TokenKind::T_ASSIGNOP, right } if matches!(left.kind, ExprKind::Identifier(_)) => vec![right],
+        ExprKind::Binary { left, right, .. } | ExprKind::Index { array: left, index: right } => vec![left, right],
+        ExprKind::Unary { expr: inner, .. } | ExprKind::Grouping(inner) | ExprKind::Cast { expr: inner, .. } | ExprKind::Field { object: inner, .. } => {
+            vec![inner]
+        }
+        ExprKind::Call { callee, args } => {
+            let callee = (!matches!(callee.kind, ExprKind::Identifier(_))).then_some(&**callee);
+            callee.into_iter().chain(args).collect()
+        }
+        ExprKind::Array(elements) => elements.iter().collect(),
+        ExprKind::StructLit { fields, .. } => fields.iter().map(|field| &field.value).collect(),
+        ExprKind::Identifier(_)
+        | ExprKind::Integer(_)
+        | ExprKind::Float(_)
+        | ExprKind::Boolean(_)
+        | ExprKind::StringLit(_)
+        | ExprKind::Char(_)
+        | ExprKind::Error => Vec::new(),
+    }
+}
+
+// Type of one expression, from the types of its operands, which were checked first and
+// are all in `types` (see analyze_expr)
+fn visit_expr(expr: &Expr, scope_stack: &ScopeStack, types: &TypeTable) -> Result<Type, TypeChkError> {
+    let err = |kind| TypeChkError::new(kind, expr.span);
+    let ty = |operand: &Expr| types.get(&operand.span).cloned().unwrap_or(Type::Unknown);
+    match &expr.kind {
+        // whether the name is declared, assigned and a value at all is up to analyze_expr;
+        // its type is the declared one either way
+        ExprKind::Identifier(name) => match scope_stack.lookup_symbol_any(name) {
+            Some(Symbol { kind: SymbolKind::Variable { .. } | SymbolKind::Parameter, ty, .. }) => Ok(ty.clone().unwrap_or(Type::Unknown)),
+            _ => Ok(Type::Unknown),
+        },
+        ExprKind::Integer(_) => Ok(Type::Int),
+        ExprKind::Float(_) => Ok(Type::Float),
+        ExprKind::Boolean(_) => Ok(Type::Bool),
+        ExprKind::StringLit(_) => Ok(Type::String),
+        ExprKind::Char(_) => Ok(Type::Char),
+
+        ExprKind::Unary { operator, expr: inner } => {
+            let t = ty(inner);
+            match operator {
+                TokenKind::T_MINUS => {
+                    if matches!(t, Type::Int | Type::Float) {
+                        Ok(t)
+                    } else {
+                        Err(err(TypeChkErrorKind::AttemptedAddOpOnNonNumeric))
+                    }
+                }
+                TokenKind::T_NOT => {
+                    if t == Type::Bool {
+                        Ok(Type::Bool)
+                    } else {
+                        Err(err(TypeChkErrorKind::AttemptedBoolOpOnNonBools))
+                    }
+                }
+                TokenKind::T_TILDE => {
+                    if t == Type::Int {
+                        Ok(Type::Int)
+                    } else {
+                        Err(err(TypeChkErrorKind::AttemptedBitOpOnNonNumeric))
+                    }
+                }
+                _ => Ok(t),
+            }
+        }
+
+        ExprKind::Binary { left, operator, right } => {
+            // the target of `x = ...` is written, not read, so it need not be assigned yet
+            let lt = match (&left.kind, operator) {
+                (ExprKind::Identifier(name), TokenKind::T_ASSIGNOP) => match scope_stack.lookup_symbol_any(name) {
+                    Some(Symbol { kind: SymbolKind::Variable { mutable: true }, ty, .. }) => ty.clone().unwrap_or(Type::Unknown),
+                    Some(Symbol { kind: SymbolKind::Variable { mutable: false } | SymbolKind::Parameter, span, .. }) => {
+                        return Err(err(TypeChkErrorKind::AssignmentToImmutable).with_related(*span, "parameter declared here"));
+                    }
+                    Some(Symbol { kind: SymbolKind::Function { .. }, span, .. }) => {
+                        return Err(err(TypeChkErrorKind::AssignmentToFunction).with_related(*span, "function defined here"));
+                    }
+                    Some(Symbol { kind: SymbolKind::Struct { .. }, .. }) => return Err(err(TypeChkErrorKind::ErroneousVarDecl)),
+                    None => Type::Unknown, // reported by analyze_expr
+                },
+                _ => ty(left),
+            };
+            let rt = ty(right);
+
+            match operator {
+                TokenKind::T_PLUS | TokenKind::T_MINUS | TokenKind::T_STAR | TokenKind::T_SLASH | TokenKind::T_PERCENT => {
+                    if !matches!(lt, Type::Int | Type::Float) || !matches!(rt, Type::Int | Type::Float) {
+                        return Err(err(TypeChkErrorKind::AttemptedAddOpOnNonNumeric));
+                    }
+                    if lt == Type::Float || rt == Type::Float { Ok(Type::Float) } else { Ok(Type::Int) }
+                }
+                TokenKind::T_CARET => {
+                    if !matches!(lt, Type::Int | Type::Float) || !matches!(rt, Type::Int | Type::Float) {
+                        return Err(err(TypeChkErrorKind::AttemptedExponentiationOfNonNumeric));
+                    }
+                    if lt == Type::Float || rt == Type::Float { Ok(Type::Float) } else { Ok(Type::Int) }
+                }
+                TokenKind::T_ANDAND | TokenKind::T_OROR => {
+                    if lt != Type::Bool || rt != Type::Bool {
+                        return Err(err(TypeChkErrorKind::AttemptedBoolOpOnNonBools));
+                    }
+                    Ok(Type::Bool)
+                }
+                TokenKind::T_AMP | TokenKind::T_PIPE => {
+                    if lt != Type::Int || rt != Type::Int {
+                        return Err(err(TypeChkErrorKind::AttemptedBitOpOnNonNumeric));
+                    }
+                    Ok(Type::Int)
+                }
+                TokenKind::T_LSHIFT | TokenKind::T_RSHIFT => {
+                    if lt != Type::Int || rt != Type::Int {
+                        return Err(err(TypeChkErrorKind::AttemptedShiftOnNonInt));
+                    }
+                    Ok(Type::Int)
+                }
+                TokenKind::T_EQUALSOP | TokenKind::T_NEQ | TokenKind::T_LT | TokenKind::T_GT | TokenKind::T_LTE | TokenKind::T_GTE => {
+                    if lt == Type::Unknown || rt == Type::Unknown {
+                        if lt != rt && lt != Type::Unknown && rt != Type::Unknown {
+                            Err(err(TypeChkErrorKind::ExpressionTypeMismatch).with_types(lt, rt))
+                        } else {
+                            Ok(Type::Bool)
+                        }
+                    } else if lt != rt {
+                        Err(err(TypeChkErrorKind::ExpressionTypeMismatch).with_types(lt, rt))
+                    } else {
+                        Ok(Type::Bool)
+                    }
+                }
+                // storing into an array element or a field must keep its type; whole arrays
+                // must also keep their length
+                TokenKind::T_ASSIGNOP if matches!(left.kind, ExprKind::Index { .. } | ExprKind::Field { .. }) => {
+                    if let Type::Array(..) = lt {
+                        if let Some(kind) = array_mismatch(&lt, &rt) {
+                            return Err(err(kind).with_types(lt, rt));
+                        }
+                    } else if lt != Type::Unknown && rt != Type::Unknown && lt != rt {
+                        let kind = match left.kind {
+                            ExprKind::Field { .. } => TypeChkErrorKind::FieldTypeMismatch,
+                            _ => TypeChkErrorKind::ArrayElementTypeMismatch,
+                        };
+                        return Err(err(kind).with_types(lt, rt));
+                    }
+                    Ok(lt)
+                }
+                TokenKind::T_ASSIGNOP if matches!(lt, Type::Array(..)) => match array_mismatch(&lt, &rt) {
+                    Some(kind) => Err(err(kind).with_types(lt, rt)),
+                    None => Ok(lt),
+                },
+                // the value of an assignment is the value stored, so `a = b = 1` checks b first
+                TokenKind::T_ASSIGNOP => {
+                    if lt != Type::Unknown && rt != Type::Unknown && lt != rt {
+                        return Err(err(TypeChkErrorKind::AssignmentTypeMismatch).with_types(lt, rt));
+                    }
+                    Ok(lt)
+                }
+                _ => Ok(Type::Unknown),
+            }
+        }
+
+            ExprKind::Call { callee, args } => {
+                if let ExprKind::Identifier(name) = &callee.kind {
+                    // a call to a function that only has a prototype, or to something that
+                    // is not a function, is reported by analyze_expr; the prototype still
+                    // says what the call takes and gives
+                    let (defined_at, param_types, ret_type) = match scope_stack.lookup_symbol_any(name) {
+                        Some(Symbol { kind: SymbolKind::Function { params, return_type, .. }, span, .. }) => {
+                            (*span, params.clone(), return_type.clone())
+                        }
+                        _ => return Ok(Type::Unknown),
+                    };
+
+                    if param_types.len() != args.len() {
+                        return Err(err(TypeChkErrorKind::FnCallParamCount)
+                            .with_related(defined_at, "function defined here")
+                            .with_note(format!("`{}` takes {} and this call passes {}", name, arguments(param_types.len()), arguments(args.len()))));
+                    }
+
+                    for (i, arg) in args.iter().enumerate() {
+                        let arg_ty = ty(arg);
+                        let param_ty = param_types.get(i).cloned().unwrap_or(Type::Unknown);
+                        if param_ty != Type::Unknown && arg_ty != Type::Unknown && param_ty != arg_ty {
+                            return Err(TypeChkError::new(TypeChkErrorKind::FnCallParamType, arg.span)
+                                .with_types(param_ty, arg_ty)
+                                .with_related(defined_at, "function defined here"));
+                        }
+                    }
+                    return Ok(ret_type);
+                }
+                Ok(Type::Unknown)
+            }
+
+        ExprKind::Grouping(inner) => Ok(ty(inner)),
+
+        ExprKind::Array(elements) => {
+            let mut elem_ty = Type::Unknown;
+            for element in elements {
+                let ty = ty(element);
+                if elem_ty == Type::Unknown {
+                    elem_ty = ty;
+                } else if ty != Type::Unknown && ty != elem_ty {
+                    return Err(TypeChkError::new(TypeChkErrorKind::ArrayElementTypeMismatch, element.span).with_types(elem_ty, ty));
+                }
+            }
+            Ok(Type::Array(Box::new(elem_ty), elements.len()))
+        }
+
+        ExprKind::Field { object, field } => match ty(object) {
+            Type::Custom(name) => match scope_stack.struct_field(&name, field) {
+                Some(ty) => Ok(ty.clone()),
+                None => Err(err(TypeChkErrorKind::UnknownField).with_note(format!("struct `{}` has no field `{}`", name, field))),
+            },
+            Type::Unknown => Ok(Type::Unknown),
+            ty => Err(TypeChkError::new(TypeChkErrorKind::FieldAccessOnNonStruct, object.span).with_note(format!("this value has type `{}`", ty))),
+        },
+
+        ExprKind::StructLit { name, fields } => {
+            let declared = match &scope_stack.lookup_struct(name) {
+                Ok(Symbol { kind: SymbolKind::Struct { fields }, .. }) => fields.clone(),
+                _ => return Ok(Type::Unknown), // reported by analyze_expr
+            };
+            for (i, init) in fields.iter().enumerate() {
+                let Some((_, expected)) = declared.iter().find(|(f, _)| *f == init.name) else {
+                    return Err(TypeChkError::new(TypeChkErrorKind::UnknownField, init.span)
+                        .with_note(format!("struct `{}` has no field `{}`", name, init.name)));
+                };
+                if let Some(earlier) = fields[..i].iter().find(|earlier| earlier.name == init.name) {
+                    return Err(TypeChkError::new(TypeChkErrorKind::DuplicateFieldInit, init.span)
+                        .with_related(earlier.span, "first initialized here"));
+                }
+                let actual = ty(&init.value);
+                let mismatch = match expected {
+                    Type::Array(..) => array_mismatch(expected, &actual),
+                    _ if actual != Type::Unknown && *expected != Type::Unknown && actual != *expected => {
+                        Some(TypeChkErrorKind::FieldTypeMismatch)
+                    }
+                    _ => None,
+                };
+                if let Some(kind) = mismatch {
+                    return Err(TypeChkError::new(kind, init.value.span).with_types(expected.clone(), actual));
+                }
+            }
+            let missing: Vec<String> = declared
+                .iter()
+                .filter(|(f, _)| !fields.iter().any(|init| init.name == *f))
+                .map(|(f, _)| format!("`{}`", f))
+                .collect();
+            if !missing.is_empty() {
+                return Err(err(TypeChkErrorKind::MissingFieldInit).with_note(format!("missing {}", missing.join(", "))));
+            }
+            Ok(Type::Custom(name.clone()))
+        }
+
+        ExprKind::Index { array, index } => {
+            let array_ty = ty(array);
+            let index_ty = ty(index);
+            if !matches!(index_ty, Type::Int | Type::Unknown) {
+                return Err(TypeChkError::new(TypeChkErrorKind::NonIntegerIndex, index.span).with_types(Type::Int, index_ty));
+            }
+            match array_ty {
+                Type::Array(elem, len) => {
+                    if let Some(n) = constant_int(index)
+                        && (n < 0 || n as u64 >= len as u64)
+                    {
+                        return Err(TypeChkError::new(TypeChkErrorKind::IndexOutOfBounds, index.span)
+                            .with_note(format!("the array has {} elements but the index is {}", len, n)));
+                    }
+                    Ok(*elem)
+                }
+                Type::Unknown => Ok(Type::Unknown),
+                ty => Err(TypeChkError::new(TypeChkErrorKind::IndexingNonArray, array.span).with_note(format!("this value has type `{}`", ty))),
+            }
+        }
+
+        ExprKind::Cast { target, expr: inner } => {
+            let from = ty(inner);
+            // char <-> int goes through the code point; chars and ints can also become strings
+            let (to, allowed) = match target {
+                TokenKind::T_INT => (Type::Int, matches!(from, Type::Int | Type::Char)),
+                TokenKind::T_CHAR => (Type::Char, matches!(from, Type::Char | Type::Int)),
+                TokenKind::T_STRING => (Type::String, matches!(from, Type::String | Type::Char | Type::Int)),
+                other => {
+                    let to = annot_to_type(&TypeAnnot::Named(other.clone()));
+                    let same = from == to;
+                    (to, same)
+                }
+            };
+            if allowed || from == Type::Unknown {
+                Ok(to)
+            } else {
+                Err(err(TypeChkErrorKind::InvalidConversion).with_note(format!("cannot convert `{}` to `{}`", from, to)))
+            }
+        }
+
+        ExprKind::Error => Ok(Type::Unknown),
+    }
+}
+
+fn arguments(n: usize) -> String {
+    format!("{} argument{}", n, if n == 1 { "" } else { "s" })
+}
+
+pub fn annot_to_type(annot: &TypeAnnot) -> Type {
+    match annot {
+        TypeAnnot::Named(TokenKind::T_INT) => Type::Int,
+        TypeAnnot::Named(TokenKind::T_FLOAT) => Type::Float,
+        TypeAnnot::Named(TokenKind::T_BOOL) => Type::Bool,
+        TypeAnnot::Named(TokenKind::T_STRING) => Type::String,
+        TypeAnnot::Named(TokenKind::T_CHAR) => Type::Char,
+        TypeAnnot::Named(_) => Type::Void, // Default fallback type
+        TypeAnnot::Array(elem, len) => Type::Array(Box::new(annot_to_type(elem)), *len),
+        TypeAnnot::Struct(name) => Type::Custom(name.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // codes of the errors and warnings `check` reports for `src`, in order
+    fn codes(src: &str) -> Vec<&'static str> {
+        let (program, diagnostics) = crate::parse(src);
+        assert!(diagnostics.is_empty(), "{} does not parse", src);
+        crate::check(&program).1.iter().map(|d| d.code).collect()
+    }
+
+    #[test]
+    fn nested_type_error_is_reported_once() {
+        assert_eq!(codes("float f = 1.0; int t = ((f << 2) + 1) * 3;"), ["E0412"]);
+        assert_eq!(codes("int i = 1; i = int(2.0);"), ["E0415"]);
+    }
+
+    #[test]
+    fn condition_of_unknown_type_is_not_reported_again() {
+        assert_eq!(codes("while (nope) {}"), ["E0301"]);
+        assert_eq!(codes("fn g(): int; if (g() > 1) {}"), ["E0303"]);
+    }
+
+    #[test]
+    fn structs_can_be_used_before_their_declaration() {
+        assert!(codes("struct A { B b; } fn f(C c): int { return c.x; } struct B { int m; } struct C { int x; }").is_empty());
+    }
+
+    #[test]
+    fn struct_that_contains_itself_is_reported() {
+        assert_eq!(codes("struct N { int v; N n; }"), ["E0315"]);
+        assert_eq!(codes("struct A { B[2] b; } struct B { A a; }"), ["E0315", "E0315"]);
+    }
+}
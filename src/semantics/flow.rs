@@ -0,0 +1,114 @@
+// Control-flow facts for the statement walk in analysis.rs. The definitely-assigned flags
+// themselves live on the symbols in ScopeStack (they describe the point being analysed);
+// Flow adds what a straight walk cannot see: whether the point is reachable at all, and
+// which variables were assigned at each `break` of the enclosing loops. Reachability at
+// the end of a function body is what decides whether it can finish without returning.
+use std::collections::HashSet;
+use crate::diagnostic::Diagnostic;
+use crate::semantics::scope::scope::ScopeStack;
+use crate::span::Span;
+
+#[derive(Debug)]
+pub struct FlowWarning {
+    pub kind: FlowWarningKind,
+    pub span: Span, // first statement of the dead code
+}
+
+impl FlowWarning {
+    pub fn new(kind: FlowWarningKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug)]
+pub enum FlowWarningKind {
+    UnreachableCode, // follows a return or break on every path
+}
+
+impl From<&FlowWarning> for Diagnostic {
+    fn from(w: &FlowWarning) -> Self {
+        match w.kind {
+            FlowWarningKind::UnreachableCode => Diagnostic::warning("W0501", "unreachable statement", w.span)
+                .with_label("no path reaches this statement")
+                .with_note("every path before it ends in a `return` or `break`"),
+        }
+    }
+}
+
+// Variables and parameters definitely assigned at a program point, as (scope level, name)
+pub type Assigned = HashSet<(usize, String)>;
+
+// The state where two paths meet: a variable is assigned only if it is on both. None is
+// an unreachable point, which places no constraint on the other path.
+pub fn join(a: Option<Assigned>, b: Option<Assigned>) -> Option<Assigned> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[derive(Debug)]
+pub struct Flow {
+    pub reachable: bool,            // false after return/break until paths merge again
+    breaks: Vec<Option<Assigned>>,  // per enclosing loop: joined state of its breaks so far
+    pub incomplete: bool,           // a statement failed to parse, so what it does to control flow is unknown
+    pub warnings: Vec<FlowWarning>,
+}
+
+impl Default for Flow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Flow {
+    pub fn new() -> Self {
+        Flow { reachable: true, breaks: Vec::new(), incomplete: false, warnings: Vec::new() }
+    }
+
+    // warns about the statements of a block from `span` on when no path reaches them;
+    // called once per block, with its first such statement
+    pub fn check_reachable(&mut self, span: Span) -> bool {
+        if !self.reachable {
+            self.warnings.push(FlowWarning::new(FlowWarningKind::UnreachableCode, span));
+        }
+        self.reachable
+    }
+
+    // state of the current point; None if it cannot be reached
+    pub fn snapshot(&self, scope_stack: &ScopeStack) -> Option<Assigned> {
+        self.reachable.then(|| scope_stack.assigned())
+    }
+
+    // continue from `state`; anything counts as assigned in unreachable code so it doesn't
+    // report uses that can never happen
+    pub fn restore(&mut self, scope_stack: &mut ScopeStack, state: Option<Assigned>) {
+        self.reachable = state.is_some();
+        match state {
+            Some(assigned) => scope_stack.set_assigned(&assigned),
+            None => scope_stack.assume_all_assigned(),
+        }
+    }
+
+    pub fn enter_loop(&mut self) {
+        self.breaks.push(None);
+    }
+
+    // joined state of every `break` out of the loop being left; None if it has none
+    pub fn exit_loop(&mut self) -> Option<Assigned> {
+        self.breaks.pop().flatten()
+    }
+
+    pub fn record_break(&mut self, scope_stack: &mut ScopeStack) {
+        let here = self.snapshot(scope_stack);
+        if let Some(slot) = self.breaks.last_mut() {
+            *slot = join(slot.take(), here);
+        }
+        self.restore(scope_stack, None);
+    }
+
+    pub fn record_return(&mut self, scope_stack: &mut ScopeStack) {
+        self.restore(scope_stack, None);
+    }
+}
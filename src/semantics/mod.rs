@@ -1,2 +1,4 @@
+pub mod flow;
 pub mod scope;
 pub mod typechecker;
+pub mod analysis;
@@ -1 +1,2 @@
-pub mod scope;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod scope;
@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type { //Had to introduce Type enum to make function signatures work
     Int,
     Float,
@@ -14,8 +17,50 @@ pub enum Type { //Had to introduce Type enum to make function signatures work
     Pointer(Box<Type>),
 }
 
+// spelled the way the type is written in source, for messages
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Unknown => write!(f, "{{unknown}}"),
+            Type::Custom(name) => write!(f, "{}", name),
+            Type::Array(elem, len) => write!(f, "{}[{}]", elem, len),
+            Type::Pointer(inner) => write!(f, "*{}", inner),
+        }
+    }
+}
+
 #[derive(Debug)]
-pub enum ScopeError {
+pub struct ScopeError {
+    pub kind: ScopeErrorKind,
+    pub span: Span, // where the offending name was used or declared
+    pub name: Option<String>,  // the offending name, for messages
+    pub previous: Option<Span>, // declaration the error refers back to, e.g. the first definition
+}
+
+impl ScopeError {
+    pub fn new(kind: ScopeErrorKind, span: Span) -> Self {
+        Self { kind, span, name: None, previous: None }
+    }
+
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_previous(mut self, previous: Option<Span>) -> Self {
+        self.previous = previous;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum ScopeErrorKind {
     // name resolution / kind mismatch
     UndeclaredIdentifier,
     FoundButWrongKind,              
@@ -28,11 +73,53 @@ pub enum ScopeError {
     // variables
     VariableRedefinition,
     VariableUsedBeforeInit,
+    // structs
+    StructRedefinition,
+    UndeclaredStruct,               // struct name in a type or literal that was never declared
+    DuplicateField,                 // same field name twice in one struct declaration
+    RecursiveStruct,                // a field holds the struct it is in, directly or through other structs
     // generic
     NoCurrentScope,
     BreakMustInsideLoop,
 }
 
+impl From<&ScopeError> for Diagnostic {
+    fn from(e: &ScopeError) -> Self {
+        let who = e.name.as_deref().map_or("this name".to_string(), |n| format!("`{}`", n));
+        let (code, message, previous) = match e.kind {
+            ScopeErrorKind::UndeclaredIdentifier => ("E0301", format!("cannot find {} in this scope", who), ""),
+            ScopeErrorKind::FoundButWrongKind => ("E0302", format!("{} cannot be used this way", who), "declared here"),
+            ScopeErrorKind::UndefinedFunctionCalled => ("E0303", format!("call to undefined function {}", who), "only a prototype is declared here"),
+            ScopeErrorKind::FunctionPrototypeRedefinition => ("E0304", format!("prototype for {} declared twice", who), "first declared here"),
+            ScopeErrorKind::FunctionRedefinition => ("E0305", format!("function {} is defined more than once", who), "previously defined here"),
+            ScopeErrorKind::FunctionRedefinitionAsPrototype => ("E0306", format!("prototype for {} follows its definition", who), "defined here"),
+            ScopeErrorKind::FunctionSignatureConflict => ("E0307", format!("conflicting signatures for {}", who), "previously declared here"),
+            ScopeErrorKind::VariableRedefinition => ("E0308", format!("{} is already declared in this scope", who), "previously declared here"),
+            ScopeErrorKind::VariableUsedBeforeInit => ("E0309", format!("{} is used before it is assigned", who), "declared here without a value"),
+            ScopeErrorKind::StructRedefinition => ("E0310", format!("struct {} is defined more than once", who), "previously defined here"),
+            ScopeErrorKind::UndeclaredStruct => ("E0311", format!("cannot find struct {}", who), ""),
+            ScopeErrorKind::DuplicateField => ("E0312", format!("field {} is declared twice", who), "first declared here"),
+            ScopeErrorKind::NoCurrentScope => ("E0313", "declaration outside of any scope".to_string(), ""),
+            ScopeErrorKind::BreakMustInsideLoop => ("E0314", "`break` outside of a loop".to_string(), ""),
+            ScopeErrorKind::RecursiveStruct => ("E0315", format!("struct {} contains itself", who), "struct declared here"),
+        };
+        let mut d = Diagnostic::error(code, message, e.span);
+        if let Some(span) = e.previous {
+            d = d.with_secondary(span, previous);
+        }
+        match e.kind {
+            ScopeErrorKind::VariableUsedBeforeInit => {
+                d.with_help(format!("assign {} on every path that reaches this use, or give it an initializer", who))
+            }
+            ScopeErrorKind::UndefinedFunctionCalled if e.previous.is_some() => {
+                d.with_note("a prototype only declares the signature; the function still needs a body")
+            }
+            ScopeErrorKind::RecursiveStruct => d.with_note("a struct holds the values of its fields, so one that contains itself would never end"),
+            _ => d,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum SymbolKind {
     Variable { mutable: bool }, 
@@ -42,6 +129,9 @@ pub enum SymbolKind {
         return_type: Type,
         defined: bool, 
     },
+    Struct {
+        fields: Vec<(String, Type)>, // in declaration order
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -51,50 +141,67 @@ pub struct Symbol {
     pub scope_level: usize,
     pub ty: Option<Type>,        
     pub initialized: bool,       
+    pub span: Span, // declaration
 }
 
 impl Symbol {
-    pub fn new_variable(name: impl Into<String>, ty: Type, mutable: bool, scope_level: usize, initialized: bool) -> Self {
+    pub fn new_variable(name: impl Into<String>, ty: Type, mutable: bool, scope_level: usize, initialized: bool, span: Span) -> Self {
         Self {
             name: name.into(),
             kind: SymbolKind::Variable { mutable },
             scope_level,
             ty: Some(ty),
             initialized,
+            span,
         }
     }
 
     // Example usage: 
-    // let p1 = new_parameter("x", ty, 0);        // &str
-    // let p2 = new_parameter(String::from("y"), ty, 0); // String
+    // let p1 = new_parameter("x", ty, 0, span);        // &str
+    // let p2 = new_parameter(String::from("y"), ty, 0, span); // String
 
-    pub fn new_parameter(name: impl Into<String>, ty: Type, scope_level: usize) -> Self {
+    pub fn new_parameter(name: impl Into<String>, ty: Type, scope_level: usize, span: Span) -> Self {
         Self {
             name: name.into(),
             kind: SymbolKind::Parameter,
             scope_level,
             ty: Some(ty),
             initialized: true, // parameters are considered initialized
+            span,
         }
     }
 
-    pub fn new_function_prototype(name: impl Into<String>, params: Vec<Type>, return_type: Type, scope_level: usize) -> Self {
+    pub fn new_function_prototype(name: impl Into<String>, params: Vec<Type>, return_type: Type, scope_level: usize, span: Span) -> Self {
         Self {
             name: name.into(),
             kind: SymbolKind::Function { params, return_type: return_type.clone(), defined: false },
             scope_level,
             ty: Some(return_type),
             initialized: true,
+            span,
         }
     }
 
-    pub fn new_function_definition(name: impl Into<String>, params: Vec<Type>, return_type: Type, scope_level: usize) -> Self {
+    pub fn new_struct(name: impl Into<String>, fields: Vec<(String, Type)>, scope_level: usize, span: Span) -> Self {
+        let name = name.into();
+        Self {
+            ty: Some(Type::Custom(name.clone())),
+            name,
+            kind: SymbolKind::Struct { fields },
+            scope_level,
+            initialized: true,
+            span,
+        }
+    }
+
+    pub fn new_function_definition(name: impl Into<String>, params: Vec<Type>, return_type: Type, scope_level: usize, span: Span) -> Self {
         Self {
             name: name.into(),
             kind: SymbolKind::Function { params, return_type: return_type.clone(), defined: true },
             scope_level,
             ty: Some(return_type),
             initialized: true,
+            span,
         }
     }
 }
@@ -123,7 +230,7 @@ impl Scope {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ScopeStack {
     pub current: Option<Box<Scope>>,
     loop_depth: usize,
@@ -159,82 +266,115 @@ impl ScopeStack {
         }
     }
 
-    fn current_scope_mut(&mut self) -> Result<&mut Scope, ScopeError> {
-        self.current.as_deref_mut().ok_or(ScopeError::NoCurrentScope)
-    }
-
-    fn current_scope(&self) -> Option<&Scope> {
-        self.current.as_deref()
+    fn current_scope_mut(&mut self) -> Result<&mut Scope, ScopeErrorKind> {
+        self.current.as_deref_mut().ok_or(ScopeErrorKind::NoCurrentScope)
     }
 
     /// Insert a variable into the current scope.
-    pub fn insert_variable(&mut self, name: String, ty: Type, mutable: bool, initialized: bool) -> Result<(), ScopeError> {
+    pub fn insert_variable(&mut self, name: String, ty: Type, mutable: bool, initialized: bool, span: Span) -> Result<(), ScopeErrorKind> {
         let scope = self.current_scope_mut()?;
         if scope.symbols.contains_key(&name) {
 
-            return Err(ScopeError::VariableRedefinition);
+            return Err(ScopeErrorKind::VariableRedefinition);
         }
-        let sym = Symbol::new_variable(name.clone(), ty, mutable, scope.level, initialized);
+        let sym = Symbol::new_variable(name.clone(), ty, mutable, scope.level, initialized, span);
         scope.symbols.insert(name, sym);
         Ok(())
     }
 
  
-    pub fn insert_function_prototype(&mut self, name: String, params: Vec<Type>, return_type: Type) -> Result<(), ScopeError> {
+    pub fn insert_parameter(&mut self, name: String, ty: Type, span: Span) -> Result<(), ScopeErrorKind> {
+        let scope = self.current_scope_mut()?;
+        if scope.symbols.contains_key(&name) {
+            return Err(ScopeErrorKind::VariableRedefinition);
+        }
+        let sym = Symbol::new_parameter(name.clone(), ty, scope.level, span);
+        scope.symbols.insert(name, sym);
+        Ok(())
+    }
+
+ 
+    pub fn insert_function_prototype(&mut self, name: String, params: Vec<Type>, return_type: Type, span: Span) -> Result<(), ScopeErrorKind> {
         let scope = self.current_scope_mut()?;
         if let Some(existing) = scope.symbols.get(&name) {
             match &existing.kind {
                 SymbolKind::Function { defined: false, params: existing_params, return_type: existing_ret, .. } => {
                     
-                    if &existing_params[..] == &params[..] && existing_ret == &return_type {
-                        return Err(ScopeError::FunctionPrototypeRedefinition);
+                    if existing_params[..] == params[..] && existing_ret == &return_type {
+                        return Err(ScopeErrorKind::FunctionPrototypeRedefinition);
                     } else {
-                        return Err(ScopeError::FunctionSignatureConflict);
+                        return Err(ScopeErrorKind::FunctionSignatureConflict);
                     }
                 }
                 SymbolKind::Function { defined: true, .. } => {
-                    return Err(ScopeError::FunctionRedefinitionAsPrototype);
+                    return Err(ScopeErrorKind::FunctionRedefinitionAsPrototype);
                 }
                 _ => {
-                    return Err(ScopeError::VariableRedefinition); // name clash with variable/param
+                    return Err(ScopeErrorKind::VariableRedefinition); // name clash with variable/param
                 }
             }
         }
-        let sym = Symbol::new_function_prototype(name.clone(), params, return_type, scope.level);
+        let sym = Symbol::new_function_prototype(name.clone(), params, return_type, scope.level, span);
         scope.symbols.insert(name, sym);
         Ok(())
     }
 
-    pub fn insert_function_definition(&mut self, name: String, params: Vec<Type>, return_type: Type) -> Result<(), ScopeError> {
+    pub fn insert_function_definition(&mut self, name: String, params: Vec<Type>, return_type: Type, span: Span) -> Result<(), ScopeErrorKind> {
         let scope = self.current_scope_mut()?;
         if let Some(existing) = scope.symbols.get(&name) {
             match &existing.kind {
                 SymbolKind::Function { defined: false, params: existing_params, return_type: existing_ret, .. } => {
                     
-                    if &existing_params[..] != &params[..] || existing_ret != &return_type {
-                        return Err(ScopeError::FunctionSignatureConflict);
+                    if existing_params[..] != params[..] || existing_ret != &return_type {
+                        return Err(ScopeErrorKind::FunctionSignatureConflict);
                     } else {
 
-                        let sym = Symbol::new_function_definition(name.clone(), params, return_type, scope.level);
+                        let sym = Symbol::new_function_definition(name.clone(), params, return_type, scope.level, span);
                         scope.symbols.insert(name, sym);
                         return Ok(());
                     }
                 }
                 SymbolKind::Function { defined: true, .. } => {
-                    return Err(ScopeError::FunctionRedefinition);
+                    return Err(ScopeErrorKind::FunctionRedefinition);
                 }
                 _ => {
-                    return Err(ScopeError::VariableRedefinition); // name clash
+                    return Err(ScopeErrorKind::VariableRedefinition); // name clash
                 }
             }
         }
 
-        let sym = Symbol::new_function_definition(name.clone(), params, return_type, scope.level);
+        let sym = Symbol::new_function_definition(name.clone(), params, return_type, scope.level, span);
+        scope.symbols.insert(name, sym);
+        Ok(())
+    }
+
+
+    pub fn insert_struct(&mut self, name: String, fields: Vec<(String, Type)>, span: Span) -> Result<(), ScopeErrorKind> {
+        let scope = self.current_scope_mut()?;
+        if let Some(existing) = scope.symbols.get(&name) {
+            return match existing.kind {
+                SymbolKind::Struct { .. } => Err(ScopeErrorKind::StructRedefinition),
+                _ => Err(ScopeErrorKind::VariableRedefinition), // name clash
+            };
+        }
+        let sym = Symbol::new_struct(name.clone(), fields, scope.level, span);
         scope.symbols.insert(name, sym);
         Ok(())
     }
 
 
+    // gives a struct registered with insert_struct its fields, once their types are known
+    pub fn set_struct_fields(&mut self, name: &str, fields: Vec<(String, Type)>) {
+        let mut current = self.current.as_deref_mut();
+        while let Some(scope) = current {
+            if let Some(Symbol { kind: SymbolKind::Struct { fields: slot }, .. }) = scope.symbols.get_mut(name) {
+                *slot = fields;
+                return;
+            }
+            current = scope.parent.as_deref_mut();
+        }
+    }
+
     fn find_symbol(&self, name: &str) -> Option<&Symbol> {
         let mut current = self.current.as_deref();
         while let Some(scope) = current {
@@ -247,33 +387,50 @@ impl ScopeStack {
     }
 
 
-    pub fn lookup_variable(&self, name: &str) -> Result<&Symbol, ScopeError> {
+    pub fn lookup_variable(&self, name: &str) -> Result<&Symbol, ScopeErrorKind> {
         if let Some(sym) = self.find_symbol(name) {
             match &sym.kind {
                 SymbolKind::Variable { .. } | SymbolKind::Parameter => {
                     if !sym.initialized {
-                        return Err(ScopeError::VariableUsedBeforeInit);
+                        return Err(ScopeErrorKind::VariableUsedBeforeInit);
                     }
                     return Ok(sym);
                 }
-                SymbolKind::Function { .. } => {
-                    return Err(ScopeError::FoundButWrongKind);
+                SymbolKind::Function { .. } | SymbolKind::Struct { .. } => {
+                    return Err(ScopeErrorKind::FoundButWrongKind);
                 }
             }
         }
-        Err(ScopeError::UndeclaredIdentifier)
+        Err(ScopeErrorKind::UndeclaredIdentifier)
     }
 
 
-    pub fn lookup_function(&self, name: &str) -> Result<&Symbol, ScopeError> {
+    pub fn lookup_function(&self, name: &str) -> Result<&Symbol, ScopeErrorKind> {
         if let Some(sym) = self.find_symbol(name) {
             match &sym.kind {
                 SymbolKind::Function { defined: true, .. } => return Ok(sym),
-                SymbolKind::Function { defined: false, .. } => return Err(ScopeError::UndefinedFunctionCalled),
-                _ => return Err(ScopeError::FoundButWrongKind),
+                SymbolKind::Function { defined: false, .. } => return Err(ScopeErrorKind::UndefinedFunctionCalled),
+                _ => return Err(ScopeErrorKind::FoundButWrongKind),
             }
         }
-        Err(ScopeError::UndefinedFunctionCalled)
+        Err(ScopeErrorKind::UndefinedFunctionCalled)
+    }
+
+
+    pub fn lookup_struct(&self, name: &str) -> Result<&Symbol, ScopeErrorKind> {
+        match self.find_symbol(name) {
+            Some(sym) if matches!(sym.kind, SymbolKind::Struct { .. }) => Ok(sym),
+            Some(_) => Err(ScopeErrorKind::FoundButWrongKind),
+            None => Err(ScopeErrorKind::UndeclaredStruct),
+        }
+    }
+
+    // type of `field` in struct `name`; None if either does not exist
+    pub fn struct_field(&self, name: &str, field: &str) -> Option<&Type> {
+        match &self.lookup_struct(name).ok()?.kind {
+            SymbolKind::Struct { fields } => fields.iter().find(|(f, _)| f == field).map(|(_, ty)| ty),
+            _ => None,
+        }
     }
 
 
@@ -282,7 +439,7 @@ impl ScopeStack {
     }
 
     // this function is specifically intended for variables and parameters, not for functions
-    pub fn mark_initialized(&mut self, name: &str) -> Result<(), ScopeError> {
+    pub fn mark_initialized(&mut self, name: &str) -> Result<(), ScopeErrorKind> {
         // Need to find the symbol mutably in the chain of scopes
         let mut current = self.current.as_deref_mut();
         while let Some(scope) = current {
@@ -292,14 +449,52 @@ impl ScopeStack {
                         sym.initialized = true;
                         return Ok(());
                     }
-                    SymbolKind::Function { .. } => return Err(ScopeError::FoundButWrongKind),
+                    SymbolKind::Function { .. } | SymbolKind::Struct { .. } => return Err(ScopeErrorKind::FoundButWrongKind),
+                }
+            }
+            current = scope.parent.as_deref_mut();
+        }
+        Err(ScopeErrorKind::UndeclaredIdentifier)
+    }
+
+
+    // (scope level, name) of every variable and parameter in scope that is initialized
+    pub fn assigned(&self) -> HashSet<(usize, String)> {
+        let mut assigned = HashSet::new();
+        let mut current = self.current.as_deref();
+        while let Some(scope) = current {
+            for sym in scope.symbols.values() {
+                if sym.initialized && matches!(sym.kind, SymbolKind::Variable { .. } | SymbolKind::Parameter) {
+                    assigned.insert((scope.level, sym.name.clone()));
+                }
+            }
+            current = scope.parent.as_deref();
+        }
+        assigned
+    }
+
+    // initialized becomes exactly "is in `assigned`" for every variable and parameter in scope
+    pub fn set_assigned(&mut self, assigned: &HashSet<(usize, String)>) {
+        let mut current = self.current.as_deref_mut();
+        while let Some(scope) = current {
+            for sym in scope.symbols.values_mut() {
+                if matches!(sym.kind, SymbolKind::Variable { .. } | SymbolKind::Parameter) {
+                    sym.initialized = assigned.contains(&(scope.level, sym.name.clone()));
                 }
             }
             current = scope.parent.as_deref_mut();
         }
-        Err(ScopeError::UndeclaredIdentifier)
     }
 
+    pub fn assume_all_assigned(&mut self) {
+        let mut current = self.current.as_deref_mut();
+        while let Some(scope) = current {
+            for sym in scope.symbols.values_mut() {
+                sym.initialized = true;
+            }
+            current = scope.parent.as_deref_mut();
+        }
+    }
 
     // Check whether a variable or parameter with the given name exists in any scope
     pub fn variable_exists(&self, name: &str) -> bool {
@@ -1,5 +1,45 @@
+use std::collections::HashMap;
+use crate::diagnostic::Diagnostic;
+use crate::semantics::scope::scope::Type;
+use crate::span::Span;
+
+// Type of every expression that checked without errors, by its span. Callee names and
+// assignment targets are not recorded, they are not evaluated as values.
+pub type TypeTable = HashMap<Span, Type>;
+
 #[derive(Debug)]
-pub enum TypeChkError {
+pub struct TypeChkError {
+    pub kind: TypeChkErrorKind,
+    pub span: Span, // expression or statement the error was found in
+    // boxed to keep Result<Type, TypeChkError> small
+    pub types: Option<Box<(Type, Type)>>,     // (expected, found) when the error is a mismatch
+    pub related: Option<Box<(Span, String)>>, // e.g. the function whose parameters didn't match
+    pub note: Option<String>,
+}
+
+impl TypeChkError {
+    pub fn new(kind: TypeChkErrorKind, span: Span) -> Self {
+        Self { kind, span, types: None, related: None, note: None }
+    }
+
+    pub fn with_types(mut self, expected: Type, found: Type) -> Self {
+        self.types = Some(Box::new((expected, found)));
+        self
+    }
+
+    pub fn with_related(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.related = Some(Box::new((span, message.into())));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum TypeChkErrorKind {
     ErroneousVarDecl,
     FnCallParamCount,
     FnCallParamType,
@@ -14,5 +54,88 @@ pub enum TypeChkError {
     AttemptedShiftOnNonInt,
     AttemptedAddOpOnNonNumeric,
     AttemptedExponentiationOfNonNumeric,
+    InvalidConversion,
+    AssignmentTypeMismatch, // value does not have the type the target was declared with
+    AssignmentToFunction,
+    AssignmentToImmutable,  // parameters cannot be reassigned
+    IndexingNonArray,
+    NonIntegerIndex,
+    IndexOutOfBounds, // constant index outside the array's length
+    ArrayElementTypeMismatch,
+    ArrayLengthMismatch,
+    UnknownField,           // struct has no field of that name
+    DuplicateFieldInit,     // same field given twice in a struct literal
+    MissingFieldInit,       // struct literal leaves a field out
+    FieldTypeMismatch,
+    FieldAccessOnNonStruct,
     ReturnStmtNotFound,
 }
+
+impl TypeChkErrorKind {
+    // stable code and headline of each error
+    fn describe(&self) -> (&'static str, &'static str) {
+        match self {
+            TypeChkErrorKind::ErroneousVarDecl => ("E0401", "this name does not refer to a value"),
+            TypeChkErrorKind::FnCallParamCount => ("E0402", "wrong number of arguments"),
+            TypeChkErrorKind::FnCallParamType => ("E0403", "mismatched argument type"),
+            TypeChkErrorKind::ErroneousReturnType => ("E0404", "mismatched return type"),
+            TypeChkErrorKind::ExpressionTypeMismatch => ("E0405", "mismatched types"),
+            TypeChkErrorKind::ExpectedBooleanExpression => ("E0406", "expected a boolean expression"),
+            TypeChkErrorKind::ErroneousBreak => ("E0407", "`break` outside of a loop"),
+            TypeChkErrorKind::NonBooleanCondStmt => ("E0408", "condition is not a boolean"),
+            TypeChkErrorKind::EmptyExpression => ("E0409", "empty expression"),
+            TypeChkErrorKind::AttemptedBoolOpOnNonBools => ("E0410", "logical operator applied to non-boolean operands"),
+            TypeChkErrorKind::AttemptedBitOpOnNonNumeric => ("E0411", "bitwise operator applied to non-integer operands"),
+            TypeChkErrorKind::AttemptedShiftOnNonInt => ("E0412", "shift applied to non-integer operands"),
+            TypeChkErrorKind::AttemptedAddOpOnNonNumeric => ("E0413", "arithmetic on non-numeric operands"),
+            TypeChkErrorKind::AttemptedExponentiationOfNonNumeric => ("E0414", "exponentiation of non-numeric operands"),
+            TypeChkErrorKind::InvalidConversion => ("E0415", "invalid conversion"),
+            TypeChkErrorKind::AssignmentTypeMismatch => ("E0416", "mismatched types in assignment"),
+            TypeChkErrorKind::AssignmentToFunction => ("E0417", "cannot assign to a function"),
+            TypeChkErrorKind::AssignmentToImmutable => ("E0418", "cannot assign to a parameter"),
+            TypeChkErrorKind::IndexingNonArray => ("E0419", "cannot index a value that is not an array"),
+            TypeChkErrorKind::NonIntegerIndex => ("E0420", "array index is not an integer"),
+            TypeChkErrorKind::IndexOutOfBounds => ("E0421", "index out of bounds"),
+            TypeChkErrorKind::ArrayElementTypeMismatch => ("E0422", "mismatched array element type"),
+            TypeChkErrorKind::ArrayLengthMismatch => ("E0423", "mismatched array length"),
+            TypeChkErrorKind::UnknownField => ("E0424", "no such field"),
+            TypeChkErrorKind::DuplicateFieldInit => ("E0425", "field initialized twice"),
+            TypeChkErrorKind::MissingFieldInit => ("E0426", "missing fields in struct literal"),
+            TypeChkErrorKind::FieldTypeMismatch => ("E0427", "mismatched field type"),
+            TypeChkErrorKind::FieldAccessOnNonStruct => ("E0428", "field access on a value that is not a struct"),
+            TypeChkErrorKind::ReturnStmtNotFound => ("E0429", "function may finish without returning a value"),
+        }
+    }
+}
+
+impl From<&TypeChkError> for Diagnostic {
+    fn from(e: &TypeChkError) -> Self {
+        let (code, message) = e.kind.describe();
+        let mut d = Diagnostic::error(code, message, e.span);
+        if let Some((expected, found)) = e.types.as_deref() {
+            d = d.with_label(format!("expected `{}`, found `{}`", expected, found));
+        }
+        match e.kind {
+            TypeChkErrorKind::ReturnStmtNotFound => {
+                d = d.with_label("not every path through this function ends in a `return`");
+            }
+            TypeChkErrorKind::InvalidConversion => {
+                d = d.with_note("a value can be cast to its own type, between `char` and `int`, and from `char` or `int` to `string`");
+            }
+            TypeChkErrorKind::AssignmentToImmutable => {
+                d = d.with_help("copy the parameter into a local variable and assign to that");
+            }
+            TypeChkErrorKind::AttemptedBoolOpOnNonBools => {
+                d = d.with_help("compare the operands to get a `bool`, e.g. `x != 0`");
+            }
+            _ => {}
+        }
+        if let Some((span, message)) = e.related.as_deref() {
+            d = d.with_secondary(*span, message.clone());
+        }
+        if let Some(note) = &e.note {
+            d = d.with_note(note.clone());
+        }
+        d
+    }
+}
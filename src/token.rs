@@ -1,4 +1,5 @@
 use std::fmt;
+use crate::span::Span;
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, PartialEq)]
@@ -9,6 +10,8 @@ pub enum TokenKind {
     T_FLOAT,
     T_BOOL,
     T_STRING,
+    T_CHAR,
+    T_STRUCT,
     T_RETURN,
     T_BREAK,
     T_IF,
@@ -22,6 +25,7 @@ pub enum TokenKind {
     T_FLOATLIT(f64),
     T_STRINGLIT(String),
     T_BOOLLIT(bool),
+    T_CHARLIT(char),
 
     // Punctuation
     T_PARENL,
@@ -59,20 +63,40 @@ pub enum TokenKind {
     T_TILDE,
     T_NOT,
 
+    T_ERROR, // stands in for input the lexer could not tokenize (recovery mode)
     T_EOF,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace, // a run of whitespace other than '\n'
+    Newline,    // a single '\n'
+    LineComment,
+    BlockComment,
+}
+
+// Whitespace or a comment between two tokens. Only collected by the trivia-preserving
+// lexer mode (HandLexer::tokenize_with_trivia); the text is `&src[span.start..span.end]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     // by default struct fields are private
     pub kind: TokenKind,
     pub line: usize, // row number (1-based)
     pub col: usize,  // column number (1-based)
+    pub span: Span,  // byte range of the lexeme in the source
+    pub leading: Vec<Trivia>,  // trivia before the token (empty unless lexed with trivia)
+    pub trailing: Vec<Trivia>, // trivia after the token up to the end of its line
 }
 
 impl Token {
-    pub fn new(kind: TokenKind, line: usize, col: usize) -> Self {
-        Self { kind, line, col }
+    pub fn new(kind: TokenKind, line: usize, col: usize, span: Span) -> Self {
+        Self { kind, line, col, span, leading: Vec::new(), trailing: Vec::new() }
     }
 }
 
@@ -87,6 +111,8 @@ impl fmt::Display for TokenKind {
             T_FLOAT => write!(f, "T_FLOAT"),
             T_BOOL => write!(f, "T_BOOL"),
             T_STRING => write!(f, "T_STRING"),
+            T_CHAR => write!(f, "T_CHAR"),
+            T_STRUCT => write!(f, "T_STRUCT"),
             T_RETURN => write!(f, "T_RETURN"),
             T_IF => write!(f, "T_IF"),
             T_ELSE => write!(f, "T_ELSE"),
@@ -99,6 +125,7 @@ impl fmt::Display for TokenKind {
             T_FLOATLIT(v) => write!(f, "T_FLOATLIT({})", v),
             T_STRINGLIT(s) => write!(f, "T_STRINGLIT(\"{}\")", s),
             T_BOOLLIT(b) => write!(f, "T_BOOLLIT({})", b),
+            T_CHARLIT(c) => write!(f, "T_CHARLIT({:?})", c),
 
             T_PARENL => write!(f, "T_PARENL"),
             T_PARENR => write!(f, "T_PARENR"),
@@ -134,6 +161,7 @@ impl fmt::Display for TokenKind {
             T_TILDE => write!(f, "T_TILDE"),
             T_NOT => write!(f, "T_NOT"),
 
+            T_ERROR => write!(f, "T_ERROR"),
             T_EOF => write!(f, "T_EOF"),
         }
     }
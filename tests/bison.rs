@@ -0,0 +1,54 @@
+// Builds the Bison parser in grammar/ against libcompiler.a, the way the README does by
+// hand, and runs it on a program in the subset of the language it parses. Skipped when
+// there is no C compiler, or the static library is not next to the test binary.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn bison_parser_runs_on_the_rust_lexer() {
+    // the test binary is in target/<profile>/deps, the library in target/<profile>
+    let exe = env::current_exe().expect("test binary has a path");
+    let library = exe.parent().and_then(|deps| deps.parent()).map(|dir| dir.join("libcompiler.a"));
+    let Some(library) = library.filter(|l| l.exists()) else {
+        eprintln!("skipped: libcompiler.a not found");
+        return;
+    };
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let grammar = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("grammar");
+    let dir = env::temp_dir().join(format!("bison-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let parser = dir.join("parser");
+    let built = Command::new(&cc)
+        .arg("-o")
+        .arg(&parser)
+        .arg(grammar.join("parser.tab.c"))
+        .arg(grammar.join("lexer_shim.c"))
+        .arg(&library)
+        .args(["-lpthread", "-ldl", "-lm"])
+        .status();
+    match built {
+        Err(_) => {
+            eprintln!("skipped: no C compiler ({})", cc);
+            return;
+        }
+        Ok(status) => assert!(status.success(), "the parser did not build"),
+    }
+
+    let src = dir.join("input.src");
+    fs::write(&src, "int x = 0x10 + 1_000;\nfn f(int a): int {\n  return a * 2;\n}\n").unwrap();
+    let output = Command::new(&parser).arg(&src).output().expect("the parser runs");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    fs::remove_dir_all(&dir).ok();
+    for line in [
+        "Primary: INTLIT (16)",
+        "Primary: INTLIT (1000)",
+        "GlobalVarDecl: name=x (with initializer)",
+        "Param: name=a",
+        "FunctionDecl: name=f",
+        "Parsing finished successfully.",
+    ] {
+        assert!(stdout.contains(line), "missing `{}` in:\n{}", line, stdout);
+    }
+}
@@ -0,0 +1,115 @@
+// Snapshots of what `check --emit=ir` and `check --emit=ssa` print for the programs in
+// tests/ir/, of the IR after -O1 and -O2 (.O1.ir, .O2.ir) and after each pass on its own,
+// as `--emit=ssa --passes=NAME` prints it (.passes.ssa), and a check that the interpreter
+// and the VM agree on those programs. After a deliberate change to lowering, SSA
+// construction or a pass, run the tests with UPDATE_SNAPSHOTS=1 to rewrite the
+// snapshots, and review their diff.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use compiler::diagnostic::Diagnostic;
+use compiler::ir;
+use compiler::ir::opt::{Pass, Pipeline};
+use compiler::parser::ast::Program;
+use compiler::semantics::typechecker::TypeTable;
+
+// loops with and without a condition, `&&`/`||` in values and conditions, stores through
+// indexes and fields, names shadowed in blocks, loops and parameters, a loop that swaps
+// two variables next to a branch that folds, and a phi of 0.0 and -0.0, which must not
+// be taken for one constant
+const PROGRAMS: &[&str] = &["loops", "short_circuit", "stores", "shadowing", "folding", "signed_zero"];
+
+fn path(name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/ir").join(format!("{}.{}", name, extension))
+}
+
+fn checked(name: &str) -> (Program, TypeTable) {
+    let src = fs::read_to_string(path(name, "src")).expect("test program is readable");
+    let (program, diagnostics) = compiler::parse(&src);
+    assert!(diagnostics.is_empty(), "{} does not parse", name);
+    let (types, diagnostics) = compiler::check(&program);
+    assert!(!diagnostics.iter().any(Diagnostic::is_error), "{} does not check", name);
+    (program, types)
+}
+
+fn emit(name: &str, ssa: bool) -> String {
+    let (program, types) = checked(name);
+    let mut module = compiler::lower(&program, &types);
+    if ssa {
+        for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+            ir::ssa::construct(function);
+        }
+    }
+    ir::dump::dump_module(&module)
+}
+
+fn optimised(name: &str, level: u8) -> String {
+    let (program, types) = checked(name);
+    let mut module = compiler::lower(&program, &types);
+    compiler::optimize(&mut module, level);
+    ir::dump::dump_module(&module)
+}
+
+// each pass once over the SSA form of the program, as if it were the only one
+fn after_each_pass(name: &str) -> String {
+    let (program, types) = checked(name);
+    let mut out = String::new();
+    for pass in Pass::ALL {
+        let mut module = compiler::lower(&program, &types);
+        for function in module.functions.iter_mut().chain(std::iter::once(&mut module.script)) {
+            ir::ssa::construct(function);
+        }
+        Pipeline { passes: vec![pass], max_rounds: 1 }.run(&mut module, |_, _, _| {});
+        out.push_str(&format!("=== {} ===\n{}", pass.name(), ir::dump::dump_module(&module)));
+    }
+    out
+}
+
+fn assert_snapshot(name: &str, extension: &str, actual: &str) {
+    let path = path(name, extension);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).expect("snapshot is writable");
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert!(actual == expected, "{} is out of date; the output is now:\n{}", path.display(), actual);
+}
+
+#[test]
+fn ir_snapshots() {
+    for name in PROGRAMS {
+        assert_snapshot(name, "ir", &emit(name, false));
+    }
+}
+
+#[test]
+fn ssa_snapshots() {
+    for name in PROGRAMS {
+        assert_snapshot(name, "ssa", &emit(name, true));
+    }
+}
+
+#[test]
+fn optimised_snapshots() {
+    for name in PROGRAMS {
+        assert_snapshot(name, "O1.ir", &optimised(name, 1));
+        assert_snapshot(name, "O2.ir", &optimised(name, 2));
+    }
+}
+
+#[test]
+fn pass_snapshots() {
+    for name in PROGRAMS {
+        assert_snapshot(name, "passes.ssa", &after_each_pass(name));
+    }
+}
+
+#[test]
+fn interpreter_and_vm_agree() {
+    for name in PROGRAMS {
+        let (program, _) = checked(name);
+        let interpreted = compiler::run(&program).map_err(|e| e.to_string());
+        let executed = compiler::execute(&compiler::compile(&program)).map_err(|e| e.to_string());
+        assert_eq!(interpreted, executed, "{}", name);
+    }
+}
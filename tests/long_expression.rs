@@ -0,0 +1,21 @@
+// A 10,000-term `1+1+…+1` nests 10,000 deep. Every stage must take it on whatever
+// thread the caller is on, here a test thread with its small default stack.
+use compiler::diagnostic::Diagnostic;
+use compiler::interpreter::value::Value;
+
+const TERMS: usize = 10_000;
+
+#[test]
+fn long_expression_goes_through_every_stage() {
+    let src = format!("int x = {};\nreturn x;\n", vec!["1"; TERMS].join("+"));
+    let (program, diagnostics) = compiler::parse(&src);
+    assert!(diagnostics.is_empty());
+    let (types, diagnostics) = compiler::check(&program);
+    assert!(!diagnostics.iter().any(Diagnostic::is_error));
+    let mut module = compiler::lower(&program, &types);
+    compiler::optimize(&mut module, 2);
+    assert_eq!(compiler::run(&program).ok(), Some(Value::Int(TERMS as i64)));
+    assert_eq!(compiler::execute(&compiler::compile(&program)).ok(), Some(Value::Int(TERMS as i64)));
+    // dropping the tree recurses as deep as building it did
+    compiler::on_deep_stack(move || drop(program));
+}